@@ -1,30 +1,169 @@
-use crate::voice::voicevox::client::Client as VoicevoxClient;
+use crate::commands::dictionary_accent::{self, AccentEditors};
+use crate::commands::dictionary_convert::{self, Converted, EntryError, GuessedAccent};
+use crate::commands::dictionary_history;
+use crate::commands::reading;
+use crate::voice::manager::VoiceManager;
+use crate::voice::playback;
+use crate::voice::voicevox::accent::{self, AccentPhrase};
+use crate::voice::voicevox::client::{Client as VoicevoxClient, WordType, DEFAULT_PRIORITY};
+use crate::voice::voicevox::dictionary::{self, DictionaryIndex, DictionaryWord, ImportDiff, ImportMode, Scope};
+use crate::voice::voicevox::history;
+use crate::voice::voicevox::kana::{self, KanaError};
+use crate::voice::voicevox::resilience::EnginesPending;
+use crate::confirmation::Confirmations;
 use crate::embed;
+use crate::permissions::{self, Permission};
 use anyhow::Result;
-use std::io::Write;
 use serenity::{
-    builder::{CreateCommand, CreateCommandOption},
-    model::application::{CommandInteraction, CommandOptionType, CommandDataOptionValue},
+    all::{GuildId, UserId},
+    builder::{CreateActionRow, CreateAttachment, CreateAutocompleteResponse, CreateButton, CreateCommand, CreateCommandOption, CreateEmbed, CreateEmbedFooter, CreateInteractionResponse, CreateInteractionResponseMessage, EditInteractionResponse},
+    model::application::{ButtonStyle, CommandDataOption, CommandInteraction, CommandOptionType, CommandDataOptionValue, ComponentInteraction},
     prelude::*,
-    builder::CreateInteractionResponse,
     builder::CreateInteractionResponseFollowup,
 };
-use serde_json::Value;
-use tracing::debug;
+use sqlx::SqlitePool;
+use tracing::{debug, error, info, warn};
 
-pub async fn run(ctx: &Context, interaction: &CommandInteraction, voicevox_client: &VoicevoxClient) -> Result<()> {
-    interaction.defer(&ctx.http).await?;
+pub const CONFIRM_COMPONENT_PREFIX: &str = "dictionary_confirm";
+const MAX_IMPORT_FILE_SIZE: u32 = 1024 * 1024;
+/// プレビューで種類ごとに表示する単語の数
+const MAX_PREVIEW_ENTRIES: usize = 10;
+pub const LIST_COMPONENT_PREFIX: &str = "dictionary_list";
+const LIST_PAGE_SIZE: usize = 10;
+/// あいまい検索で一致とみなす類似度 (Jaro-Winkler)
+const FUZZY_MATCH_THRESHOLD: f64 = 0.8;
+/// Discordが受け付ける補完候補の最大数
+const MAX_AUTOCOMPLETE_CHOICES: usize = 25;
 
-    let response_embed = process_dictionary_command(ctx, interaction, voicevox_client).await;
+/// 確認ボタンが押されるまで保留している操作
+pub enum PendingAction {
+    Reset(Scope),
+    /// 保存されている全体の辞書をエンジンに反映する
+    RestoreEngine,
+    RestoreSnapshot { scope: Scope, snapshot_id: i64 },
+    /// 差分は適用する時点の辞書から計算し直す
+    Import { scope: Scope, words: Vec<DictionaryWord>, mode: ImportMode },
+}
+
+/// ボタンで操作する辞書の画面の状態
+pub struct DictionaryPrompts {
+    pub confirmations: Confirmations<PendingAction>,
+    pub accent_editors: AccentEditors,
+}
+
+impl DictionaryPrompts {
+    pub fn new() -> Self {
+        Self { confirmations: Confirmations::new(CONFIRM_COMPONENT_PREFIX), accent_editors: AccentEditors::default() }
+    }
+}
 
-    let builder = CreateInteractionResponseFollowup::new().embed(response_embed);
+pub async fn run(ctx: &Context, interaction: &CommandInteraction, pool: &SqlitePool, voicevox_client: &VoicevoxClient, voice_manager: &VoiceManager, index: &DictionaryIndex, prompts: &DictionaryPrompts) -> Result<()> {
+    let subcommand_name = interaction.data.options().first().map(|opt| opt.name);
 
-    let _ = interaction.create_followup(&ctx.http, builder).await?;
+    if let Some(permission) = required_permission(interaction)
+        && !permissions::require(ctx, pool, interaction, permission).await?
+    {
+        return Ok(());
+    }
+
+    // 確認や編集の画面は本人にだけ表示する
+    if matches!(subcommand_name, Some("reset" | "restore" | "import" | "accent")) {
+        interaction.defer_ephemeral(&ctx.http).await?;
+    } else {
+        interaction.defer(&ctx.http).await?;
+    }
+
+    // 添付ファイルやボタンを使うサブコマンドは埋め込みだけの応答と別に扱う
+    let result = match subcommand_name {
+        Some("list") => list_data(ctx, interaction, pool).await,
+        Some("export") => export_data(ctx, interaction, pool).await,
+        Some("import") => import_data(ctx, interaction, pool, voicevox_client, &prompts.confirmations).await,
+        Some("reset") => confirm_reset(ctx, interaction, pool, &prompts.confirmations).await,
+        Some("restore") => confirm_restore(ctx, interaction, pool, &prompts.confirmations).await,
+        Some("history") => dictionary_history::history_data(ctx, interaction, pool).await,
+        Some("accent") => dictionary_accent::editor_data(ctx, interaction, pool, voicevox_client, &prompts.accent_editors).await,
+        Some("test") => test_word(ctx, interaction, pool, voicevox_client, voice_manager).await,
+        _ => {
+            let response_embed = process_dictionary_command(ctx, interaction, pool, voicevox_client).await;
+
+            let builder = CreateInteractionResponseFollowup::new().embed(response_embed);
+
+            interaction.create_followup(&ctx.http, builder).await.map(|_| ()).map_err(Into::into)
+        }
+    };
+
+    // 変更後の単語を補完候補に出せるように索引を読み込み直す
+    if matches!(subcommand_name, Some("add" | "edit" | "remove" | "undo"))
+        && let Err(e) = index.refresh(pool).await
+    {
+        warn!("Failed to refresh dictionary index: {}", e);
+    }
+
+    result
+}
+
+/// 確認ボタンが押されたら保留していた操作を実行する
+pub async fn handle_confirm_component(ctx: &Context, component: &ComponentInteraction, pool: &SqlitePool, voicevox_client: &VoicevoxClient, index: &DictionaryIndex, confirmations: &Confirmations<PendingAction>) -> Result<()> {
+    let Some(action) = confirmations.resolve(ctx, component).await? else {
+        return Ok(());
+    };
+
+    let actor = component.user.id;
+    let result_embed = match action {
+        PendingAction::Reset(scope) => reset_data(ctx, pool, voicevox_client, scope, actor).await,
+        PendingAction::RestoreEngine => restore_data(ctx, pool, voicevox_client).await,
+        PendingAction::RestoreSnapshot { scope, snapshot_id } => dictionary_history::restore_snapshot(ctx, pool, voicevox_client, scope, actor, snapshot_id).await,
+        PendingAction::Import { scope, words, mode } => apply_import_data(ctx, pool, voicevox_client, scope, actor, words, mode).await,
+    };
+
+    if let Err(e) = index.refresh(pool).await {
+        warn!("Failed to refresh dictionary index: {}", e);
+    }
+
+    component.edit_response(&ctx.http, EditInteractionResponse::new().embed(result_embed).components(vec![])).await?;
+    Ok(())
+}
+
+/// サブコマンドの実行に必要な権限。一覧や履歴など読み出すだけのものは誰でも実行できる
+fn required_permission(interaction: &CommandInteraction) -> Option<Permission> {
+    match interaction.data.options.first().map(|opt| opt.name.as_str()) {
+        Some("add" | "edit" | "remove" | "snapshot" | "accent") => Some(Permission::DictionaryEdit),
+        Some("import") if subcommand_args(interaction).iter().any(|opt| opt.name == "mode" && opt.value.as_str() == Some("replace")) => Some(Permission::DictionaryManage),
+        Some("import") => Some(Permission::DictionaryEdit),
+        Some("reset" | "restore" | "undo") => Some(Permission::DictionaryManage),
+        _ => None,
+    }
+}
+
+/// `edit`と`remove`の`surface`、`restore`の`snapshot`の補完候補を返す
+pub async fn autocomplete(ctx: &Context, interaction: &CommandInteraction, pool: &SqlitePool, index: &DictionaryIndex) -> Result<()> {
+    let Some(focused) = interaction.data.autocomplete() else {
+        return Ok(());
+    };
+
+    let scope = match (scope_choice(interaction), interaction.guild_id) {
+        ("global", _) => Scope::Global,
+        (_, Some(guild_id)) => Scope::Guild(guild_id),
+        (_, None) => Scope::Global,
+    };
 
+    let response = match focused.name {
+        "surface" => index.suggest(scope, focused.value, MAX_AUTOCOMPLETE_CHOICES).into_iter().fold(CreateAutocompleteResponse::new(), |response, (surface, pronunciation)| {
+            let name = format!("{} ({})", surface, pronunciation);
+            // 選択肢の表示名は100文字まで
+            let name = if name.chars().count() > 100 { surface.clone() } else { name };
+            response.add_string_choice(name, surface)
+        }),
+        "snapshot" => dictionary_history::snapshot_choices(pool, scope).await?.into_iter().fold(CreateAutocompleteResponse::new(), |response, (name, snapshot_id)| {
+            response.add_int_choice(name, snapshot_id)
+        }),
+        _ => CreateAutocompleteResponse::new(),
+    };
+    interaction.create_response(&ctx.http, CreateInteractionResponse::Autocomplete(response)).await?;
     Ok(())
 }
 
-async fn process_dictionary_command(ctx: &Context, interaction: &CommandInteraction, voicevox_client: &VoicevoxClient) -> serenity::all::CreateEmbed {
+async fn process_dictionary_command(ctx: &Context, interaction: &CommandInteraction, pool: &SqlitePool, voicevox_client: &VoicevoxClient) -> serenity::all::CreateEmbed {
     let subcommand_name = match interaction.data.options().first() {
         Some(cmd) => cmd.name,
         None => {
@@ -32,22 +171,63 @@ async fn process_dictionary_command(ctx: &Context, interaction: &CommandInteract
         }
     };
 
-    match subcommand_name.as_ref() {
-        "add" => add_word(ctx, interaction, voicevox_client).await,
-        "edit" => edit_word(ctx, interaction, voicevox_client).await,
-        "list" => list_data(ctx, voicevox_client).await,
-        "remove" => remove_word(ctx, interaction, voicevox_client).await,
-        "reset" => reset_data(ctx, voicevox_client).await,
-        "restore" => restore_data(ctx, voicevox_client).await,
+    let scope = match subcommand_name {
+        "add" | "edit" | "remove" => match resolve_scope(ctx, interaction).await {
+            Ok(scope) => scope,
+            Err(embed) => return embed,
+        },
+        _ => Scope::Global,
+    };
+
+    match subcommand_name {
+        "add" => add_word(ctx, interaction, pool, voicevox_client, scope).await,
+        "edit" => edit_word(ctx, interaction, pool, voicevox_client, scope).await,
+        "remove" => remove_word(ctx, interaction, pool, voicevox_client, scope).await,
+        "undo" => dictionary_history::undo_data(ctx, interaction, pool, voicevox_client).await,
+        "snapshot" => dictionary_history::snapshot_data(ctx, interaction, pool).await,
         _ => embed::simple_embed(ctx, "エラー", &format!("「{}」は不明なコマンドです。", subcommand_name), 0xff0000).await,
     }
 }
 
-async fn add_word(ctx: &Context, interaction: &CommandInteraction, voicevox_client: &VoicevoxClient) -> serenity::all::CreateEmbed {
+/// `scope`オプションから辞書の適用範囲を決める。全体の辞書はBOTのオーナーのみ編集できる
+pub(super) async fn resolve_scope(ctx: &Context, interaction: &CommandInteraction) -> std::result::Result<Scope, serenity::all::CreateEmbed> {
+    if scope_choice(interaction) == "global" {
+        return match permissions::is_bot_owner(ctx, interaction.user.id).await {
+            Ok(true) => Ok(Scope::Global),
+            Ok(false) => Err(embed::simple_embed(ctx, "エラー", "全体の辞書はBOTのオーナーのみ編集できます", 0xff0000).await),
+            Err(e) => {
+                warn!("Failed to check bot owner: {}", e);
+                Err(embed::simple_embed(ctx, "エラー", &format!("権限の確認に失敗しました: {}", e), 0xff0000).await)
+            }
+        };
+    }
+
+    match interaction.guild_id {
+        Some(guild_id) => Ok(Scope::Guild(guild_id)),
+        None => Err(embed::simple_embed(ctx, "エラー", "サーバーの辞書はギルド内でのみ編集できます", 0xff0000).await),
+    }
+}
+
+pub(super) fn subcommand_args(interaction: &CommandInteraction) -> &[CommandDataOption] {
+    match interaction.data.options.first().map(|opt| &opt.value) {
+        Some(CommandDataOptionValue::SubCommand(args)) => args,
+        _ => &[],
+    }
+}
+
+fn scope_choice(interaction: &CommandInteraction) -> &str {
+    subcommand_args(interaction)
+        .iter()
+        .find(|arg| arg.name == "scope")
+        .and_then(|opt| opt.value.as_str())
+        .unwrap_or("server")
+}
+
+async fn add_word(ctx: &Context, interaction: &CommandInteraction, pool: &SqlitePool, voicevox_client: &VoicevoxClient, scope: Scope) -> serenity::all::CreateEmbed {
     debug!("Adding word to dictionary: {:?}", interaction.data.options);
 
     let subcommand_args = if let Some(CommandDataOptionValue::SubCommand(args)) =
-        interaction.data.options.get(0).map(|opt| &opt.value)
+        interaction.data.options.first().map(|opt| &opt.value)
     {
         args
     } else {
@@ -102,30 +282,42 @@ async fn add_word(ctx: &Context, interaction: &CommandInteraction, voicevox_clie
                 return embed::simple_embed(ctx, "エラー", "'accent_type' オプションの値が見つかりません", 0xff0000).await;
             }
         };
-        let accent_type_str = accent_type.to_string();
 
-        (surface, pronunciation, accent_type_str)
+        (surface, pronunciation, *accent_type)
     };
 
-    if voicevox_client.find_uuid_by_surface(surface.as_ref()).await.is_ok() {
-        return embed::simple_embed(ctx, "エラー", "既に辞書に同じ単語が存在します", 0xff0000).await;
-    }
+    let (pronunciation, accent_type) = match validate_reading(pronunciation, accent_type) {
+        Ok(validated) => validated,
+        Err(e) => return e.embed(ctx).await,
+    };
 
-    match voicevox_client.add_dict_word(surface, pronunciation, accent_type.parse::<u8>().unwrap(), None).await {
-        Ok(()) => {
-            auto_save_data(voicevox_client).await.unwrap();
-            let description = format!("**単語:** {}\n**読み方:** {}\n**アクセント:** {}", surface, pronunciation, accent_type);
-            embed::simple_embed(ctx, "辞書に追加しました", &description, 0x00ff00).await
-        },
-        Err(e) => embed::simple_embed(ctx, "エラー", &format!("辞書の追加に失敗しました: {}", e), 0xff0000).await
+    let word = DictionaryWord {
+        surface: surface.clone(),
+        pronunciation: pronunciation.clone(),
+        accent_type: accent_type as i64,
+        word_type: word_type_arg(subcommand_args).unwrap_or_default(),
+        priority: priority_arg(subcommand_args).unwrap_or(DEFAULT_PRIORITY as i64),
+    };
+
+    match dictionary::insert(pool, scope, interaction.user.id, &word).await {
+        Ok(true) => {}
+        Ok(false) => return embed::simple_embed(ctx, "エラー", "既に辞書に同じ単語が存在します", 0xff0000).await,
+        Err(e) => return embed::simple_embed(ctx, "エラー", &format!("辞書の追加に失敗しました: {}", e), 0xff0000).await,
     }
+
+    let engine_result = match scope {
+        Scope::Global => voicevox_client.add_dict_word(surface, &pronunciation, accent_type, Some(word.word_type), word.priority as u8).await,
+        Scope::Guild(_) => Ok(()),
+    };
+    let description = describe_word(&word, scope);
+    engine_result_embed(ctx, "辞書に追加しました", description, engine_result).await
 }
 
-async fn edit_word(ctx: &Context, interaction: &CommandInteraction, voicevox_client: &VoicevoxClient) -> serenity::all::CreateEmbed {
+async fn edit_word(ctx: &Context, interaction: &CommandInteraction, pool: &SqlitePool, voicevox_client: &VoicevoxClient, scope: Scope) -> serenity::all::CreateEmbed {
     debug!("Editing word to dictionary: {:?}", interaction.data.options);
 
     let subcommand_args = if let Some(CommandDataOptionValue::SubCommand(args)) =
-        interaction.data.options.get(0).map(|opt| &opt.value)
+        interaction.data.options.first().map(|opt| &opt.value)
     {
         args
     } else {
@@ -180,102 +372,310 @@ async fn edit_word(ctx: &Context, interaction: &CommandInteraction, voicevox_cli
                 return embed::simple_embed(ctx, "エラー", "'accent_type' オプションの値が見つかりません", 0xff0000).await;
             }
         };
-        let accent_type_str = accent_type.to_string();
 
-        (surface, pronunciation, accent_type_str)
+        (surface, pronunciation, *accent_type)
+    };
+
+    let (pronunciation, accent_type) = match validate_reading(pronunciation, accent_type) {
+        Ok(validated) => validated,
+        Err(e) => return e.embed(ctx).await,
+    };
+
+    // 省略された単語の種類と優先度は登録済みの値を引き継ぐ
+    let existing = match dictionary::fetch(pool, scope, surface).await {
+        Ok(Some(existing)) => existing,
+        Ok(None) => return embed::simple_embed(ctx, "エラー", &format!("単語「{}」は辞書に登録されていません", surface), 0xff0000).await,
+        Err(e) => return embed::simple_embed(ctx, "エラー", &format!("辞書内の単語の編集に失敗しました: {}", e), 0xff0000).await,
     };
 
-    if !voicevox_client.find_uuid_by_surface(surface.as_ref()).await.is_ok() {
-        return embed::simple_embed(ctx, "エラー", &format!("単語「{}」は辞書に登録されていません", surface), 0xff0000).await;
+    let word = DictionaryWord {
+        surface: surface.clone(),
+        pronunciation: pronunciation.clone(),
+        accent_type: accent_type as i64,
+        word_type: word_type_arg(subcommand_args).unwrap_or(existing.word_type),
+        priority: priority_arg(subcommand_args).unwrap_or(existing.priority),
+    };
+
+    match dictionary::update(pool, scope, interaction.user.id, &word).await {
+        Ok(true) => {}
+        Ok(false) => return embed::simple_embed(ctx, "エラー", &format!("単語「{}」は辞書に登録されていません", surface), 0xff0000).await,
+        Err(e) => return embed::simple_embed(ctx, "エラー", &format!("辞書内の単語の編集に失敗しました: {}", e), 0xff0000).await,
     }
 
-    match voicevox_client.rewrite_dict_word(surface, pronunciation, accent_type.parse::<u8>().unwrap(), None).await {
-        Ok(()) => {
-            auto_save_data(voicevox_client).await.unwrap();
-            let description = format!("**単語:** {}\n**読み方:** {}\n**アクセント:** {}", surface, pronunciation, accent_type);
-            embed::simple_embed(ctx, "単語を編集しました", &description, 0x00ff00).await
-        },
-        Err(e) => embed::simple_embed(ctx, "エラー", &format!("辞書内の単語の編集に失敗しました: {}", e), 0xff0000).await
+    let engine_result = match scope {
+        Scope::Global => voicevox_client.rewrite_dict_word(surface, &pronunciation, accent_type, Some(word.word_type), word.priority as u8).await,
+        Scope::Guild(_) => Ok(()),
+    };
+    let description = describe_word(&word, scope);
+    engine_result_embed(ctx, "単語を編集しました", description, engine_result).await
+}
+
+/// 単語を辞書の登録前と登録後の読みで続けて再生し、アクセントを比べられるようにする
+async fn test_word(ctx: &Context, interaction: &CommandInteraction, pool: &SqlitePool, voicevox_client: &VoicevoxClient, voice_manager: &VoiceManager) -> Result<()> {
+    debug!("Testing dictionary word: {:?}", interaction.data.options);
+
+    let Some(guild_id) = interaction.guild_id else {
+        let embed = embed::simple_embed(ctx, "エラー", "このコマンドはギルド内でのみ使えます", 0xff0000).await;
+        return send_embed(ctx, interaction, embed).await;
+    };
+    let connected = match songbird::get(ctx).await {
+        Some(manager) => manager.get(guild_id).is_some(),
+        None => false,
+    };
+    if !connected {
+        let embed = embed::simple_embed(ctx, "エラー", "VCに接続していません。`/join` で参加してから実行してください", 0xff0000).await;
+        return send_embed(ctx, interaction, embed).await;
     }
+
+    let args = subcommand_args(interaction);
+    let Some(surface) = args.iter().find(|opt| opt.name == "surface").and_then(|opt| opt.value.as_str()) else {
+        let embed = embed::simple_embed(ctx, "エラー", "'surface' オプションが見つかりません。", 0xff0000).await;
+        return send_embed(ctx, interaction, embed).await;
+    };
+
+    // 読みを省略した場合は、サーバー、全体の順に登録されている単語を使う
+    let registered = match dictionary::fetch(pool, Scope::Guild(guild_id), surface).await {
+        Ok(Some(word)) => Some((word, Scope::Guild(guild_id))),
+        Ok(None) => dictionary::fetch(pool, Scope::Global, surface).await.ok().flatten().map(|word| (word, Scope::Global)),
+        Err(e) => {
+            warn!("Failed to fetch dictionary word: {}", e);
+            None
+        }
+    };
+    let pronunciation = args.iter().find(|opt| opt.name == "pronunciation").and_then(|opt| opt.value.as_str());
+    let accent_type = args.iter().find(|opt| opt.name == "accent_type").and_then(|opt| opt.value.as_i64());
+    let (pronunciation, accent_type) = match (pronunciation, &registered) {
+        (Some(pronunciation), _) => (pronunciation.to_string(), accent_type.unwrap_or(0)),
+        (None, Some((word, _))) => (word.pronunciation.clone(), accent_type.unwrap_or(word.accent_type)),
+        (None, None) => {
+            let embed = embed::simple_embed(ctx, "エラー", &format!("「{}」は辞書に登録されていません。'pronunciation' で読みを指定してください", surface), 0xff0000).await;
+            return send_embed(ctx, interaction, embed).await;
+        }
+    };
+    let (pronunciation, accent_type) = match validate_reading(&pronunciation, accent_type) {
+        Ok(reading) => reading,
+        Err(e) => return send_embed(ctx, interaction, e.embed(ctx).await).await,
+    };
+
+    let result = async {
+        let before = voicevox_client.create_audio_query(surface, playback::SPEAKER_ID, playback::SPEED_SCALE).await?;
+        let after_phrases = voicevox_client.create_accent_phrases_from_kana(&accent::to_kana(&pronunciation, accent_type as usize), playback::SPEAKER_ID).await?;
+        let after = accent::replace_accent_phrases(&before, after_phrases.clone())?;
+        let (_, before_phrases) = accent::parse_audio_query(&before)?;
+        let after_phrases: Vec<AccentPhrase> = serde_json::from_value(after_phrases)?;
+
+        playback::play_audio_query(ctx, voicevox_client, voice_manager, guild_id, &before, playback::SPEAKER_ID).await?;
+        playback::play_audio_query(ctx, voicevox_client, voice_manager, guild_id, &after, playback::SPEAKER_ID).await?;
+        anyhow::Ok((before_phrases, after_phrases))
+    }
+    .await;
+
+    let embed = match result {
+        Ok((before_phrases, after_phrases)) => {
+            let mut description = format!(
+                "**単語:** {}\n\n**登録前の読み**\n{}\n\n**登録後の読み**\n{}\n\n登録前、登録後の順にVCで再生します",
+                surface, reading::describe_accent_phrases(&before_phrases), reading::describe_accent_phrases(&after_phrases),
+            );
+            if registered.is_some_and(|(_, scope)| scope == Scope::Global) {
+                description.push_str("\n全体の辞書の単語はエンジンに登録済みのため、登録前の読みにも反映されています");
+            }
+            embed::simple_embed(ctx, "読みの聞き比べ", &description, 0x0099ff).await
+        }
+        Err(e) => {
+            error!("Failed to test dictionary word: {}", e);
+            embed::simple_embed(ctx, "エラー", &format!("読みの再生に失敗しました: {}", e), 0xff0000).await
+        }
+    };
+    send_embed(ctx, interaction, embed).await
 }
 
-async fn list_data(ctx: &Context, voicevox_client: &VoicevoxClient) -> serenity::all::CreateEmbed {
-    debug!("Listing dictionary data");
+/// 読みを全角カタカナに揃え、アクセントの位置が読みに収まっているか確認する
+pub(super) fn validate_reading(pronunciation: &str, accent_type: i64) -> std::result::Result<(String, u8), ReadingError> {
+    let pronunciation = kana::normalize_pronunciation(pronunciation).map_err(|e| ReadingError::Pronunciation(pronunciation.to_string(), e))?;
+    let accent_type = kana::validate_accent(&pronunciation, accent_type).map_err(|e| ReadingError::Accent(pronunciation.clone(), e))?;
+    Ok((pronunciation, accent_type))
+}
 
-    match voicevox_client.get_user_dict().await {
-        Ok(data) => {
-            match serde_json::from_str::<Value>(&data) {
-                Ok(json_data) => {
-                    let mut formatted_entries = Vec::new();
-
-                    if let Value::Object(dict) = json_data {
-                        let total_entries = dict.len();
-
-                        for (_uuid, entry) in dict.iter() {
-                            if let Value::Object(word_data) = entry {
-                                let surface = word_data.get("surface")
-                                    .and_then(|v| v.as_str())
-                                    .unwrap_or("不明");
-                                let pronunciation = word_data.get("pronunciation")
-                                    .and_then(|v| v.as_str())
-                                    .unwrap_or("不明");
-                                let accent_type = word_data.get("accent_type")
-                                    .and_then(|v| v.as_u64())
-                                    .unwrap_or(0);
-
-                                formatted_entries.push(format!("**{}** → {} (アクセント: {})",
-                                                               surface, pronunciation, accent_type));
-                            }
-                        }
-
-                        let max_entries = 20;
-                        let display_entries = if formatted_entries.len() > max_entries {
-                            let mut truncated = formatted_entries.into_iter().take(max_entries).collect::<Vec<_>>();
-                            truncated.push(format!("... 他{}件", total_entries - max_entries));
-                            truncated
-                        } else {
-                            formatted_entries
-                        };
-
-                        if display_entries.is_empty() {
-                            embed::simple_embed(ctx, "辞書データ一覧", "辞書に登録されている単語はありません", 0x0099ff).await
-                        } else {
-                            let description = format!("**登録単語数:** {}件\n\n{}",
-                                                      total_entries,
-                                                      display_entries.join("\n")
-                            );
-
-                            let final_description = if description.len() > 2000 {
-                                format!("**登録単語数:** {}件\n\n登録単語が多すぎるため、詳細な一覧を表示できません。\n`/dictionary remove` で不要な単語を削除してください。", total_entries)
-                            } else {
-                                description
-                            };
-
-                            embed::simple_embed(ctx, "辞書データ一覧", &final_description, 0x0099ff).await
-                        }
-                    } else {
-                        embed::simple_embed(ctx, "辞書データ一覧", "辞書に登録されている単語はありません", 0x0099ff).await
-                    }
-                }
-                Err(_) => {
-                    let truncated_data = if data.len() > 1900 {
-                        format!("{}...\n\n*データが長すぎるため一部省略されました*", &data[..1900])
-                    } else {
-                        data
-                    };
-                    embed::simple_embed(ctx, "辞書データ一覧", &format!("```json\n{}\n```", truncated_data), 0x0099ff).await
-                }
+pub(super) enum ReadingError {
+    Pronunciation(String, KanaError),
+    Accent(String, KanaError),
+}
+
+impl ReadingError {
+    pub(super) async fn embed(self, ctx: &Context) -> serenity::all::CreateEmbed {
+        match self {
+            ReadingError::Pronunciation(input, e) => {
+                let description = format!("{}\n\n**入力された読み:** {}", e, input);
+                embed::simple_embed(ctx, "読み方が正しくありません", &description, 0xff0000).await
             }
+            ReadingError::Accent(pronunciation, e) => {
+                let description = format!(
+                    "{}\n\n**読み:** {} ({}モーラ)\n0は平板型、1以上は下がり目の直前のモーラの位置です",
+                    e, pronunciation, kana::count_morae(&pronunciation),
+                );
+                embed::simple_embed(ctx, "アクセントの位置が正しくありません", &description, 0xff0000).await
+            }
+        }
+    }
+}
+
+fn word_type_arg(args: &[CommandDataOption]) -> Option<WordType> {
+    args.iter()
+        .find(|opt| opt.name == "word_type")
+        .and_then(|opt| opt.value.as_str())
+        .and_then(|word_type| word_type.parse().ok())
+}
+
+fn priority_arg(args: &[CommandDataOption]) -> Option<i64> {
+    args.iter().find(|opt| opt.name == "priority").and_then(|opt| opt.value.as_i64())
+}
+
+pub(super) fn describe_word(word: &DictionaryWord, scope: Scope) -> String {
+    // サーバーの辞書はエンジンの形態素解析を通さずBOTが置き換えるので、単語の種類と優先度は保存されるだけで使われない
+    let note = match scope {
+        Scope::Global => "",
+        Scope::Guild(_) => " (全体の辞書でのみ有効)",
+    };
+    format!(
+        "**単語:** {}\n**読み方:** {}\n**アクセント:** {}\n**単語の種類:** {}{}\n**優先度:** {}{}\n**辞書:** {}",
+        word.surface, word.pronunciation, word.accent_type, word.word_type.label(), note, word.priority, note, scope.label(),
+    )
+}
+
+async fn list_data(ctx: &Context, interaction: &CommandInteraction, pool: &SqlitePool) -> Result<()> {
+    debug!("Listing dictionary data");
+
+    let query = subcommand_args(interaction)
+        .iter()
+        .find(|opt| opt.name == "query")
+        .and_then(|opt| opt.value.as_str())
+        .map(str::trim)
+        .filter(|query| !query.is_empty());
+
+    let (embed, components) = list_page(ctx, pool, interaction.guild_id, query, 0).await;
+    let builder = CreateInteractionResponseFollowup::new().embed(embed).components(components);
+    interaction.create_followup(&ctx.http, builder).await?;
+    Ok(())
+}
+
+/// 一覧のページ送りのボタン。カスタムIDは`dictionary_list:ページ:検索語`
+pub async fn handle_list_component(ctx: &Context, component: &ComponentInteraction, pool: &SqlitePool) -> Result<()> {
+    let mut parts = component.data.custom_id.splitn(3, ':').skip(1);
+    let page = parts.next().and_then(|page| page.parse::<usize>().ok()).unwrap_or(0);
+    let query = parts.next().filter(|query| !query.is_empty());
+    debug!(page, query, "Turning dictionary list page");
+
+    let (embed, components) = list_page(ctx, pool, component.guild_id, query, page).await;
+    let response = CreateInteractionResponseMessage::new().embed(embed).components(components);
+    component.create_response(&ctx.http, CreateInteractionResponse::UpdateMessage(response)).await?;
+    Ok(())
+}
+
+async fn list_page(ctx: &Context, pool: &SqlitePool, guild_id: Option<GuildId>, query: Option<&str>, page: usize) -> (CreateEmbed, Vec<CreateActionRow>) {
+    let guild_words = match guild_id {
+        Some(guild_id) => match dictionary::fetch_all(pool, Scope::Guild(guild_id)).await {
+            Ok(words) => words,
+            Err(e) => return (embed::simple_embed(ctx, "エラー", &format!("辞書の取得に失敗しました: {}", e), 0xff0000).await, vec![]),
         },
-        Err(e) => embed::simple_embed(ctx, "エラー", &format!("辞書の取得に失敗しました: {}", e), 0xff0000).await
+        None => Vec::new(),
+    };
+    let global_words = match dictionary::fetch_all(pool, Scope::Global).await {
+        Ok(words) => words,
+        Err(e) => return (embed::simple_embed(ctx, "エラー", &format!("辞書の取得に失敗しました: {}", e), 0xff0000).await, vec![]),
+    };
+
+    let total_entries = guild_words.len() + global_words.len();
+    if total_entries == 0 {
+        return (embed::simple_embed(ctx, "辞書データ一覧", "辞書に登録されている単語はありません", 0x0099ff).await, vec![]);
+    }
+
+    // (スコア, 単語, 辞書の表示, 全体の辞書か)。検索しない場合はすべて同じスコアで表層形の順に並べる
+    let mut entries = guild_words.iter()
+        .map(|word| (word, "サーバー", false))
+        .chain(global_words.iter().map(|word| {
+            // サーバーの辞書に同じ単語がある場合はそちらが優先される
+            let overridden = guild_words.iter().any(|guild_word| guild_word.surface == word.surface);
+            (word, if overridden { "全体・サーバーの辞書が優先" } else { "全体" }, true)
+        }))
+        .filter_map(|(word, label, global)| match query {
+            Some(query) => match_score(word, query).map(|score| (score, word, label, global)),
+            None => Some((0.0, word, label, global)),
+        })
+        .collect::<Vec<_>>();
+    entries.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| a.1.surface.cmp(&b.1.surface)));
+
+    let pages = entries.len().div_ceil(LIST_PAGE_SIZE).max(1);
+    let page = page.min(pages - 1);
+
+    let lines = entries.iter()
+        .enumerate()
+        .skip(page * LIST_PAGE_SIZE)
+        .take(LIST_PAGE_SIZE)
+        .map(|(index, (_, word, label, global))| if *global {
+            format!(
+                "`{}.` **{}** → {} (アクセント: {}, {}, 優先度: {}) [{}]",
+                index + 1, word.surface, word.pronunciation, word.accent_type, word.word_type.label(), word.priority, label,
+            )
+        } else {
+            format!("`{}.` **{}** → {} (アクセント: {}) [{}]", index + 1, word.surface, word.pronunciation, word.accent_type, label)
+        })
+        .collect::<Vec<_>>();
+
+    let mut description = format!("**登録単語数:** {}件 (サーバー: {}件 / 全体: {}件)", total_entries, guild_words.len(), global_words.len());
+    if let Some(query) = query {
+        description.push_str(&format!("\n**検索:** {} ({}件)", query, entries.len()));
     }
+    description.push_str("\n\n");
+    if lines.is_empty() {
+        description.push_str("一致する単語はありません");
+    } else {
+        description.push_str(&lines.join("\n"));
+    }
+
+    let embed = embed::simple_embed(ctx, "辞書データ一覧", &description, 0x0099ff).await
+        .footer(CreateEmbedFooter::new(format!("{} / {} ページ", page + 1, pages)));
+
+    if pages == 1 {
+        return (embed, vec![]);
+    }
+
+    let query = query.unwrap_or_default();
+    let buttons = CreateActionRow::Buttons(vec![
+        CreateButton::new(format!("{}:{}:{}", LIST_COMPONENT_PREFIX, page.saturating_sub(1), query))
+            .label("前へ")
+            .style(ButtonStyle::Secondary)
+            .disabled(page == 0),
+        CreateButton::new(format!("{}:{}:{}", LIST_COMPONENT_PREFIX, page + 1, query))
+            .label("次へ")
+            .style(ButtonStyle::Secondary)
+            .disabled(page + 1 >= pages),
+    ]);
+
+    (embed, vec![buttons])
+}
+
+/// 検索語との近さ。部分一致を優先し、それ以外は表層形か読みとの類似度で判定する
+fn match_score(word: &DictionaryWord, query: &str) -> Option<f64> {
+    let surface = word.surface.to_lowercase();
+    let query = query.to_lowercase();
+    let reading = kana::to_katakana(&query);
+
+    if surface == query || word.pronunciation == reading {
+        return Some(3.0);
+    }
+    if surface.contains(&query) || word.pronunciation.contains(&reading) {
+        return Some(2.0);
+    }
+
+    let similarity = strsim::jaro_winkler(&surface, &query).max(strsim::jaro_winkler(&word.pronunciation, &reading));
+    (similarity >= FUZZY_MATCH_THRESHOLD).then_some(similarity)
 }
 
-async fn remove_word(ctx: &Context, interaction: &CommandInteraction, voicevox_client: &VoicevoxClient) -> serenity::all::CreateEmbed {
+async fn remove_word(ctx: &Context, interaction: &CommandInteraction, pool: &SqlitePool, voicevox_client: &VoicevoxClient, scope: Scope) -> serenity::all::CreateEmbed {
     debug!("Removing word from dictionary: {:?}", interaction.data.options);
 
     let subcommand_args = if let Some(CommandDataOptionValue::SubCommand(args)) =
-        interaction.data.options.get(0).map(|opt| &opt.value)
+        interaction.data.options.first().map(|opt| &opt.value)
     {
         args
     } else {
@@ -298,64 +698,380 @@ async fn remove_word(ctx: &Context, interaction: &CommandInteraction, voicevox_c
         }
     };
 
-    if !voicevox_client.find_uuid_by_surface(surface.as_ref()).await.is_ok() {
-        return embed::simple_embed(ctx, "エラー", &format!("単語「{}」は辞書に登録されていません", surface), 0xff0000).await;
+    match dictionary::delete(pool, scope, interaction.user.id, surface).await {
+        Ok(true) => {}
+        Ok(false) => return embed::simple_embed(ctx, "エラー", &format!("単語「{}」は辞書に登録されていません", surface), 0xff0000).await,
+        Err(e) => return embed::simple_embed(ctx, "エラー", &format!("単語の削除に失敗しました: {}", e), 0xff0000).await,
     }
 
-    match voicevox_client.delete_dict_word(surface).await {
-        Ok(()) => {
-            auto_save_data(voicevox_client).await.unwrap();
-            embed::simple_embed(ctx, "単語を削除しました", &format!("**削除した単語:** {}", surface), 0x00ff00).await
-        },
-        Err(e) => embed::simple_embed(ctx, "エラー", &format!("単語の削除に失敗しました: {}", e), 0xff0000).await
+    let engine_result = match scope {
+        Scope::Global => voicevox_client.delete_dict_word(surface).await,
+        Scope::Guild(_) => Ok(()),
+    };
+    engine_result_embed(ctx, "単語を削除しました", format!("**削除した単語:** {}\n**辞書:** {}", surface, scope.label()), engine_result).await
+}
+
+async fn confirm_reset(ctx: &Context, interaction: &CommandInteraction, pool: &SqlitePool, confirmations: &Confirmations<PendingAction>) -> Result<()> {
+    let scope = match resolve_scope(ctx, interaction).await {
+        Ok(scope) => scope,
+        Err(embed) => return send_embed(ctx, interaction, embed).await,
+    };
+
+    let words = match dictionary::fetch_all(pool, scope).await {
+        Ok(words) => words,
+        Err(e) => {
+            error!("Failed to fetch dictionary words: {}", e);
+            let embed = embed::simple_embed(ctx, "エラー", &format!("辞書の取得に失敗しました: {}", e), 0xff0000).await;
+            return send_embed(ctx, interaction, embed).await;
+        }
+    };
+    if words.is_empty() {
+        let embed = embed::simple_embed(ctx, "エラー", &format!("{}の辞書に単語はありません", scope.label()), 0xff0000).await;
+        return send_embed(ctx, interaction, embed).await;
     }
+
+    let description = format!(
+        "{}の辞書の**{}件**の単語をすべて削除します。よろしいですか？\nリセット前の辞書はスナップショットとして保存されます",
+        scope.label(), words.len(),
+    );
+    let embed = embed::simple_embed(ctx, "辞書のリセットの確認", &description, 0xffaa00).await;
+    confirmations.request(ctx, interaction, CreateInteractionResponseFollowup::new().embed(embed), "リセットする", PendingAction::Reset(scope)).await
 }
 
-async fn reset_data(ctx: &Context, voicevox_client: &VoicevoxClient) -> serenity::all::CreateEmbed {
-    debug!("Resetting dictionary data");
+async fn confirm_restore(ctx: &Context, interaction: &CommandInteraction, pool: &SqlitePool, confirmations: &Confirmations<PendingAction>) -> Result<()> {
+    let Some(snapshot_id) = subcommand_args(interaction).iter().find(|opt| opt.name == "snapshot").and_then(|opt| opt.value.as_i64()) else {
+        let embed = embed::simple_embed(ctx, "辞書の復元の確認", "保存されている全体の辞書をエンジンに反映します。よろしいですか？\nエンジンにだけ登録されている単語は削除されます", 0xffaa00).await;
+        return confirmations.request(ctx, interaction, CreateInteractionResponseFollowup::new().embed(embed), "反映する", PendingAction::RestoreEngine).await;
+    };
 
-    match voicevox_client.reset_dict().await {
-        Ok(()) => {
-            auto_save_data(voicevox_client).await.unwrap();
-            embed::simple_embed(ctx, "辞書をリセットしました", "すべての単語が削除されました", 0x00ff00).await
-        },
-        Err(e) => embed::simple_embed(ctx, "エラー", &format!("辞書のリセットに失敗しました: {}", e), 0xff0000).await
+    let scope = match resolve_scope(ctx, interaction).await {
+        Ok(scope) => scope,
+        Err(embed) => return send_embed(ctx, interaction, embed).await,
+    };
+
+    let diff = match history::snapshot_diff(pool, scope, snapshot_id).await {
+        Ok(Some(diff)) => diff,
+        Ok(None) => {
+            let embed = embed::simple_embed(ctx, "エラー", &format!("{}の辞書にID {}のスナップショットはありません", scope.label(), snapshot_id), 0xff0000).await;
+            return send_embed(ctx, interaction, embed).await;
+        }
+        Err(e) => {
+            error!("Failed to load dictionary snapshot: {}", e);
+            let embed = embed::simple_embed(ctx, "エラー", &format!("スナップショットの取得に失敗しました: {}", e), 0xff0000).await;
+            return send_embed(ctx, interaction, embed).await;
+        }
+    };
+    if diff.is_empty() {
+        let embed = embed::simple_embed(ctx, "辞書の復元", "現在の辞書はスナップショットと同じ内容です", 0x0099ff).await;
+        return send_embed(ctx, interaction, embed).await;
     }
+
+    let description = format!("スナップショット(ID {})の時点の辞書に戻します。よろしいですか？\n\n{}", snapshot_id, describe_import(scope, ImportMode::Replace, &diff));
+    let embed = embed::simple_embed(ctx, "辞書の復元の確認", &description, 0xffaa00).await;
+    confirmations.request(ctx, interaction, CreateInteractionResponseFollowup::new().embed(embed), "復元する", PendingAction::RestoreSnapshot { scope, snapshot_id }).await
 }
 
-async fn restore_data(ctx: &Context, voicevox_client: &VoicevoxClient) -> serenity::all::CreateEmbed {
+async fn reset_data(ctx: &Context, pool: &SqlitePool, voicevox_client: &VoicevoxClient, scope: Scope, actor: UserId) -> serenity::all::CreateEmbed {
+    debug!("Resetting dictionary data: {:?}", scope);
+
+    if let Err(e) = dictionary::delete_all(pool, scope, actor).await {
+        return embed::simple_embed(ctx, "エラー", &format!("辞書のリセットに失敗しました: {}", e), 0xff0000).await;
+    }
+
+    let engine_result = match scope {
+        Scope::Global => dictionary::sync_to_engine(pool, voicevox_client).await.map(|_| ()),
+        Scope::Guild(_) => Ok(()),
+    };
+    engine_result_embed(ctx, "辞書をリセットしました", format!("{}の辞書のすべての単語が削除されました\nリセット前の辞書は `/dictionary restore` で復元できます", scope.label()), engine_result).await
+}
+
+async fn restore_data(ctx: &Context, pool: &SqlitePool, voicevox_client: &VoicevoxClient) -> serenity::all::CreateEmbed {
     debug!("Restoring dictionary data");
 
-    let data = match std::fs::read_to_string("user_dict.json") {
+    match dictionary::sync_to_engine(pool, voicevox_client).await {
+        Ok(report) => {
+            let description = format!(
+                "保存されている辞書をエンジンに反映しました\n**追加:** {}件\n**更新:** {}件\n**削除:** {}件",
+                report.added, report.updated, report.removed,
+            );
+            if report.failed > 0 {
+                embed::simple_embed(ctx, "辞書の復元に一部失敗しました", &format!("{}\n**失敗:** {}件\n再度実行してください", description, report.failed), 0xffaa00).await
+            } else {
+                embed::simple_embed(ctx, "辞書の復元に成功しました", &description, 0x00ff00).await
+            }
+        }
+        Err(e) => embed::simple_embed(ctx, "エラー", &format!("辞書の復元に失敗しました。再度実行してください: {}", e), 0xff0000).await
+    }
+}
+
+pub(super) async fn send_embed(ctx: &Context, interaction: &CommandInteraction, embed: CreateEmbed) -> Result<()> {
+    interaction.create_followup(&ctx.http, CreateInteractionResponseFollowup::new().embed(embed)).await?;
+    Ok(())
+}
+
+async fn export_data(ctx: &Context, interaction: &CommandInteraction, pool: &SqlitePool) -> Result<()> {
+    debug!("Exporting dictionary data");
+
+    // 読み出すだけなので全体の辞書も誰でもエクスポートできる
+    let scope = match (scope_choice(interaction), interaction.guild_id) {
+        ("global", _) => Scope::Global,
+        (_, Some(guild_id)) => Scope::Guild(guild_id),
+        (_, None) => {
+            let embed = embed::simple_embed(ctx, "エラー", "サーバーの辞書はギルド内でのみエクスポートできます", 0xff0000).await;
+            return send_embed(ctx, interaction, embed).await;
+        }
+    };
+
+    let words = match dictionary::fetch_all(pool, scope).await {
+        Ok(words) => words,
+        Err(e) => {
+            error!("Failed to fetch dictionary words: {}", e);
+            let embed = embed::simple_embed(ctx, "エラー", &format!("辞書の取得に失敗しました: {}", e), 0xff0000).await;
+            return send_embed(ctx, interaction, embed).await;
+        }
+    };
+
+    let json = match dictionary::to_engine_json(&words) {
+        Ok(json) => json,
+        Err(e) => {
+            error!("Failed to export dictionary: {}", e);
+            let embed = embed::simple_embed(ctx, "エラー", &format!("辞書の書き出しに失敗しました: {}", e), 0xff0000).await;
+            return send_embed(ctx, interaction, embed).await;
+        }
+    };
+    let csv = dictionary::to_csv(&words);
+
+    let description = format!("**辞書:** {}\n**単語数:** {}件\n\n`/dictionary import` でこのファイルを読み込めます", scope.label(), words.len());
+    let builder = CreateInteractionResponseFollowup::new()
+        .embed(embed::simple_embed(ctx, "辞書をエクスポートしました", &description, 0x00ff00).await)
+        .add_file(CreateAttachment::bytes(json.into_bytes(), "dictionary.json"))
+        .add_file(CreateAttachment::bytes(csv.into_bytes(), "dictionary.csv"));
+
+    interaction.create_followup(&ctx.http, builder).await?;
+    Ok(())
+}
+
+async fn import_data(ctx: &Context, interaction: &CommandInteraction, pool: &SqlitePool, voicevox_client: &VoicevoxClient, confirmations: &Confirmations<PendingAction>) -> Result<()> {
+    debug!("Importing dictionary data");
+
+    let scope = match resolve_scope(ctx, interaction).await {
+        Ok(scope) => scope,
+        Err(embed) => return send_embed(ctx, interaction, embed).await,
+    };
+
+    let args = subcommand_args(interaction);
+    let mode = match args.iter().find(|opt| opt.name == "mode").and_then(|opt| opt.value.as_str()) {
+        Some("replace") => ImportMode::Replace,
+        _ => ImportMode::Merge,
+    };
+
+    let attachment = args.iter()
+        .find(|opt| opt.name == "file")
+        .and_then(|opt| opt.value.as_attachment_id())
+        .and_then(|attachment_id| interaction.data.resolved.attachments.get(&attachment_id));
+    let Some(attachment) = attachment else {
+        let embed = embed::simple_embed(ctx, "エラー", "'file' オプションが見つかりません。", 0xff0000).await;
+        return send_embed(ctx, interaction, embed).await;
+    };
+
+    if attachment.size > MAX_IMPORT_FILE_SIZE {
+        let embed = embed::simple_embed(ctx, "エラー", "ファイルが大きすぎます (1MBまで)", 0xff0000).await;
+        return send_embed(ctx, interaction, embed).await;
+    }
+
+    let data = match attachment.download().await {
         Ok(data) => data,
         Err(e) => {
-            return embed::simple_embed(ctx, "エラー", &format!("辞書ファイルの読み込みに失敗しました: {}", e), 0xff0000).await;
+            error!("Failed to download attachment: {}", e);
+            let embed = embed::simple_embed(ctx, "エラー", &format!("ファイルのダウンロードに失敗しました: {}", e), 0xff0000).await;
+            return send_embed(ctx, interaction, embed).await;
         }
     };
+    let Some(data) = dictionary_convert::decode(&data) else {
+        let embed = embed::simple_embed(ctx, "エラー", "ファイルの文字コードはUTF-8かUTF-16にしてください", 0xff0000).await;
+        return send_embed(ctx, interaction, embed).await;
+    };
 
-    match voicevox_client.import_dict(data.as_str()).await {
-        Ok(()) => {
-            auto_save_data(voicevox_client).await.unwrap();
-            embed::simple_embed(ctx, "辞書の復元に成功しました", "最後に保存されたデータから復元されました", 0x00ff00).await
+    let mut converted = match dictionary_convert::convert(&attachment.filename, &data) {
+        Ok(converted) => converted,
+        Err(e) => {
+            let embed = embed::simple_embed(ctx, "エラー", &format!("ファイルを読み込めませんでした: {}", e), 0xff0000).await;
+            return send_embed(ctx, interaction, embed).await;
         }
-        Err(e) => embed::simple_embed(ctx, "エラー", &format!("辞書の復元に失敗しました。再度実行してください: {}", e), 0xff0000).await
+    };
+    converted.guess_accents_with_engine(voicevox_client).await;
+    debug!(words = converted.words.len(), errors = converted.errors.len(), guessed = converted.guessed.len(), "Converted import file");
+    let report = import_report(&converted);
+    let Converted { words: imported, errors, guessed } = converted;
+
+    let current = match dictionary::fetch_all(pool, scope).await {
+        Ok(words) => words,
+        Err(e) => {
+            error!("Failed to fetch dictionary words: {}", e);
+            let embed = embed::simple_embed(ctx, "エラー", &format!("辞書の取得に失敗しました: {}", e), 0xff0000).await;
+            return send_embed(ctx, interaction, embed).await;
+        }
+    };
+
+    let diff = dictionary::diff_import(&current, imported.clone(), mode);
+    debug!(added = diff.added.len(), changed = diff.changed.len(), removed = diff.removed.len(), conflicting = diff.conflicting.len(), "Computed import diff");
+
+    let description = format!("{}{}", describe_import(scope, mode, &diff), describe_conversion(&errors, &guessed));
+
+    if diff.is_empty() {
+        let embed = embed::simple_embed(ctx, "インポート", &format!("辞書に変更はありません\n\n{}", description), 0x0099ff).await;
+        let mut builder = CreateInteractionResponseFollowup::new().embed(embed);
+        if let Some(report) = report {
+            builder = builder.add_file(report);
+        }
+        interaction.create_followup(&ctx.http, builder).await?;
+        return Ok(());
     }
+
+    let preview = embed::simple_embed(ctx, "インポートの確認", &description, 0x0099ff).await;
+    let mut builder = CreateInteractionResponseFollowup::new().embed(preview);
+    if let Some(report) = report {
+        builder = builder.add_file(report);
+    }
+    confirmations.request(ctx, interaction, builder, "適用する", PendingAction::Import { scope, words: imported, mode }).await
 }
 
-async fn auto_save_data(voicevox_client: &VoicevoxClient) -> Result<()> {
-    match voicevox_client.get_user_dict().await {
-        Ok(data) => {
-            let mut file = std::fs::File::create("user_dict.json")?;
-            file.write_all(data.as_bytes())?;
-            Ok(())
+async fn apply_import_data(ctx: &Context, pool: &SqlitePool, voicevox_client: &VoicevoxClient, scope: Scope, actor: UserId, words: Vec<DictionaryWord>, mode: ImportMode) -> CreateEmbed {
+    match dictionary::apply_import(pool, scope, actor, words, mode).await {
+        Ok(diff) => {
+            let engine_result = match scope {
+                Scope::Global if !diff.is_empty() => dictionary::sync_to_engine(pool, voicevox_client).await.map(|_| ()),
+                _ => Ok(()),
+            };
+            let description = format!(
+                "**辞書:** {}\n**追加:** {}件\n**変更:** {}件\n**削除:** {}件",
+                scope.label(), diff.added.len(), diff.changed.len(), diff.removed.len(),
+            );
+            engine_result_embed(ctx, "辞書をインポートしました", description, engine_result).await
         }
         Err(e) => {
-            Err(anyhow::anyhow!("Failed to auto save dictionary: {}", e))
+            error!("Failed to import dictionary: {}", e);
+            embed::simple_embed(ctx, "エラー", &format!("辞書のインポートに失敗しました: {}", e), 0xff0000).await
         }
     }
 }
 
+fn describe_import(scope: Scope, mode: ImportMode, diff: &ImportDiff) -> String {
+    fn section(title: &str, lines: Vec<String>) -> String {
+        let total = lines.len();
+        let mut shown = lines.into_iter().take(MAX_PREVIEW_ENTRIES).collect::<Vec<_>>();
+        if total > MAX_PREVIEW_ENTRIES {
+            shown.push(format!("... 他{}件", total - MAX_PREVIEW_ENTRIES));
+        }
+        format!("**{}:** {}件\n{}", title, total, shown.join("\n"))
+    }
+
+    let mut sections = vec![format!("**辞書:** {}\n**モード:** {}\n**変更なし:** {}件", scope.label(), mode.label(), diff.unchanged)];
+    if !diff.added.is_empty() {
+        sections.push(section("追加", diff.added.iter()
+            .map(|word| format!("+ {} → {} ({})", word.surface, word.pronunciation, word.accent_type))
+            .collect()));
+    }
+    if !diff.changed.is_empty() {
+        sections.push(section("変更", diff.changed.iter()
+            .map(|(old, new)| format!(
+                "~ {}: {} ({}, {}, {}) → {} ({}, {}, {})",
+                old.surface,
+                old.pronunciation, old.accent_type, old.word_type.label(), old.priority,
+                new.pronunciation, new.accent_type, new.word_type.label(), new.priority,
+            ))
+            .collect()));
+    }
+    if !diff.removed.is_empty() {
+        sections.push(section("削除", diff.removed.iter()
+            .map(|word| format!("- {} → {}", word.surface, word.pronunciation))
+            .collect()));
+    }
+    if !diff.conflicting.is_empty() {
+        sections.push(section("競合 (ファイル内で読みが重複しているため適用しません)", diff.conflicting.iter()
+            .map(|surface| format!("! {}", surface))
+            .collect()));
+    }
+
+    sections.join("\n\n")
+}
+
+fn describe_conversion(errors: &[EntryError], guessed: &[GuessedAccent]) -> String {
+    let mut description = String::new();
+    if !guessed.is_empty() {
+        let by_rule = guessed.iter().filter(|guessed| !guessed.by_engine).count();
+        description.push_str(&format!("\n\n**アクセントを推定した単語:** {}件 (エンジン: {}件、規則: {}件)", guessed.len(), guessed.len() - by_rule, by_rule));
+        if by_rule > 0 {
+            description.push_str("\nエンジンを使えなかった単語は、後ろから3モーラ目を高くする規則で推定しました。添付のファイルを確認してください");
+        }
+    }
+    if !errors.is_empty() {
+        let mut lines = errors.iter()
+            .take(MAX_PREVIEW_ENTRIES)
+            .map(|error| format!("{}: {}", error.location, error.message))
+            .collect::<Vec<_>>();
+        if errors.len() > MAX_PREVIEW_ENTRIES {
+            lines.push(format!("... 他{}件 (添付のファイルを確認してください)", errors.len() - MAX_PREVIEW_ENTRIES));
+        }
+        description.push_str(&format!("\n\n**読み込めなかった単語:** {}件\n{}", errors.len(), lines.join("\n")));
+    }
+    description
+}
+
+/// 読み込めなかった単語と、アクセントを推定した単語を行ごとに書き出したファイル
+fn import_report(converted: &Converted) -> Option<CreateAttachment> {
+    if converted.errors.is_empty() && converted.guessed.is_empty() {
+        return None;
+    }
+    let errors = converted.errors.iter().map(|error| format!("{}: [エラー] {}\n", error.location, error.message));
+    let guessed = converted.guessed.iter().map(|guessed| {
+        let word = &converted.words[guessed.index];
+        let method = if guessed.by_engine { "エンジン" } else { "規則" };
+        format!("{}: [推定] 「{}」({}) のアクセントを{}で{}と推定しました\n", guessed.location, word.surface, word.pronunciation, method, word.accent_type)
+    });
+    let report = errors.chain(guessed).collect::<String>();
+    Some(CreateAttachment::bytes(report.into_bytes(), "import_report.txt"))
+}
+
+/// データベースへの保存後、エンジンへの反映結果に応じた埋め込みを返す
+pub(super) async fn engine_result_embed(ctx: &Context, title: &str, description: String, engine_result: Result<()>) -> serenity::all::CreateEmbed {
+    match engine_result {
+        Ok(()) => embed::simple_embed(ctx, title, &description, 0x00ff00).await,
+        Err(e) if e.is::<EnginesPending>() => {
+            info!("{}", e);
+            let description = format!("{}\n\n停止中のエンジンには、復帰したときに反映します", description);
+            embed::simple_embed(ctx, title, &description, 0x00ff00).await
+        }
+        Err(e) => {
+            warn!("Failed to apply dictionary change to engine: {}", e);
+            let description = format!("{}\n\nエンジンへの反映に失敗しました。エンジンが応答するようになったら再度反映します: {}", description, e);
+            embed::simple_embed(ctx, title, &description, 0xffaa00).await
+        }
+    }
+}
+
+fn word_type_option() -> CreateCommandOption {
+    WordType::ALL.into_iter().fold(
+        CreateCommandOption::new(CommandOptionType::String, "word_type", "単語の種類 (読み方に影響するのは全体の辞書のみ)"),
+        |option, word_type| option.add_string_choice(word_type.label(), word_type.as_str()),
+    )
+}
+
+fn priority_option() -> CreateCommandOption {
+    CreateCommandOption::new(CommandOptionType::Integer, "priority", "単語の優先度 (0〜10、大きいほど優先。読み方に影響するのは全体の辞書のみ)")
+        .min_int_value(0)
+        .max_int_value(10)
+}
+
+fn scope_option() -> CreateCommandOption {
+    CreateCommandOption::new(CommandOptionType::String, "scope", "編集する辞書 (省略するとこのサーバーの辞書)")
+        .add_string_choice("このサーバー", "server")
+        .add_string_choice("全体 (BOTのオーナーのみ)", "global")
+}
+
 pub fn register() -> CreateCommand {
+    // 一覧・エクスポート・テストは誰でも使え、編集系もロールに与えた権限で使えるように
+    // `default_member_permissions`は設定せず、サブコマンドごとに`run`で確認する
     let command = CreateCommand::new("dictionary");
     command
         .description("VOICEVOXの辞書を管理します")
@@ -367,46 +1083,144 @@ pub fn register() -> CreateCommand {
                         .max_length(100)
                 )
                 .add_sub_option(
-                    CreateCommandOption::new(CommandOptionType::String, "pronunciation", "カタカナでの読み方")
+                    CreateCommandOption::new(CommandOptionType::String, "pronunciation", "読み方 (ひらがなかカタカナ)")
                         .required(true)
                         .max_length(100)
                 )
                 .add_sub_option(
-                    CreateCommandOption::new(CommandOptionType::Integer, "accent_type", "何文字目にアクセントを付けるか")
+                    CreateCommandOption::new(CommandOptionType::Integer, "accent_type", "何モーラ目の後で音が下がるか (0で平板型)")
                         .required(true)
+                        .min_int_value(0)
                 )
+                .add_sub_option(word_type_option())
+                .add_sub_option(priority_option())
+                .add_sub_option(scope_option())
         )
         .add_option(
             CreateCommandOption::new(CommandOptionType::SubCommand, "edit", "辞書にある単語の編集をします")
                 .add_sub_option(
                     CreateCommandOption::new(CommandOptionType::String, "surface", "編集する単語")
                         .required(true)
+                        .set_autocomplete(true)
                         .max_length(100)
                 )
                 .add_sub_option(
-                    CreateCommandOption::new(CommandOptionType::String, "pronunciation", "カタカナでの読み方")
+                    CreateCommandOption::new(CommandOptionType::String, "pronunciation", "読み方 (ひらがなかカタカナ)")
                         .required(true)
                         .max_length(100)
                 )
                 .add_sub_option(
-                    CreateCommandOption::new(CommandOptionType::Integer, "accent_type", "何文字目にアクセントを付けるか")
+                    CreateCommandOption::new(CommandOptionType::Integer, "accent_type", "何モーラ目の後で音が下がるか (0で平板型)")
                         .required(true)
+                        .min_int_value(0)
                 )
+                .add_sub_option(word_type_option())
+                .add_sub_option(priority_option())
+                .add_sub_option(scope_option())
         )
         .add_option(
             CreateCommandOption::new(CommandOptionType::SubCommand, "list", "辞書にある単語の一覧を表示します")
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::String, "query", "単語か読みで検索します (あいまい検索)")
+                        .max_length(50)
+                )
         )
         .add_option(
             CreateCommandOption::new(CommandOptionType::SubCommand, "remove", "辞書の単語を削除します")
                 .add_sub_option(
                     CreateCommandOption::new(CommandOptionType::String, "surface", "削除する単語")
                         .required(true)
+                        .set_autocomplete(true)
                 )
+                .add_sub_option(scope_option())
         )
         .add_option(
             CreateCommandOption::new(CommandOptionType::SubCommand, "reset", "辞書をリセットします")
+                .add_sub_option(scope_option())
         )
         .add_option(
-            CreateCommandOption::new(CommandOptionType::SubCommand, "restore", "最後に保存された辞書データを復元します")
+            CreateCommandOption::new(CommandOptionType::SubCommand, "restore", "スナップショットから辞書を復元します。省略すると保存されている辞書をエンジンに反映します")
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::Integer, "snapshot", "復元するスナップショット")
+                        .set_autocomplete(true)
+                )
+                .add_sub_option(scope_option())
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "snapshot", "現在の辞書をスナップショットとして保存します")
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::String, "label", "スナップショットの名前")
+                        .max_length(50)
+                )
+                .add_sub_option(scope_option())
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "history", "辞書の変更履歴を表示します")
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::String, "scope", "表示する辞書 (省略するとこのサーバーの辞書)")
+                        .add_string_choice("このサーバー", "server")
+                        .add_string_choice("全体", "global")
+                )
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "undo", "直近の辞書の変更を取り消します")
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::Integer, "count", "取り消す変更の数 (省略すると1)")
+                        .min_int_value(1)
+                        .max_int_value(50)
+                )
+                .add_sub_option(scope_option())
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "accent", "ボタンでアクセントを選び、試聴しながら単語を登録します")
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::String, "surface", "アクセントを編集する単語")
+                        .required(true)
+                        .set_autocomplete(true)
+                        .max_length(100)
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::String, "pronunciation", "読み方 (省略すると登録されている読みかエンジンの推定)")
+                        .max_length(100)
+                )
+                .add_sub_option(scope_option())
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "test", "単語を辞書の登録前と登録後の読みでVCに流して聞き比べます")
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::String, "surface", "確認する単語")
+                        .required(true)
+                        .set_autocomplete(true)
+                        .max_length(100)
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::String, "pronunciation", "試す読み方 (省略すると登録されている読み)")
+                        .max_length(100)
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::Integer, "accent_type", "試すアクセントの位置 (0で平板型)")
+                        .min_int_value(0)
+                )
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "export", "辞書をJSONとCSVのファイルで書き出します")
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::String, "scope", "書き出す辞書 (省略するとこのサーバーの辞書)")
+                        .add_string_choice("このサーバー", "server")
+                        .add_string_choice("全体", "global")
+                )
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "import", "ファイルから辞書を読み込みます。他の読み上げBOTの辞書にも対応しています")
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::Attachment, "file", "JSON、CSV (単語,読み[,アクセント])、TSV、IMEの.dicファイル")
+                        .required(true)
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::String, "mode", "既存の単語の扱い (省略するとマージ)")
+                        .add_string_choice("マージ", "merge")
+                        .add_string_choice("置き換え", "replace")
+                )
+                .add_sub_option(scope_option())
         )
 }
\ No newline at end of file
@@ -0,0 +1,359 @@
+use crate::commands::dictionary::{describe_word, engine_result_embed, resolve_scope, send_embed, subcommand_args, validate_reading};
+use crate::voice::manager::VoiceManager;
+use crate::voice::playback;
+use crate::voice::voicevox::accent;
+use crate::voice::voicevox::client::{Client as VoicevoxClient, DEFAULT_PRIORITY};
+use crate::voice::voicevox::dictionary::{self, DictionaryIndex, DictionaryWord, Scope};
+use crate::voice::voicevox::kana;
+use crate::embed;
+use anyhow::Result;
+use serenity::{
+    all::{ComponentInteractionDataKind, GuildId, UserId},
+    builder::{
+        CreateActionRow, CreateButton, CreateEmbed, CreateInteractionResponse, CreateInteractionResponseFollowup, CreateInteractionResponseMessage,
+        CreateSelectMenu, CreateSelectMenuKind, CreateSelectMenuOption, EditInteractionResponse,
+    },
+    model::application::{ButtonStyle, CommandInteraction, ComponentInteraction},
+    prelude::*,
+};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{debug, error, warn};
+
+pub const ACCENT_COMPONENT_PREFIX: &str = "dictionary_accent";
+/// 編集画面を操作できる時間。インタラクションのトークンが切れる15分より短くする
+const EDITOR_TIMEOUT: Duration = Duration::from_secs(600);
+/// セレクトメニューの選択肢は25個までなので、平板型と24モーラ目までを並べる
+const MAX_SELECT_MORAE: usize = 24;
+
+/// アクセントの編集中の単語。ボタンのカスタムIDは`dictionary_accent:操作:ID`
+pub struct AccentEditors {
+    sessions: Arc<Mutex<HashMap<u64, Session>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+#[derive(Clone)]
+struct Session {
+    user_id: UserId,
+    scope: Scope,
+    surface: String,
+    morae: Vec<String>,
+    accent_type: usize,
+}
+
+impl Default for AccentEditors {
+    fn default() -> Self {
+        Self { sessions: Arc::default(), next_id: Arc::new(AtomicU64::new(1)) }
+    }
+}
+
+/// `/dictionary accent`で編集画面を開く。単語の読みとアクセントは登録済みの値か、エンジンの推定から始める
+pub async fn editor_data(ctx: &Context, interaction: &CommandInteraction, pool: &SqlitePool, voicevox_client: &VoicevoxClient, editors: &AccentEditors) -> Result<()> {
+    debug!("Opening accent editor: {:?}", interaction.data.options);
+
+    let scope = match resolve_scope(ctx, interaction).await {
+        Ok(scope) => scope,
+        Err(embed) => return send_embed(ctx, interaction, embed).await,
+    };
+    let args = subcommand_args(interaction);
+    let Some(surface) = args.iter().find(|opt| opt.name == "surface").and_then(|opt| opt.value.as_str()) else {
+        let embed = embed::simple_embed(ctx, "エラー", "'surface' オプションが見つかりません。", 0xff0000).await;
+        return send_embed(ctx, interaction, embed).await;
+    };
+
+    let registered = match dictionary::fetch(pool, scope, surface).await {
+        Ok(registered) => registered,
+        Err(e) => {
+            let embed = embed::simple_embed(ctx, "エラー", &format!("辞書の取得に失敗しました: {}", e), 0xff0000).await;
+            return send_embed(ctx, interaction, embed).await;
+        }
+    };
+    let guessed = match guess_reading(voicevox_client, surface).await {
+        Ok(guessed) => guessed,
+        Err(e) => {
+            error!("Failed to guess reading: {}", e);
+            let embed = embed::simple_embed(ctx, "エラー", &format!("読みの推定に失敗しました: {}", e), 0xff0000).await;
+            return send_embed(ctx, interaction, embed).await;
+        }
+    };
+
+    // 読みを指定した場合は、登録済みかエンジンの推定と同じ読みのときだけそのアクセントを引き継ぐ
+    let (pronunciation, accent_type) = match args.iter().find(|opt| opt.name == "pronunciation").and_then(|opt| opt.value.as_str()) {
+        Some(pronunciation) => {
+            let pronunciation = match validate_reading(pronunciation, 0) {
+                Ok((pronunciation, _)) => pronunciation,
+                Err(e) => return send_embed(ctx, interaction, e.embed(ctx).await).await,
+            };
+            let accent_type = [registered.as_ref().map(|word| (word.pronunciation.clone(), word.accent_type as usize)), Some(guessed.clone())]
+                .into_iter()
+                .flatten()
+                .find(|(known, _)| *known == pronunciation)
+                .map_or(0, |(_, accent_type)| accent_type);
+            (pronunciation, accent_type)
+        }
+        None => match &registered {
+            Some(word) => (word.pronunciation.clone(), word.accent_type as usize),
+            None => guessed,
+        },
+    };
+    if pronunciation.is_empty() {
+        let embed = embed::simple_embed(ctx, "エラー", &format!("「{}」の読みを推定できませんでした。'pronunciation' で読みを指定してください", surface), 0xff0000).await;
+        return send_embed(ctx, interaction, embed).await;
+    }
+
+    let morae = kana::split_morae(&pronunciation);
+    let session = Session {
+        user_id: interaction.user.id,
+        scope,
+        surface: surface.to_string(),
+        accent_type: accent_type.min(morae.len()),
+        morae,
+    };
+    let id = editors.next_id.fetch_add(1, Ordering::Relaxed);
+    let (embed, components) = (editor_embed(ctx, &session).await, editor_components(&session, id));
+    editors.sessions.lock().unwrap().insert(id, session);
+
+    let builder = CreateInteractionResponseFollowup::new().embed(embed).components(components);
+    let message = match interaction.create_followup(&ctx.http, builder).await {
+        Ok(message) => message,
+        Err(e) => {
+            editors.sessions.lock().unwrap().remove(&id);
+            return Err(e.into());
+        }
+    };
+
+    let sessions = editors.sessions.clone();
+    let ctx = ctx.clone();
+    let interaction = interaction.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(EDITOR_TIMEOUT).await;
+        if sessions.lock().unwrap().remove(&id).is_none() {
+            return;
+        }
+
+        debug!(id, "Accent editor timed out");
+        let embed = embed::simple_embed(&ctx, "編集の期限が切れました", "時間内に保存されなかったため、辞書は変更されていません", 0xffaa00).await;
+        let builder = CreateInteractionResponseFollowup::new().embed(embed).components(vec![]);
+        if let Err(e) = interaction.edit_followup(&ctx.http, message.id, builder).await {
+            warn!("Failed to update timed out accent editor: {}", e);
+        }
+    });
+
+    Ok(())
+}
+
+/// エンジンの`/audio_query`のアクセント句から、単語の読みとアクセントを推定する
+pub(super) async fn guess_reading(voicevox_client: &VoicevoxClient, surface: &str) -> Result<(String, usize)> {
+    let audio_query = voicevox_client.create_audio_query(surface, playback::SPEAKER_ID, playback::SPEED_SCALE).await?;
+    let (_, accent_phrases) = accent::parse_audio_query(&audio_query)?;
+    let (pronunciation, accent_type) = accent::word_reading(&accent_phrases);
+    Ok((kana::normalize_pronunciation(&pronunciation).unwrap_or_default(), accent_type))
+}
+
+/// 編集画面のボタンとセレクトメニューの操作
+pub async fn handle_accent_component(ctx: &Context, component: &ComponentInteraction, pool: &SqlitePool, voicevox_client: &VoicevoxClient, voice_manager: &VoiceManager, index: &DictionaryIndex, editors: &AccentEditors) -> Result<()> {
+    let mut parts = component.data.custom_id.splitn(3, ':').skip(1);
+    let action = parts.next().unwrap_or_default();
+    let id = parts.next().and_then(|id| id.parse::<u64>().ok()).unwrap_or_default();
+    debug!(id, action, "Operating accent editor");
+
+    let session = {
+        let mut sessions = editors.sessions.lock().unwrap();
+        match sessions.get_mut(&id) {
+            Some(session) if session.user_id != component.user.id => Err(()),
+            Some(session) => {
+                let last = session.morae.len();
+                match (action, &component.data.kind) {
+                    ("select", ComponentInteractionDataKind::StringSelect { values }) => {
+                        session.accent_type = values.first().and_then(|value| value.parse::<usize>().ok()).unwrap_or(0).min(last);
+                    }
+                    ("left", _) => session.accent_type = session.accent_type.saturating_sub(1),
+                    ("right", _) => session.accent_type = (session.accent_type + 1).min(last),
+                    _ => {}
+                }
+                Ok(if matches!(action, "save" | "cancel") { sessions.remove(&id) } else { Some(session.clone()) })
+            }
+            None => Ok(None),
+        }
+    };
+
+    let session = match session {
+        Ok(Some(session)) => session,
+        Ok(None) => {
+            let embed = embed::simple_embed(ctx, "編集の期限が切れました", "もう一度 `/dictionary accent` を実行してください", 0xffaa00).await;
+            let response = CreateInteractionResponseMessage::new().embed(embed).components(vec![]);
+            component.create_response(&ctx.http, CreateInteractionResponse::UpdateMessage(response)).await?;
+            return Ok(());
+        }
+        Err(()) => {
+            let embed = embed::simple_embed(ctx, "エラー", "この編集画面はコマンドを実行した人のみ操作できます", 0xff0000).await;
+            let response = CreateInteractionResponseMessage::new().embed(embed).ephemeral(true);
+            component.create_response(&ctx.http, CreateInteractionResponse::Message(response)).await?;
+            return Ok(());
+        }
+    };
+
+    match action {
+        "preview" => preview(ctx, component, voicevox_client, voice_manager, &session).await,
+        "save" => {
+            // エンジンへの反映に時間がかかることがあるため、先に応答してから結果で書き換える
+            component.create_response(&ctx.http, CreateInteractionResponse::Acknowledge).await?;
+            let result_embed = save(ctx, pool, voicevox_client, component.user.id, &session).await;
+
+            if let Err(e) = index.refresh(pool).await {
+                warn!("Failed to refresh dictionary index: {}", e);
+            }
+
+            component.edit_response(&ctx.http, EditInteractionResponse::new().embed(result_embed).components(vec![])).await?;
+            Ok(())
+        }
+        "cancel" => {
+            let embed = embed::simple_embed(ctx, "キャンセルしました", "辞書は変更されていません", 0x0099ff).await;
+            let response = CreateInteractionResponseMessage::new().embed(embed).components(vec![]);
+            component.create_response(&ctx.http, CreateInteractionResponse::UpdateMessage(response)).await?;
+            Ok(())
+        }
+        _ => {
+            let response = CreateInteractionResponseMessage::new().embed(editor_embed(ctx, &session).await).components(editor_components(&session, id));
+            component.create_response(&ctx.http, CreateInteractionResponse::UpdateMessage(response)).await?;
+            Ok(())
+        }
+    }
+}
+
+/// 選んでいるアクセントで単語をVCに流す
+async fn preview(ctx: &Context, component: &ComponentInteraction, voicevox_client: &VoicevoxClient, voice_manager: &VoiceManager, session: &Session) -> Result<()> {
+    let connected_guild = match (component.guild_id, songbird::get(ctx).await) {
+        (Some(guild_id), Some(manager)) => manager.get(guild_id).map(|_| guild_id),
+        _ => None,
+    };
+    let Some(guild_id) = connected_guild else {
+        let embed = embed::simple_embed(ctx, "エラー", "VCに接続していません。`/join` で参加してから試聴してください", 0xff0000).await;
+        let response = CreateInteractionResponseMessage::new().embed(embed).ephemeral(true);
+        component.create_response(&ctx.http, CreateInteractionResponse::Message(response)).await?;
+        return Ok(());
+    };
+
+    component.create_response(&ctx.http, CreateInteractionResponse::Acknowledge).await?;
+    if let Err(e) = play_preview(ctx, voicevox_client, voice_manager, guild_id, session).await {
+        error!("Failed to preview accent: {}", e);
+        let embed = embed::simple_embed(ctx, "エラー", &format!("試聴に失敗しました: {}", e), 0xff0000).await;
+        component.create_followup(&ctx.http, CreateInteractionResponseFollowup::new().embed(embed).ephemeral(true)).await?;
+    }
+    Ok(())
+}
+
+async fn play_preview(ctx: &Context, voicevox_client: &VoicevoxClient, voice_manager: &VoiceManager, guild_id: GuildId, session: &Session) -> Result<()> {
+    let audio_query = voicevox_client.create_audio_query(&session.surface, playback::SPEAKER_ID, playback::SPEED_SCALE).await?;
+    let accent_phrases = voicevox_client.create_accent_phrases_from_kana(&accent::to_kana(&session.morae.concat(), session.accent_type), playback::SPEAKER_ID).await?;
+    let audio_query = accent::replace_accent_phrases(&audio_query, accent_phrases)?;
+    playback::play_audio_query(ctx, voicevox_client, voice_manager, guild_id, &audio_query, playback::SPEAKER_ID).await
+}
+
+/// 登録済みの単語は書き換え、無ければ追加する。単語の種類と優先度は登録済みの値を引き継ぐ
+async fn save(ctx: &Context, pool: &SqlitePool, voicevox_client: &VoicevoxClient, actor: UserId, session: &Session) -> CreateEmbed {
+    let pronunciation = session.morae.concat();
+    let accent_type = session.accent_type as u8;
+
+    let existing = match dictionary::fetch(pool, session.scope, &session.surface).await {
+        Ok(existing) => existing,
+        Err(e) => return embed::simple_embed(ctx, "エラー", &format!("辞書の取得に失敗しました: {}", e), 0xff0000).await,
+    };
+    let word = DictionaryWord {
+        surface: session.surface.clone(),
+        pronunciation: pronunciation.clone(),
+        accent_type: accent_type as i64,
+        word_type: existing.as_ref().map(|word| word.word_type).unwrap_or_default(),
+        priority: existing.as_ref().map_or(DEFAULT_PRIORITY as i64, |word| word.priority),
+    };
+
+    let registered = existing.is_some();
+    let (title, saved) = if registered {
+        ("単語を編集しました", dictionary::update(pool, session.scope, actor, &word).await)
+    } else {
+        ("辞書に追加しました", dictionary::insert(pool, session.scope, actor, &word).await)
+    };
+    match saved {
+        Ok(true) => {}
+        Ok(false) => return embed::simple_embed(ctx, "エラー", "保存中に辞書が変更されました。もう一度実行してください", 0xff0000).await,
+        Err(e) => return embed::simple_embed(ctx, "エラー", &format!("辞書の保存に失敗しました: {}", e), 0xff0000).await,
+    }
+
+    let engine_result = match session.scope {
+        Scope::Global if registered => voicevox_client.rewrite_dict_word(&word.surface, &pronunciation, accent_type, Some(word.word_type), word.priority as u8).await,
+        Scope::Global => voicevox_client.add_dict_word(&word.surface, &pronunciation, accent_type, Some(word.word_type), word.priority as u8).await,
+        Scope::Guild(_) => Ok(()),
+    };
+    engine_result_embed(ctx, title, describe_word(&word, session.scope), engine_result).await
+}
+
+async fn editor_embed(ctx: &Context, session: &Session) -> CreateEmbed {
+    let description = format!(
+        "**単語:** {}\n**読み:** {}\n**アクセント:** {} ({})\n**音の高さ:** {}\n**辞書:** {}\n\n\
+        メニューか◀▶で音が下がる位置を選び、「試聴」でVCで確認してから「保存」してください",
+        session.surface,
+        marked(&session.morae, session.accent_type),
+        session.accent_type,
+        accent_kind(session.morae.len(), session.accent_type),
+        pitch_pattern(session.morae.len(), session.accent_type),
+        session.scope.label(),
+    );
+    embed::simple_embed(ctx, "アクセントの編集", &description, 0x0099ff).await
+}
+
+fn editor_components(session: &Session, id: u64) -> Vec<CreateActionRow> {
+    let options = std::iter::once(CreateSelectMenuOption::new("0: 平板型 (下がらない)", "0").default_selection(session.accent_type == 0))
+        .chain(session.morae.iter().enumerate().take(MAX_SELECT_MORAE).map(|(index, mora)| {
+            let accent_type = index + 1;
+            CreateSelectMenuOption::new(format!("{}: {}", accent_type, marked(&session.morae, accent_type)), accent_type.to_string())
+                .description(format!("「{}」の後で音が下がる", mora))
+                .default_selection(session.accent_type == accent_type)
+        }))
+        .collect();
+    let select = CreateSelectMenu::new(format!("{}:select:{}", ACCENT_COMPONENT_PREFIX, id), CreateSelectMenuKind::String { options })
+        .placeholder("音が下がる位置を選ぶ");
+
+    let buttons = vec![
+        CreateButton::new(format!("{}:left:{}", ACCENT_COMPONENT_PREFIX, id)).label("◀").style(ButtonStyle::Secondary).disabled(session.accent_type == 0),
+        CreateButton::new(format!("{}:right:{}", ACCENT_COMPONENT_PREFIX, id)).label("▶").style(ButtonStyle::Secondary).disabled(session.accent_type == session.morae.len()),
+        CreateButton::new(format!("{}:preview:{}", ACCENT_COMPONENT_PREFIX, id)).label("試聴").style(ButtonStyle::Primary),
+        CreateButton::new(format!("{}:save:{}", ACCENT_COMPONENT_PREFIX, id)).label("保存").style(ButtonStyle::Success),
+        CreateButton::new(format!("{}:cancel:{}", ACCENT_COMPONENT_PREFIX, id)).label("キャンセル").style(ButtonStyle::Secondary),
+    ];
+    vec![CreateActionRow::SelectMenu(select), CreateActionRow::Buttons(buttons)]
+}
+
+/// 下がり目のモーラの後に「＼」を付けた表記
+fn marked(morae: &[String], accent_type: usize) -> String {
+    morae
+        .iter()
+        .enumerate()
+        .map(|(index, mora)| if index + 1 == accent_type { format!("{}＼", mora) } else { mora.clone() })
+        .collect()
+}
+
+fn accent_kind(morae: usize, accent_type: usize) -> &'static str {
+    match accent_type {
+        0 => "平板型",
+        1 => "頭高型",
+        n if n == morae => "尾高型",
+        _ => "中高型",
+    }
+}
+
+/// 東京式アクセントの各モーラの高低。1モーラ目と2モーラ目は必ず高さが変わる
+fn pitch_pattern(morae: usize, accent_type: usize) -> String {
+    (1..=morae)
+        .map(|position| {
+            let high = match accent_type {
+                0 => position > 1,
+                1 => position == 1,
+                n => position > 1 && position <= n,
+            };
+            if high { '高' } else { '低' }
+        })
+        .collect()
+}
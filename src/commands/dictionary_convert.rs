@@ -0,0 +1,417 @@
+use crate::commands::dictionary_accent::guess_reading;
+use crate::voice::voicevox::client::{Client as VoicevoxClient, WordType, DEFAULT_PRIORITY};
+use crate::voice::voicevox::dictionary::DictionaryWord;
+use crate::voice::voicevox::kana::{self, KanaError};
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// 見出し行とみなす1列目の値
+const HEADER_NAMES: [&str; 6] = ["surface", "word", "単語", "語句", "表記", "よみ"];
+const SURFACE_KEYS: [&str; 6] = ["surface", "word", "key", "from", "before", "単語"];
+const READING_KEYS: [&str; 8] = ["pronunciation", "reading", "read", "yomi", "value", "to", "after", "読み"];
+const ACCENT_KEYS: [&str; 2] = ["accent_type", "accent"];
+/// エンジンでアクセントを推定する時間の上限。超えた分は規則で推定したままにする
+const ENGINE_GUESS_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// ファイルの1件分。単語と読み以外は省略できる
+#[derive(Debug, Default)]
+struct RawEntry<'a> {
+    surface: &'a str,
+    reading: &'a str,
+    accent_type: Option<&'a str>,
+    word_type: Option<WordType>,
+    word_type_name: Option<&'a str>,
+    priority: Option<&'a str>,
+}
+
+/// 読み込めなかった行と理由
+#[derive(Debug)]
+pub struct EntryError {
+    /// 「3行目」「3件目」のような位置
+    pub location: String,
+    pub message: String,
+}
+
+/// アクセントが指定されておらず推定した単語
+#[derive(Debug)]
+pub struct GuessedAccent {
+    pub location: String,
+    /// `Converted::words`での位置
+    pub index: usize,
+    /// エンジンで推定できたか。`false`は規則による推定
+    pub by_engine: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct Converted {
+    pub words: Vec<DictionaryWord>,
+    pub errors: Vec<EntryError>,
+    pub guessed: Vec<GuessedAccent>,
+}
+
+impl Converted {
+    fn push(&mut self, location: String, entry: RawEntry) {
+        match convert_entry(entry) {
+            Ok((word, guessed)) => {
+                if guessed {
+                    self.guessed.push(GuessedAccent { location, index: self.words.len(), by_engine: false });
+                }
+                self.words.push(word);
+            }
+            Err(message) => self.errors.push(EntryError { location, message }),
+        }
+    }
+
+    /// 規則で推定したアクセントを、エンジンの`/audio_query`の推定で置き換える。
+    /// エンジンが使えなくなった時点で問い合わせをやめ、残りは規則による推定のままにする
+    pub async fn guess_accents_with_engine(&mut self, voicevox_client: &VoicevoxClient) {
+        let deadline = tokio::time::Instant::now() + ENGINE_GUESS_TIMEOUT;
+
+        for position in 0..self.guessed.len() {
+            let pronunciation = self.words[self.guessed[position].index].pronunciation.clone();
+            match tokio::time::timeout_at(deadline, guess_reading(voicevox_client, &pronunciation)).await {
+                Ok(Ok((reading, accent_type))) => {
+                    if !self.apply_engine_guess(position, &reading, accent_type) {
+                        debug!("Engine read {} as {}, keeping the guessed accent", pronunciation, reading);
+                    }
+                }
+                Ok(Err(e)) => {
+                    warn!("Failed to guess accents with the engine: {}", e);
+                    break;
+                }
+                Err(_) => {
+                    warn!("Timed out guessing accents with the engine");
+                    break;
+                }
+            }
+        }
+    }
+
+    /// エンジンが読みを同じモーラ数で読んだときだけ、そのアクセントを使う
+    fn apply_engine_guess(&mut self, position: usize, reading: &str, accent_type: usize) -> bool {
+        let guessed = &mut self.guessed[position];
+        let word = &mut self.words[guessed.index];
+        if kana::count_morae(reading) != kana::count_morae(&word.pronunciation) || kana::validate_accent(&word.pronunciation, accent_type as i64).is_err() {
+            return false;
+        }
+        word.accent_type = accent_type as i64;
+        guessed.by_engine = true;
+        true
+    }
+}
+
+/// 添付ファイルの文字コードを判定して文字列にする。BOM付きのUTF-16(IMEの書き出し)にも対応する
+pub fn decode(bytes: &[u8]) -> Option<String> {
+    match bytes {
+        [0xef, 0xbb, 0xbf, rest @ ..] => String::from_utf8(rest.to_vec()).ok(),
+        [0xff, 0xfe, rest @ ..] => decode_utf16(rest, u16::from_le_bytes),
+        [0xfe, 0xff, rest @ ..] => decode_utf16(rest, u16::from_be_bytes),
+        _ => String::from_utf8(bytes.to_vec()).ok(),
+    }
+}
+
+fn decode_utf16(bytes: &[u8], from_bytes: fn([u8; 2]) -> u16) -> Option<String> {
+    if !bytes.len().is_multiple_of(2) {
+        return None;
+    }
+    let units = bytes.chunks_exact(2).map(|pair| from_bytes([pair[0], pair[1]])).collect::<Vec<_>>();
+    String::from_utf16(&units).ok()
+}
+
+/// ファイル名と内容から形式を判定し、辞書の単語に変換する
+///
+/// 対応する形式:
+/// - VOICEVOXや他の読み上げBOTのJSON (`{"単語": "読み"}`、単語のオブジェクトの配列など)
+/// - `単語,読み[,アクセント]`のCSV
+/// - `単語<TAB>読み[<TAB>アクセント]`のTSV
+/// - `.dic` (IMEのユーザー辞書の書き出し形式 `よみ<TAB>単語<TAB>品詞`)
+pub fn convert(filename: &str, data: &str) -> Result<Converted> {
+    let data = data.trim_start_matches('\u{feff}');
+    let extension = filename.rsplit_once('.').map(|(_, extension)| extension.to_ascii_lowercase());
+
+    let trimmed = data.trim_start();
+    if trimmed.starts_with('{') || trimmed.starts_with('[') {
+        return convert_json(data);
+    }
+
+    match extension.as_deref() {
+        Some("dic") => Ok(convert_lines(data, '\t', true)),
+        Some("tsv") => Ok(convert_lines(data, '\t', false)),
+        Some("csv") => Ok(convert_lines(data, ',', false)),
+        _ if data.lines().find(|line| !line.trim().is_empty()).is_some_and(|line| line.contains('\t')) => Ok(convert_lines(data, '\t', false)),
+        _ => Ok(convert_lines(data, ',', false)),
+    }
+}
+
+fn convert_lines(data: &str, delimiter: char, reading_first: bool) -> Converted {
+    let mut converted = Converted::default();
+
+    for (index, line) in data.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        // IMEの書き出しは`!`で始まる行がコメント
+        if line.trim().is_empty() || line.starts_with('!') || line.starts_with('#') {
+            continue;
+        }
+
+        let fields = split_line(line, delimiter);
+        if index == 0 && fields.first().is_some_and(|field| HEADER_NAMES.contains(&field.trim())) {
+            continue;
+        }
+
+        let location = format!("{}行目", index + 1);
+        let field = |index: usize| fields.get(index).map(String::as_str);
+        let entry = match fields.len() {
+            // IMEの形式は3列目以降が品詞やコメントなので、アクセントは推定する
+            2.. if reading_first => RawEntry { surface: &fields[1], reading: &fields[0], ..Default::default() },
+            // 単語,読み[,アクセント[,単語の種類[,優先度]]]
+            2..=5 => RawEntry {
+                surface: &fields[0],
+                reading: &fields[1],
+                accent_type: field(2),
+                word_type_name: field(3),
+                priority: field(4),
+                ..Default::default()
+            },
+            _ => {
+                converted.errors.push(EntryError { location, message: "列の数が正しくありません".to_string() });
+                continue;
+            }
+        };
+
+        converted.push(location, entry);
+    }
+
+    converted
+}
+
+fn convert_json(data: &str) -> Result<Converted> {
+    let json: Value = serde_json::from_str(data).context("JSONの形式が正しくありません")?;
+    let mut converted = Converted::default();
+
+    let entries: Vec<(Option<&str>, &Value)> = match &json {
+        Value::Object(map) => map.iter().map(|(key, value)| (Some(key.as_str()), value)).collect(),
+        Value::Array(items) => items.iter().map(|value| (None, value)).collect(),
+        _ => return Err(anyhow::anyhow!("JSONの形式に対応していません")),
+    };
+
+    for (index, (key, value)) in entries.into_iter().enumerate() {
+        let location = format!("{}件目", index + 1);
+        match value {
+            // {"単語": "読み"}
+            Value::String(reading) => match key {
+                Some(surface) => converted.push(location, RawEntry { surface, reading, ..Default::default() }),
+                None => converted.errors.push(EntryError { location, message: "単語が指定されていません".to_string() }),
+            },
+            // [["単語", "読み"], ...]
+            Value::Array(pair) => match pair.as_slice() {
+                [Value::String(surface), Value::String(reading)] => converted.push(location, RawEntry { surface, reading, ..Default::default() }),
+                _ => converted.errors.push(EntryError { location, message: "単語と読みの組になっていません".to_string() }),
+            },
+            // VOICEVOXの`{"uuid": {"surface": ..}}`や`[{"word": .., "read": ..}]`
+            Value::Object(entry) => {
+                let field = |keys: &[&str]| keys.iter().find_map(|key| entry.get(*key));
+                let surface = field(&SURFACE_KEYS).and_then(|value| value.as_str());
+                let reading = field(&READING_KEYS).and_then(|value| value.as_str());
+                let text = |value: &Value| match value {
+                    Value::String(text) => text.clone(),
+                    other => other.to_string(),
+                };
+                let accent_type = field(&ACCENT_KEYS).map(text);
+                let priority = entry.get("priority").map(text);
+                // エンジンの`/user_dict`は単語の種類ではなく品詞を返す
+                let word_type = entry.get("part_of_speech").and_then(|value| value.as_str()).map(|part_of_speech| {
+                    let detail = entry.get("part_of_speech_detail_1").and_then(|value| value.as_str()).unwrap_or_default();
+                    WordType::from_part_of_speech(part_of_speech, detail)
+                });
+
+                match (surface, reading) {
+                    (Some(surface), Some(reading)) => converted.push(location, RawEntry {
+                        surface,
+                        reading,
+                        accent_type: accent_type.as_deref(),
+                        word_type,
+                        word_type_name: entry.get("word_type").and_then(|value| value.as_str()),
+                        priority: priority.as_deref(),
+                    }),
+                    _ => converted.errors.push(EntryError { location, message: "単語か読みが見つかりません".to_string() }),
+                }
+            }
+            _ => converted.errors.push(EntryError { location, message: "単語の形式に対応していません".to_string() }),
+        }
+    }
+
+    Ok(converted)
+}
+
+fn convert_entry(entry: RawEntry) -> std::result::Result<(DictionaryWord, bool), String> {
+    let RawEntry { surface, reading, accent_type, word_type, word_type_name, priority } = entry;
+    let surface = surface.trim();
+    if surface.is_empty() {
+        return Err("単語が空です".to_string());
+    }
+
+    let pronunciation = match kana::normalize_pronunciation(reading) {
+        Ok(pronunciation) => pronunciation,
+        Err(KanaError::Empty) => return Err(format!("「{}」の読みが空です", surface)),
+        Err(e) => return Err(format!("「{}」: {}", surface, e)),
+    };
+
+    let (accent_type, guessed) = match accent_type.map(str::trim).filter(|accent_type| !accent_type.is_empty()) {
+        Some(accent_type) => match accent_type.parse::<i64>() {
+            Ok(accent_type) => (accent_type, false),
+            Err(_) => return Err(format!("「{}」のアクセントが数字ではありません", surface)),
+        },
+        None => (guess_accent(kana::count_morae(&pronunciation) as i64), true),
+    };
+    // 推定したアクセントも、小書きの文字だけの読みなどではモーラ数に収まらない
+    if let Err(e) = kana::validate_accent(&pronunciation, accent_type) {
+        return Err(format!("「{}」: {}", surface, e));
+    }
+
+    let word_type = match word_type_name.map(str::trim).filter(|name| !name.is_empty()) {
+        Some(name) => match WordType::ALL.into_iter().find(|word_type| word_type.as_str() == name || word_type.label() == name) {
+            Some(word_type) => word_type,
+            None => return Err(format!("「{}」の単語の種類「{}」は対応していません", surface, name)),
+        },
+        None => word_type.unwrap_or_default(),
+    };
+
+    let priority = match priority.map(str::trim).filter(|priority| !priority.is_empty()) {
+        Some(priority) => match priority.parse::<i64>() {
+            Ok(priority) if (0..=10).contains(&priority) => priority,
+            _ => return Err(format!("「{}」の優先度は0から10の数字にしてください", surface)),
+        },
+        None => DEFAULT_PRIORITY as i64,
+    };
+
+    Ok((DictionaryWord { surface: surface.to_string(), pronunciation, accent_type, word_type, priority }, guessed))
+}
+
+/// エンジンを使えないときの推定。外来語や固有名詞に多い、後ろから3モーラ目にアクセントを置く型にする
+fn guess_accent(morae: i64) -> i64 {
+    if morae <= 2 { 1 } else { morae - 2 }
+}
+
+fn split_line(line: &str, delimiter: char) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => in_quotes = !in_quotes,
+            c if c == delimiter && !in_quotes => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(converted: &Converted) -> Vec<(&str, &str, i64)> {
+        converted.words.iter().map(|word| (word.surface.as_str(), word.pronunciation.as_str(), word.accent_type)).collect()
+    }
+
+    fn errors(converted: &Converted) -> Vec<&str> {
+        converted.errors.iter().map(|error| error.location.as_str()).collect()
+    }
+
+    #[test]
+    fn decode_handles_bom_and_utf16() {
+        assert_eq!(decode(b"\xef\xbb\xbfword,reading").as_deref(), Some("word,reading"));
+
+        let utf16_le = [0xff, 0xfe].into_iter().chain("単語".encode_utf16().flat_map(u16::to_le_bytes)).collect::<Vec<_>>();
+        assert_eq!(decode(&utf16_le).as_deref(), Some("単語"));
+        let utf16_be = [0xfe, 0xff].into_iter().chain("単語".encode_utf16().flat_map(u16::to_be_bytes)).collect::<Vec<_>>();
+        assert_eq!(decode(&utf16_be).as_deref(), Some("単語"));
+
+        assert_eq!(decode(&[0xff, 0xfe, 0x41]), None);
+        assert_eq!(decode(&[0xc3, 0x28]), None);
+    }
+
+    #[test]
+    fn convert_csv_skips_header_and_reads_optional_columns() {
+        let data = "surface,pronunciation,accent_type,word_type,priority\r\n緑,みどり,0\r\n\"a,b\",エービー,1,COMMON_NOUN,7\r\n# コメント\r\n\r\n";
+        let converted = convert("words.csv", data).unwrap();
+
+        assert_eq!(words(&converted), [("緑", "ミドリ", 0), ("a,b", "エービー", 1)]);
+        assert_eq!(converted.words[1].word_type, WordType::CommonNoun);
+        assert_eq!(converted.words[1].priority, 7);
+        assert!(converted.guessed.is_empty());
+        assert!(converted.errors.is_empty());
+    }
+
+    #[test]
+    fn convert_tsv_guesses_missing_accents() {
+        let converted = convert("words.txt", "ずんだもん\tずんだもん\n東北\tﾄｳﾎｸ\n").unwrap();
+
+        assert_eq!(words(&converted), [("ずんだもん", "ズンダモン", 3), ("東北", "トウホク", 2)]);
+        assert_eq!(converted.guessed.iter().map(|guessed| (guessed.location.as_str(), guessed.index, guessed.by_engine)).collect::<Vec<_>>(), [("1行目", 0, false), ("2行目", 1, false)]);
+    }
+
+    #[test]
+    fn engine_guess_replaces_heuristic_only_for_same_morae() {
+        let mut converted = convert("words.csv", "緑,みどり,0\n東北,とうほく\nずんだもん,ずんだもん\n").unwrap();
+        assert_eq!(converted.guessed.iter().map(|guessed| guessed.index).collect::<Vec<_>>(), [1, 2]);
+
+        assert!(converted.apply_engine_guess(0, "トーホク", 0));
+        // モーラ数が違う読みやモーラ数を超えるアクセントは使わない
+        assert!(!converted.apply_engine_guess(1, "ズンダ", 1));
+        assert!(!converted.apply_engine_guess(1, "ズンダモン", 6));
+
+        assert_eq!(words(&converted), [("緑", "ミドリ", 0), ("東北", "トウホク", 0), ("ずんだもん", "ズンダモン", 3)]);
+        assert!(converted.guessed[0].by_engine);
+        assert!(!converted.guessed[1].by_engine);
+    }
+
+    #[test]
+    fn convert_dic_reads_reading_first() {
+        let data = "!Microsoft IME Dictionary Tool\nみどりん\t緑りん\t固有名詞\nきゃ\t脚\t名詞\n";
+        let converted = convert("user.dic", data).unwrap();
+
+        assert_eq!(words(&converted), [("緑りん", "ミドリン", 2), ("脚", "キャ", 1)]);
+    }
+
+    #[test]
+    fn convert_json_accepts_maps_pairs_and_engine_dict() {
+        let converted = convert("dict.json", r#"{"緑": "みどり", "青": 1}"#).unwrap();
+        assert_eq!(words(&converted), [("緑", "ミドリ", 1)]);
+        assert_eq!(errors(&converted), ["2件目"]);
+
+        let converted = convert("dict.json", r#"[["緑", "みどり"], ["空"], {"word": "東北", "read": "とうほく", "accent": 0}]"#).unwrap();
+        assert_eq!(words(&converted), [("緑", "ミドリ", 1), ("東北", "トウホク", 0)]);
+        assert_eq!(errors(&converted), ["2件目"]);
+
+        let engine_dict = r#"{"2b6f8d2e-0000-4000-8000-000000000001": {"surface": "ｍｉｄｏｒｉｎ", "pronunciation": "ミドリン", "accent_type": 1, "priority": 10, "part_of_speech": "名詞", "part_of_speech_detail_1": "一般"}}"#;
+        let converted = convert("user_dict.json", engine_dict).unwrap();
+        assert_eq!(words(&converted), [("ｍｉｄｏｒｉｎ", "ミドリン", 1)]);
+        assert_eq!(converted.words[0].word_type, WordType::CommonNoun);
+        assert_eq!(converted.words[0].priority, 10);
+
+        assert!(convert("dict.json", "{").is_err());
+        assert_eq!(errors(&convert("dict.json", "[1]").unwrap()), ["1件目"]);
+    }
+
+    #[test]
+    fn convert_reports_invalid_rows() {
+        let data = "緑,みどり,0\n列,が,0,COMMON_NOUN,5,多い\n空,,1\n英語,abc\n範囲,はんい,4\n負数,ふすう,-1\n数字,すうじ,一\n小書き,ぁ\n種類,しゅるい,0,動物\n優先度,ゆうせんど,0,COMMON_NOUN,11\n";
+        let converted = convert("words.csv", data).unwrap();
+
+        assert_eq!(words(&converted), [("緑", "ミドリ", 0)]);
+        assert_eq!(errors(&converted), ["2行目", "3行目", "4行目", "5行目", "6行目", "7行目", "8行目", "9行目", "10行目"]);
+        assert!(converted.errors[0].message.contains("列の数"));
+        // 小書きの文字だけの読みはモーラが無いので、推定したアクセントも範囲外になる
+        assert_eq!(converted.errors[6].message, "「小書き」: アクセントの位置(1)は0から読みのモーラ数(0)までの数字にしてください");
+    }
+}
@@ -0,0 +1,195 @@
+use crate::commands::dictionary::{describe_word, engine_result_embed, validate_reading};
+use crate::commands::dictionary_accent::guess_reading;
+use crate::voice::voicevox::client::{Client as VoicevoxClient, WordType, DEFAULT_PRIORITY};
+use crate::voice::voicevox::dictionary::{self, DictionaryIndex, DictionaryWord, Scope};
+use crate::embed;
+use crate::permissions::{self, Permission};
+use anyhow::Result;
+use serenity::{
+    all::{ActionRowComponent, CommandType, InputTextStyle, ResolvedTarget},
+    builder::{CreateActionRow, CreateCommand, CreateEmbed, CreateInputText, CreateInteractionResponse, CreateInteractionResponseFollowup, CreateInteractionResponseMessage, CreateModal},
+    model::application::{CommandInteraction, ModalInteraction},
+    prelude::*,
+};
+use sqlx::SqlitePool;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+pub const COMMAND_NAME: &str = "辞書に登録";
+pub const FORM_MODAL_ID: &str = "dictionary_form";
+/// 単語と読みの入力欄の長さ。`/dictionary add`のオプションと揃える
+const MAX_FIELD_LENGTH: usize = 100;
+/// 辞書の入力欄の値。`Scope::label`と揃える
+const GUILD_SCOPE_LABEL: &str = "サーバー";
+const GLOBAL_SCOPE_LABEL: &str = "全体";
+/// モーダルは3秒以内に返す必要があるため、読みの推定とオーナーの確認はこの時間で打ち切る
+const OPEN_TIMEOUT: Duration = Duration::from_millis(1500);
+
+/// メッセージの右クリックメニューから、本文を単語にした登録フォームを開く
+///
+/// モーダルは最初の応答でしか開けないため`defer`せず、読みの推定に失敗するか時間がかかったら読みの欄を空にする。
+/// BOTのオーナーには、全体の辞書に登録するための単語の種類と辞書の欄も表示する
+pub async fn run(ctx: &Context, interaction: &CommandInteraction, pool: &SqlitePool, voicevox_client: &VoicevoxClient) -> Result<()> {
+    if !permissions::require(ctx, pool, interaction, Permission::DictionaryEdit).await? {
+        return Ok(());
+    }
+
+    let surface = match interaction.data.target() {
+        Some(ResolvedTarget::Message(message)) => message.content.trim().chars().take(MAX_FIELD_LENGTH).collect::<String>(),
+        _ => String::new(),
+    };
+    if interaction.guild_id.is_none() || surface.is_empty() {
+        let description = if surface.is_empty() { "本文のあるメッセージを選んでください" } else { "このコマンドはギルド内でのみ使えます" };
+        let embed = embed::simple_embed(ctx, "エラー", description, 0xff0000).await;
+        let response = CreateInteractionResponseMessage::new().embed(embed).ephemeral(true);
+        interaction.create_response(&ctx.http, CreateInteractionResponse::Message(response)).await?;
+        return Ok(());
+    }
+    debug!("Opening dictionary form: {}", surface);
+
+    let (guessed, is_owner) = tokio::join!(
+        tokio::time::timeout(OPEN_TIMEOUT, guess_reading(voicevox_client, &surface)),
+        tokio::time::timeout(OPEN_TIMEOUT, permissions::is_bot_owner(ctx, interaction.user.id)),
+    );
+    let is_owner = match is_owner {
+        Ok(Ok(is_owner)) => is_owner,
+        Ok(Err(e)) => {
+            warn!("Failed to check bot owner: {}", e);
+            false
+        }
+        Err(_) => {
+            warn!("Timed out checking bot owner");
+            false
+        }
+    };
+    let (pronunciation, accent_type) = match guessed {
+        Ok(Ok((pronunciation, accent_type))) if pronunciation.chars().count() <= MAX_FIELD_LENGTH => (pronunciation, accent_type.to_string()),
+        Ok(Ok(_)) => (String::new(), String::new()),
+        Ok(Err(e)) => {
+            warn!("Failed to guess reading: {}", e);
+            (String::new(), String::new())
+        }
+        Err(_) => {
+            warn!("Timed out guessing reading");
+            (String::new(), String::new())
+        }
+    };
+
+    let mut pronunciation_input = CreateInputText::new(InputTextStyle::Short, "読み方 (ひらがなかカタカナ)", "pronunciation").max_length(MAX_FIELD_LENGTH as u16);
+    let mut accent_input = CreateInputText::new(InputTextStyle::Short, "何モーラ目の後で音が下がるか (0で平板型)", "accent_type").max_length(3);
+    if !pronunciation.is_empty() {
+        pronunciation_input = pronunciation_input.value(pronunciation);
+        accent_input = accent_input.value(accent_type);
+    }
+    let mut components = vec![
+        CreateActionRow::InputText(CreateInputText::new(InputTextStyle::Short, "単語", "surface").max_length(MAX_FIELD_LENGTH as u16).value(surface)),
+        CreateActionRow::InputText(pronunciation_input),
+        CreateActionRow::InputText(accent_input),
+    ];
+    // サーバーの辞書はBOTが読みに置き換えるので単語の種類は使われない。全体の辞書を編集できるオーナーにだけ入力させる
+    if is_owner {
+        let word_types = WordType::ALL.map(|word_type| word_type.label()).join("・");
+        components.push(CreateActionRow::InputText(
+            CreateInputText::new(InputTextStyle::Short, "単語の種類 (全体の辞書でのみ有効)", "word_type")
+                .placeholder(word_types)
+                .value(WordType::default().label())
+                .required(false),
+        ));
+        components.push(CreateActionRow::InputText(
+            CreateInputText::new(InputTextStyle::Short, format!("辞書 ({} か {})", GUILD_SCOPE_LABEL, GLOBAL_SCOPE_LABEL), "scope")
+                .value(GUILD_SCOPE_LABEL)
+                .required(false),
+        ));
+    }
+    let modal = CreateModal::new(FORM_MODAL_ID, "辞書に登録").components(components);
+    interaction.create_response(&ctx.http, CreateInteractionResponse::Modal(modal)).await?;
+    Ok(())
+}
+
+/// フォームが送信されたら、選ばれた辞書(省略するとこのサーバーの辞書)に単語を追加する
+pub async fn handle_modal(ctx: &Context, modal: &ModalInteraction, pool: &SqlitePool, voicevox_client: &VoicevoxClient, index: &DictionaryIndex) -> Result<()> {
+    modal.defer(&ctx.http).await?;
+
+    let response_embed = add_word(ctx, modal, pool, voicevox_client).await;
+
+    if let Err(e) = index.refresh(pool).await {
+        warn!("Failed to refresh dictionary index: {}", e);
+    }
+
+    modal.create_followup(&ctx.http, CreateInteractionResponseFollowup::new().embed(response_embed)).await?;
+    Ok(())
+}
+
+async fn add_word(ctx: &Context, modal: &ModalInteraction, pool: &SqlitePool, voicevox_client: &VoicevoxClient) -> CreateEmbed {
+    let Some(guild_id) = modal.guild_id else {
+        return embed::simple_embed(ctx, "エラー", "サーバーの辞書はギルド内でのみ編集できます", 0xff0000).await;
+    };
+    let field = |name: &str| {
+        modal.data.components
+            .iter()
+            .flat_map(|row| row.components.iter())
+            .find_map(|component| match component {
+                ActionRowComponent::InputText(input) if input.custom_id == name => input.value.as_deref().map(str::trim),
+                _ => None,
+            })
+            .unwrap_or_default()
+    };
+    debug!("Adding word from dictionary form: {}", field("surface"));
+
+    let surface = field("surface");
+    if surface.is_empty() {
+        return embed::simple_embed(ctx, "エラー", "単語を入力してください", 0xff0000).await;
+    }
+    let Ok(accent_type) = field("accent_type").parse::<i64>() else {
+        return embed::simple_embed(ctx, "エラー", "アクセントは0以上の数字で入力してください", 0xff0000).await;
+    };
+    let (pronunciation, accent_type) = match validate_reading(field("pronunciation"), accent_type) {
+        Ok(validated) => validated,
+        Err(e) => return e.embed(ctx).await,
+    };
+    // 単語の種類と辞書の欄はオーナーにだけ表示しているので、無ければ既定値にする
+    let word_type = match field("word_type") {
+        "" => WordType::default(),
+        value => match WordType::ALL.into_iter().find(|word_type| word_type.label() == value || word_type.as_str() == value) {
+            Some(word_type) => word_type,
+            None => {
+                let word_types = WordType::ALL.map(|word_type| word_type.label()).join("・");
+                return embed::simple_embed(ctx, "エラー", &format!("単語の種類は{}のどれかを入力してください", word_types), 0xff0000).await;
+            }
+        },
+    };
+    let scope = if field("scope") == GLOBAL_SCOPE_LABEL {
+        match permissions::is_bot_owner(ctx, modal.user.id).await {
+            Ok(true) => Scope::Global,
+            Ok(false) => return embed::simple_embed(ctx, "エラー", "全体の辞書はBOTのオーナーのみ編集できます", 0xff0000).await,
+            Err(e) => {
+                warn!("Failed to check bot owner: {}", e);
+                return embed::simple_embed(ctx, "エラー", &format!("権限の確認に失敗しました: {}", e), 0xff0000).await;
+            }
+        }
+    } else {
+        Scope::Guild(guild_id)
+    };
+    let word = DictionaryWord {
+        surface: surface.to_string(),
+        pronunciation,
+        accent_type: accent_type as i64,
+        word_type,
+        priority: DEFAULT_PRIORITY as i64,
+    };
+    match dictionary::insert(pool, scope, modal.user.id, &word).await {
+        Ok(true) => {}
+        Ok(false) => return embed::simple_embed(ctx, "エラー", "既に辞書に同じ単語が存在します。`/dictionary edit` で編集してください", 0xff0000).await,
+        Err(e) => return embed::simple_embed(ctx, "エラー", &format!("辞書の追加に失敗しました: {}", e), 0xff0000).await,
+    }
+
+    let engine_result = match scope {
+        Scope::Global => voicevox_client.add_dict_word(&word.surface, &word.pronunciation, accent_type, Some(word.word_type), DEFAULT_PRIORITY).await,
+        Scope::Guild(_) => Ok(()),
+    };
+    engine_result_embed(ctx, "辞書に追加しました", describe_word(&word, scope), engine_result).await
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new(COMMAND_NAME).kind(CommandType::Message)
+}
@@ -0,0 +1,257 @@
+use crate::commands::dictionary::{engine_result_embed, resolve_scope, send_embed, subcommand_args};
+use crate::voice::voicevox::client::Client as VoicevoxClient;
+use crate::voice::voicevox::dictionary::{self, DictionaryWord, Scope};
+use crate::voice::voicevox::history::{self, Change, ChangeEntry};
+use crate::embed;
+use anyhow::Result;
+use serenity::{
+    all::{GuildId, UserId},
+    builder::{CreateActionRow, CreateButton, CreateEmbed, CreateEmbedFooter, CreateInteractionResponse, CreateInteractionResponseFollowup, CreateInteractionResponseMessage},
+    model::application::{ButtonStyle, CommandInteraction, ComponentInteraction},
+    prelude::*,
+};
+use sqlx::SqlitePool;
+use tracing::{debug, error};
+
+pub const HISTORY_COMPONENT_PREFIX: &str = "dictionary_history";
+const HISTORY_PAGE_SIZE: i64 = 10;
+/// 1件の履歴で表示する単語の数
+const MAX_ENTRIES_PER_CHANGE: i64 = 3;
+/// 取り消しの結果で表示する単語の数
+const MAX_UNDO_ENTRIES: usize = 10;
+/// 復元するスナップショットの補完候補の数
+const MAX_SNAPSHOT_CHOICES: i64 = 25;
+
+/// 閲覧するだけなので、全体の辞書の履歴も誰でも見られる
+fn view_scope(scope: &str, guild_id: Option<GuildId>) -> Option<Scope> {
+    match (scope, guild_id) {
+        ("global", _) => Some(Scope::Global),
+        (_, Some(guild_id)) => Some(Scope::Guild(guild_id)),
+        (_, None) => None,
+    }
+}
+
+pub async fn history_data(ctx: &Context, interaction: &CommandInteraction, pool: &SqlitePool) -> Result<()> {
+    debug!("Showing dictionary history");
+
+    let scope_name = subcommand_args(interaction)
+        .iter()
+        .find(|opt| opt.name == "scope")
+        .and_then(|opt| opt.value.as_str())
+        .unwrap_or("server");
+    let Some(scope) = view_scope(scope_name, interaction.guild_id) else {
+        let embed = embed::simple_embed(ctx, "エラー", "サーバーの辞書の履歴はギルド内でのみ表示できます", 0xff0000).await;
+        return send_embed(ctx, interaction, embed).await;
+    };
+
+    let (embed, components) = history_page(ctx, pool, scope, scope_name, 0).await;
+    let builder = CreateInteractionResponseFollowup::new().embed(embed).components(components);
+    interaction.create_followup(&ctx.http, builder).await?;
+    Ok(())
+}
+
+/// 履歴のページ送りのボタン。カスタムIDは`dictionary_history:ページ:辞書`
+pub async fn handle_history_component(ctx: &Context, component: &ComponentInteraction, pool: &SqlitePool) -> Result<()> {
+    let mut parts = component.data.custom_id.splitn(3, ':').skip(1);
+    let page = parts.next().and_then(|page| page.parse::<i64>().ok()).unwrap_or(0);
+    let scope_name = parts.next().unwrap_or("server");
+    debug!(page, scope_name, "Turning dictionary history page");
+
+    let (embed, components) = match view_scope(scope_name, component.guild_id) {
+        Some(scope) => history_page(ctx, pool, scope, scope_name, page).await,
+        None => (embed::simple_embed(ctx, "エラー", "サーバーの辞書の履歴はギルド内でのみ表示できます", 0xff0000).await, vec![]),
+    };
+    let response = CreateInteractionResponseMessage::new().embed(embed).components(components);
+    component.create_response(&ctx.http, CreateInteractionResponse::UpdateMessage(response)).await?;
+    Ok(())
+}
+
+async fn history_page(ctx: &Context, pool: &SqlitePool, scope: Scope, scope_name: &str, page: i64) -> (CreateEmbed, Vec<CreateActionRow>) {
+    let total = match history::changes(pool, scope, 1, 0).await {
+        Ok((_, total)) => total,
+        Err(e) => return (embed::simple_embed(ctx, "エラー", &format!("履歴の取得に失敗しました: {}", e), 0xff0000).await, vec![]),
+    };
+    if total == 0 {
+        return (embed::simple_embed(ctx, "辞書の変更履歴", &format!("{}の辞書の変更履歴はありません", scope.label()), 0x0099ff).await, vec![]);
+    }
+
+    let pages = (total + HISTORY_PAGE_SIZE - 1) / HISTORY_PAGE_SIZE;
+    let page = page.clamp(0, pages - 1);
+    let changes = match history::changes(pool, scope, HISTORY_PAGE_SIZE, page * HISTORY_PAGE_SIZE).await {
+        Ok((changes, _)) => changes,
+        Err(e) => return (embed::simple_embed(ctx, "エラー", &format!("履歴の取得に失敗しました: {}", e), 0xff0000).await, vec![]),
+    };
+
+    let mut lines = Vec::new();
+    for change in &changes {
+        let entries = match history::entries(pool, change.id, MAX_ENTRIES_PER_CHANGE).await {
+            Ok(entries) => entries,
+            Err(e) => {
+                error!("Failed to fetch dictionary change entries: {}", e);
+                Vec::new()
+            }
+        };
+        lines.push(describe_change(change, &entries));
+    }
+
+    let description = format!(
+        "**辞書:** {}\n`/dictionary undo` で新しいものから取り消せます\n\n{}",
+        scope.label(), lines.join("\n\n"),
+    );
+    let embed = embed::simple_embed(ctx, "辞書の変更履歴", &description, 0x0099ff).await
+        .footer(CreateEmbedFooter::new(format!("{} / {} ページ", page + 1, pages)));
+
+    if pages == 1 {
+        return (embed, vec![]);
+    }
+
+    let buttons = CreateActionRow::Buttons(vec![
+        CreateButton::new(format!("{}:{}:{}", HISTORY_COMPONENT_PREFIX, (page - 1).max(0), scope_name))
+            .label("新しい変更")
+            .style(ButtonStyle::Secondary)
+            .disabled(page == 0),
+        CreateButton::new(format!("{}:{}:{}", HISTORY_COMPONENT_PREFIX, page + 1, scope_name))
+            .label("古い変更")
+            .style(ButtonStyle::Secondary)
+            .disabled(page + 1 >= pages),
+    ]);
+
+    (embed, vec![buttons])
+}
+
+fn describe_change(change: &Change, entries: &[ChangeEntry]) -> String {
+    let user = match change.user_id {
+        0 => "不明なユーザー".to_string(),
+        user_id => format!("<@{}>", user_id),
+    };
+    let mut header = format!("`#{}` <t:{}:f> {} **{}**", change.id, change.created_at, user, change.action.label());
+    if change.entry_count > 1 {
+        header.push_str(&format!(" ({}件)", change.entry_count));
+    }
+    if change.undone {
+        header = format!("~~{}~~ (取り消し済み)", header);
+    }
+
+    let mut lines = vec![header];
+    lines.extend(entries.iter().map(|entry| format!("- {}", describe_entry(entry))));
+    if change.entry_count > entries.len() as i64 {
+        lines.push(format!("- ほか{}件", change.entry_count - entries.len() as i64));
+    }
+    lines.join("\n")
+}
+
+fn describe_entry(entry: &ChangeEntry) -> String {
+    fn reading(word: &DictionaryWord) -> String {
+        format!("{} (アクセント: {})", word.pronunciation, word.accent_type)
+    }
+
+    match (&entry.before, &entry.after) {
+        (None, Some(after)) => format!("「{}」 → {}", entry.surface, reading(after)),
+        (Some(before), Some(after)) => format!("「{}」 {} → {}", entry.surface, reading(before), reading(after)),
+        (Some(before), None) => format!("「{}」 {} → 削除", entry.surface, reading(before)),
+        (None, None) => format!("「{}」", entry.surface),
+    }
+}
+
+pub async fn undo_data(ctx: &Context, interaction: &CommandInteraction, pool: &SqlitePool, voicevox_client: &VoicevoxClient) -> CreateEmbed {
+    debug!("Undoing dictionary changes: {:?}", interaction.data.options);
+
+    let scope = match resolve_scope(ctx, interaction).await {
+        Ok(scope) => scope,
+        Err(embed) => return embed,
+    };
+    let count = subcommand_args(interaction)
+        .iter()
+        .find(|opt| opt.name == "count")
+        .and_then(|opt| opt.value.as_i64())
+        .unwrap_or(1);
+
+    let report = match history::undo(pool, scope, interaction.user.id, count).await {
+        Ok(report) => report,
+        Err(e) => {
+            error!("Failed to undo dictionary changes: {}", e);
+            return embed::simple_embed(ctx, "エラー", &format!("変更の取り消しに失敗しました: {}", e), 0xff0000).await;
+        }
+    };
+    if report.changes == 0 {
+        return embed::simple_embed(ctx, "取り消す変更がありません", &format!("{}の辞書に取り消せる変更はありません", scope.label()), 0xffaa00).await;
+    }
+
+    let mut description = format!("**辞書:** {}\n**取り消した操作:** {}件\n**元に戻した単語:** {}件", scope.label(), report.changes, report.entries.len());
+    let lines = report.entries.iter().take(MAX_UNDO_ENTRIES).map(|entry| format!("- {}", describe_entry(entry))).collect::<Vec<_>>();
+    if !lines.is_empty() {
+        description.push_str(&format!("\n\n{}", lines.join("\n")));
+    }
+
+    let engine_result = match scope {
+        Scope::Global => dictionary::sync_to_engine(pool, voicevox_client).await.map(|_| ()),
+        Scope::Guild(_) => Ok(()),
+    };
+    engine_result_embed(ctx, "変更を取り消しました", description, engine_result).await
+}
+
+pub async fn snapshot_data(ctx: &Context, interaction: &CommandInteraction, pool: &SqlitePool) -> CreateEmbed {
+    debug!("Creating dictionary snapshot");
+
+    let scope = match resolve_scope(ctx, interaction).await {
+        Ok(scope) => scope,
+        Err(embed) => return embed,
+    };
+    let label = subcommand_args(interaction)
+        .iter()
+        .find(|opt| opt.name == "label")
+        .and_then(|opt| opt.value.as_str())
+        .map(str::trim)
+        .filter(|label| !label.is_empty())
+        .unwrap_or("手動");
+
+    match history::snapshot(pool, scope, interaction.user.id, label).await {
+        Ok(snapshot_id) => {
+            let description = format!("**辞書:** {}\n**名前:** {}\n**ID:** {}\n\n`/dictionary restore` でこの時点の辞書に戻せます", scope.label(), label, snapshot_id);
+            embed::simple_embed(ctx, "スナップショットを保存しました", &description, 0x00ff00).await
+        }
+        Err(e) => {
+            error!("Failed to create dictionary snapshot: {}", e);
+            embed::simple_embed(ctx, "エラー", &format!("スナップショットの保存に失敗しました: {}", e), 0xff0000).await
+        }
+    }
+}
+
+/// 辞書をスナップショットの時点に戻す
+pub async fn restore_snapshot(ctx: &Context, pool: &SqlitePool, voicevox_client: &VoicevoxClient, scope: Scope, actor: UserId, snapshot_id: i64) -> CreateEmbed {
+    debug!(snapshot_id, "Restoring dictionary snapshot");
+
+    let diff = match history::restore_snapshot(pool, scope, actor, snapshot_id).await {
+        Ok(Some(diff)) => diff,
+        Ok(None) => return embed::simple_embed(ctx, "エラー", &format!("{}の辞書にID {}のスナップショットはありません", scope.label(), snapshot_id), 0xff0000).await,
+        Err(e) => {
+            error!("Failed to restore dictionary snapshot: {}", e);
+            return embed::simple_embed(ctx, "エラー", &format!("スナップショットの復元に失敗しました: {}", e), 0xff0000).await;
+        }
+    };
+
+    let description = format!(
+        "**辞書:** {}\n**追加:** {}件\n**変更:** {}件\n**削除:** {}件\n\n復元前の辞書もスナップショットとして保存されています",
+        scope.label(), diff.added.len(), diff.changed.len(), diff.removed.len(),
+    );
+    let engine_result = match scope {
+        Scope::Global if !diff.is_empty() => dictionary::sync_to_engine(pool, voicevox_client).await.map(|_| ()),
+        _ => Ok(()),
+    };
+    engine_result_embed(ctx, "スナップショットから復元しました", description, engine_result).await
+}
+
+/// `restore`の`snapshot`の補完候補。(表示名, スナップショットのID)
+///
+/// 補完候補ではタイムスタンプの記法が使えないので、日時の代わりに新しい順のIDを出す
+pub async fn snapshot_choices(pool: &SqlitePool, scope: Scope) -> Result<Vec<(String, i64)>> {
+    let snapshots = history::snapshots(pool, scope, MAX_SNAPSHOT_CHOICES).await?;
+    Ok(snapshots
+        .into_iter()
+        .map(|snapshot| {
+            let name = format!("#{} {} ({}件)", snapshot.id, snapshot.label, snapshot.word_count);
+            (name.chars().take(100).collect(), snapshot.id)
+        })
+        .collect())
+}
+
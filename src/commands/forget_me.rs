@@ -0,0 +1,63 @@
+use crate::database;
+use crate::embed;
+use anyhow::Result;
+use serenity::{
+    builder::{CreateCommand, CreateEmbed, CreateInteractionResponseFollowup},
+    model::application::CommandInteraction,
+    prelude::*,
+};
+use sqlx::SqlitePool;
+use tracing::{error, info};
+
+pub async fn run(ctx: &Context, interaction: &CommandInteraction, pool: &SqlitePool) -> Result<()> {
+    interaction.defer_ephemeral(&ctx.http).await?;
+
+    let response_embed = process_forget_me_command(ctx, interaction, pool).await;
+
+    let builder = CreateInteractionResponseFollowup::new().embed(response_embed).ephemeral(true);
+
+    interaction.create_followup(&ctx.http, builder).await?;
+
+    Ok(())
+}
+
+async fn process_forget_me_command(ctx: &Context, interaction: &CommandInteraction, pool: &SqlitePool) -> CreateEmbed {
+    match database::forget_user(pool, interaction.user.id).await {
+        Ok(removed) => {
+            let total: u64 = removed.iter().map(|(_, count)| count).sum();
+            info!(user_id = %interaction.user.id, total, "Deleted user data");
+
+            let details = removed
+                .iter()
+                .map(|(table, count)| format!("**{}:** {}件", table_label(table), count))
+                .collect::<Vec<_>>()
+                .join("\n");
+            let description = format!(
+                "あなたに関するデータを{}件削除しました\n\n{}\n\n`/optout` の読み上げの停止設定は削除されません\nモデレーターが設定した読み上げルールは削除されません",
+                total, details,
+            );
+            embed::simple_embed(ctx, "データを削除しました", &description, 0x00ff00).await
+        }
+        Err(e) => {
+            error!("Failed to delete user data: {}", e);
+            embed::simple_embed(ctx, "エラー", &format!("データの削除に失敗しました: {}", e), 0xff0000).await
+        }
+    }
+}
+
+fn table_label(table: &str) -> &str {
+    match table {
+        "name_reading" => "名前の読み",
+        "user_preset" => "声のプリセットの選択",
+        "ignore_rule" => "自分で設定した読み上げルール",
+        "ignore_rule_created_by" => "追加した読み上げルールの追加者 (匿名化)",
+        "dictionary_change" => "辞書の変更履歴 (匿名化)",
+        "dictionary_snapshot" => "辞書のスナップショット (匿名化)",
+        _ => table,
+    }
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("forget-me")
+        .description("BOTに保存されているあなたのデータをすべて削除します")
+}
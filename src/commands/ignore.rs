@@ -0,0 +1,274 @@
+use crate::embed;
+use crate::ignore::{self, IgnoreRules, RuleKind};
+use crate::permissions::{self, Permission};
+use anyhow::Result;
+use serenity::{
+    all::{GuildId, UserId},
+    builder::{CreateCommand, CreateCommandOption, CreateEmbed, CreateInteractionResponseFollowup},
+    model::application::{CommandDataOption, CommandDataOptionValue, CommandInteraction, CommandOptionType},
+    prelude::*,
+};
+use sqlx::SqlitePool;
+use tracing::{debug, error};
+
+pub async fn run(ctx: &Context, interaction: &CommandInteraction, pool: &SqlitePool) -> Result<()> {
+    let removed_by = match access(interaction) {
+        Access::Anyone => None,
+        Access::Settings => {
+            if !permissions::require(ctx, pool, interaction, Permission::Settings).await? {
+                return Ok(());
+            }
+            None
+        }
+        Access::OwnRule => match own_rule_remover(pool, interaction).await {
+            Some(user_id) => Some(user_id),
+            None => {
+                if !permissions::require(ctx, pool, interaction, Permission::Settings).await? {
+                    return Ok(());
+                }
+                None
+            }
+        },
+    };
+
+    interaction.defer(&ctx.http).await?;
+
+    let response_embed = process_ignore_command(ctx, interaction, pool, removed_by).await;
+
+    let builder = CreateInteractionResponseFollowup::new().embed(response_embed);
+
+    interaction.create_followup(&ctx.http, builder).await?;
+
+    Ok(())
+}
+
+/// コマンドの実行に必要な権限
+enum Access {
+    /// 誰でも実行できる
+    Anyone,
+    /// 自分自身を読み上げないルールの削除。自分で追加したルールなら権限は要らない
+    OwnRule,
+    /// サーバー設定の権限が必要
+    Settings,
+}
+
+/// 一覧の表示と自分自身を読み上げない設定の追加以外は、サーバー設定の権限が必要
+fn access(interaction: &CommandInteraction) -> Access {
+    let Some(option) = interaction.data.options.first() else {
+        return Access::Anyone;
+    };
+    match option.name.as_str() {
+        "list" => Access::Anyone,
+        "user" => {
+            let Some((action, args)) = subcommand_of_group(option) else {
+                return Access::Settings;
+            };
+            let target = args.iter()
+                .find(|opt| opt.name == "user")
+                .and_then(|opt| opt.value.as_user_id())
+                .unwrap_or(interaction.user.id);
+            if target != interaction.user.id {
+                Access::Settings
+            } else if action == "add" {
+                Access::Anyone
+            } else {
+                Access::OwnRule
+            }
+        }
+        _ => Access::Settings,
+    }
+}
+
+/// 自分自身を読み上げないルールを本人が削除できる場合、削除を本人が追加したルールに限るためのユーザーIDを返す
+///
+/// サーバー設定の権限があるか、他の人が追加したルールの場合は`None`を返す
+async fn own_rule_remover(pool: &SqlitePool, interaction: &CommandInteraction) -> Option<UserId> {
+    let guild_id = interaction.guild_id?;
+    if permissions::has_permission(pool, interaction, Permission::Settings).await.unwrap_or(false) {
+        return None;
+    }
+
+    let user_id = interaction.user.id;
+    match ignore::rule_creator(pool, guild_id, RuleKind::User, &user_id.to_string()).await {
+        // 登録されていなければ削除しても何も起きないので、そのまま「登録されていません」と返す
+        Ok(None) => Some(user_id),
+        Ok(Some(created_by)) if created_by == Some(user_id) => Some(user_id),
+        Ok(Some(_)) => None,
+        Err(e) => {
+            error!("Failed to fetch ignore rule: {}", e);
+            None
+        }
+    }
+}
+
+async fn process_ignore_command(ctx: &Context, interaction: &CommandInteraction, pool: &SqlitePool, removed_by: Option<UserId>) -> CreateEmbed {
+    let guild_id = match interaction.guild_id {
+        Some(guild_id) => guild_id,
+        None => {
+            return embed::simple_embed(ctx, "エラー", "このコマンドはギルド内でのみ使えます", 0xff0000).await;
+        }
+    };
+
+    let Some(option) = interaction.data.options.first() else {
+        return embed::simple_embed(ctx, "エラー", "サブコマンドを指定してください。", 0xff0000).await;
+    };
+
+    if option.name == "list" {
+        return list_rules(ctx, pool, guild_id).await;
+    }
+
+    let Ok(kind) = option.name.parse::<RuleKind>() else {
+        return embed::simple_embed(ctx, "エラー", &format!("「{}」は不明なコマンドです。", option.name), 0xff0000).await;
+    };
+    let Some((action, args)) = subcommand_of_group(option) else {
+        return embed::simple_embed(ctx, "エラー", "サブコマンドの引数を正しく取得できませんでした。", 0xff0000).await;
+    };
+
+    let value = match kind {
+        RuleKind::Prefix => args.iter()
+            .find(|opt| opt.name == "prefix")
+            .and_then(|opt| opt.value.as_str())
+            .map(|prefix| prefix.to_string()),
+        // ユーザーを省略した場合は自分自身を対象にする
+        RuleKind::User => Some(
+            args.iter()
+                .find(|opt| opt.name == "user")
+                .and_then(|opt| opt.value.as_user_id())
+                .unwrap_or(interaction.user.id)
+                .to_string(),
+        ),
+        RuleKind::Role => args.iter()
+            .find(|opt| opt.name == "role")
+            .and_then(|opt| opt.value.as_role_id())
+            .map(|role_id| role_id.to_string()),
+        RuleKind::Bot => args.iter()
+            .find(|opt| opt.name == "bot")
+            .and_then(|opt| opt.value.as_user_id())
+            .map(|user_id| user_id.to_string()),
+        RuleKind::Webhook => args.iter()
+            .find(|opt| opt.name == "webhook_id")
+            .and_then(|opt| opt.value.as_str())
+            .filter(|id| id.parse::<u64>().is_ok())
+            .map(|id| id.to_string()),
+    };
+
+    let Some(value) = value else {
+        return embed::simple_embed(ctx, "エラー", "対象の指定が正しくありません", 0xff0000).await;
+    };
+
+    debug!(%kind, action, value, "Updating ignore rule");
+
+    let result = match action {
+        "add" => ignore::add_rule(pool, guild_id, kind, &value, interaction.user.id).await,
+        "remove" => ignore::remove_rule(pool, guild_id, kind, &value, removed_by).await,
+        _ => {
+            return embed::simple_embed(ctx, "エラー", &format!("「{}」は不明なコマンドです。", action), 0xff0000).await;
+        }
+    };
+
+    let target = describe(kind, &value);
+    match (action, result) {
+        ("add", Ok(true)) => embed::simple_embed(ctx, "ルールを追加しました", &format!("{}を追加しました", target), 0x00ff00).await,
+        ("add", Ok(false)) => embed::simple_embed(ctx, "エラー", &format!("{}は既に登録されています", target), 0xff0000).await,
+        (_, Ok(true)) => embed::simple_embed(ctx, "ルールを削除しました", &format!("{}を削除しました", target), 0x00ff00).await,
+        (_, Ok(false)) => embed::simple_embed(ctx, "エラー", &format!("{}は登録されていません", target), 0xff0000).await,
+        (_, Err(e)) => {
+            error!("Failed to update ignore rule: {}", e);
+            embed::simple_embed(ctx, "エラー", &format!("ルールの変更に失敗しました: {}", e), 0xff0000).await
+        }
+    }
+}
+
+fn subcommand_of_group(option: &CommandDataOption) -> Option<(&str, &[CommandDataOption])> {
+    let CommandDataOptionValue::SubCommandGroup(subcommands) = &option.value else {
+        return None;
+    };
+    let subcommand = subcommands.first()?;
+    let CommandDataOptionValue::SubCommand(args) = &subcommand.value else {
+        return None;
+    };
+    Some((subcommand.name.as_str(), args.as_slice()))
+}
+
+fn describe(kind: RuleKind, value: &str) -> String {
+    match kind {
+        RuleKind::Prefix => format!("読み上げないプレフィックス `{}`", value),
+        RuleKind::User => format!("読み上げないユーザー <@{}>", value),
+        RuleKind::Role => format!("読み上げないロール <@&{}>", value),
+        RuleKind::Bot => format!("読み上げるBOT <@{}>", value),
+        RuleKind::Webhook => format!("読み上げるWebhook `{}`", value),
+    }
+}
+
+async fn list_rules(ctx: &Context, pool: &SqlitePool, guild_id: GuildId) -> CreateEmbed {
+    debug!("Listing ignore rules");
+
+    let rules = match IgnoreRules::fetch(pool, guild_id).await {
+        Ok(rules) => rules,
+        Err(e) => {
+            error!("Failed to fetch ignore rules: {}", e);
+            return embed::simple_embed(ctx, "エラー", &format!("ルールの取得に失敗しました: {}", e), 0xff0000).await;
+        }
+    };
+
+    fn join_or_none(items: Vec<String>) -> String {
+        if items.is_empty() { "なし".to_string() } else { items.join(", ") }
+    }
+
+    let description = format!(
+        "**読み上げないプレフィックス:** {}\n**読み上げないユーザー:** {}\n**読み上げないロール:** {}\n**読み上げるBOT:** {}\n**読み上げるWebhook:** {}",
+        join_or_none(rules.prefixes.iter().map(|prefix| format!("`{}`", prefix)).collect()),
+        join_or_none(rules.users.iter().map(|id| format!("<@{}>", id)).collect()),
+        join_or_none(rules.roles.iter().map(|id| format!("<@&{}>", id)).collect()),
+        join_or_none(rules.bots.iter().map(|id| format!("<@{}>", id)).collect()),
+        join_or_none(rules.webhooks.iter().map(|id| format!("`{}`", id)).collect()),
+    );
+
+    embed::simple_embed(ctx, "読み上げルール", &description, 0x0099ff).await
+}
+
+fn rule_group(name: &str, description: &str, add_description: &str, remove_description: &str, target: CreateCommandOption) -> CreateCommandOption {
+    CreateCommandOption::new(CommandOptionType::SubCommandGroup, name, description)
+        .add_sub_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "add", add_description)
+                .add_sub_option(target.clone())
+        )
+        .add_sub_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "remove", remove_description)
+                .add_sub_option(target)
+        )
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("ignore")
+        .description("読み上げるメッセージのルールを管理します")
+        .add_option(rule_group(
+            "prefix", "読み上げないプレフィックスを管理します", "読み上げないプレフィックスを追加します", "読み上げないプレフィックスを削除します",
+            CreateCommandOption::new(CommandOptionType::String, "prefix", "このプレフィックスで始まるメッセージは読み上げません")
+                .required(true)
+                .max_length(10),
+        ))
+        .add_option(rule_group(
+            "user", "読み上げないユーザーを管理します", "読み上げないユーザーを追加します", "読み上げないユーザーを削除します",
+            CreateCommandOption::new(CommandOptionType::User, "user", "対象のユーザー (省略すると自分)"),
+        ))
+        .add_option(rule_group(
+            "role", "読み上げないロールを管理します", "読み上げないロールを追加します", "読み上げないロールを削除します",
+            CreateCommandOption::new(CommandOptionType::Role, "role", "対象のロール")
+                .required(true),
+        ))
+        .add_option(rule_group(
+            "bot", "読み上げるBOTを管理します", "BOTのメッセージを読み上げるようにします", "BOTのメッセージを読み上げないようにします",
+            CreateCommandOption::new(CommandOptionType::User, "bot", "対象のBOT")
+                .required(true),
+        ))
+        .add_option(rule_group(
+            "webhook", "読み上げるWebhookを管理します", "Webhookのメッセージを読み上げるようにします", "Webhookのメッセージを読み上げないようにします",
+            CreateCommandOption::new(CommandOptionType::String, "webhook_id", "対象のWebhookのID")
+                .required(true)
+                .max_length(20),
+        ))
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "list", "読み上げルールの一覧を表示します")
+        )
+}
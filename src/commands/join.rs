@@ -1,16 +1,16 @@
 use crate::embed;
 use crate::voice::voicevox::client::Client as VoicevoxClient;
+use crate::voice::voicevox::dictionary::DictionaryIndex;
 use crate::voice::manager::VoiceManager;
 use crate::voice::playback;
 use anyhow::Result;
 use serenity::{
-    builder::{CreateCommand, CreateInteractionResponse, CreateInteractionResponseFollowup},
+    builder::{CreateCommand, CreateInteractionResponseFollowup},
     model::application::CommandInteraction,
 };
-use sqlx::SqlitePool;
-use tracing::{info, error, instrument, debug};
+use tracing::{error, debug};
 
-pub async fn run(ctx: &serenity::all::Context, interaction: &CommandInteraction, voicevox_client: &VoicevoxClient, voice_manager: &VoiceManager) -> Result<()> {
+pub async fn run(ctx: &serenity::all::Context, interaction: &CommandInteraction, voicevox_client: &VoicevoxClient, voice_manager: &VoiceManager, dictionary_index: &DictionaryIndex) -> Result<()> {
     interaction.defer(&ctx.http).await?;
     let (guild_id, voice_channel_id) = {
         let guild_id = match interaction.guild_id {
@@ -50,14 +50,14 @@ pub async fn run(ctx: &serenity::all::Context, interaction: &CommandInteraction,
 
     match voice_manager.connect(ctx, guild_id, interaction.channel_id, voice_channel_id).await {
         Ok(_) => {
-            let response_content = embed::simple_embed(&ctx, "接続しました", &format!("{} に接続しました！", voice_channel_url), 0x00ff00, ).await;
+            let response_content = embed::simple_embed(ctx, "接続しました", &format!("{} に接続しました！", voice_channel_url), 0x00ff00, ).await;
 
             let response = CreateInteractionResponseFollowup::new().embed(response_content);
             interaction.create_followup(ctx, response).await?;
 
             // 音声再生
             if let Err(e) =
-                playback::play(&ctx, &voicevox_client, guild_id, "接続しました".to_string()).await
+                playback::play(ctx, voicevox_client, voice_manager, dictionary_index, guild_id, None, "接続しました".to_string()).await
             {
                 error!("Failed to play audio: {}", e);
             } else {
@@ -70,7 +70,7 @@ pub async fn run(ctx: &serenity::all::Context, interaction: &CommandInteraction,
             error!("Failed to connect to voice channel: {}", e);
 
             let response_content = embed::simple_embed(
-                &ctx,
+                ctx,
                 "接続に失敗しました",
                 &format!("VCへの接続に失敗しました:\n{}", e),
                 0xff0000,
@@ -1,16 +1,19 @@
 use crate::embed;
-use crate::voice::voicevox::client::Client as VoicevoxClient;
+use crate::permissions::{self, Permission};
 use crate::voice::manager::VoiceManager;
-use crate::voice::playback;
 use anyhow::Result;
 use serenity::{
-    builder::{CreateCommand, CreateInteractionResponse, CreateInteractionResponseFollowup},
+    builder::{CreateCommand, CreateInteractionResponseFollowup},
     model::application::CommandInteraction,
 };
 use sqlx::SqlitePool;
-use tracing::{info, error, instrument, debug};
+use tracing::error;
+
+pub async fn run(ctx: &serenity::all::Context, interaction: &CommandInteraction, pool: &SqlitePool, voice_manager: &VoiceManager) -> Result<()> {
+    if !permissions::require(ctx, pool, interaction, Permission::Disconnect).await? {
+        return Ok(());
+    }
 
-pub async fn run(ctx: &serenity::all::Context, interaction: &CommandInteraction, voice_manager: &VoiceManager) -> Result<()> {
     interaction.defer(&ctx.http).await?;
     let guild_id = match interaction.guild_id {
         Some(guild_id) => guild_id,
@@ -23,7 +26,7 @@ pub async fn run(ctx: &serenity::all::Context, interaction: &CommandInteraction,
 
     match voice_manager.disconnect(ctx, guild_id, interaction.channel_id).await {
         Ok(_) => {
-            let response_content = embed::simple_embed(&ctx, "切断しました", "ご利用していただきありがとうございました", 0xff0000).await;
+            let response_content = embed::simple_embed(ctx, "切断しました", "ご利用していただきありがとうございました", 0xff0000).await;
 
             let response = CreateInteractionResponseFollowup::new().embed(response_content);
             interaction.create_followup(ctx, response).await?;
@@ -34,7 +37,7 @@ pub async fn run(ctx: &serenity::all::Context, interaction: &CommandInteraction,
             error!("Failed to connect to voice channel: {}", e);
 
             let response_content = embed::simple_embed(
-                &ctx,
+                ctx,
                 "切断に失敗しました",
                 &format!("VCからの切断に失敗しました:\n{}", e),
                 0xff0000,
@@ -50,5 +53,7 @@ pub async fn run(ctx: &serenity::all::Context, interaction: &CommandInteraction,
 }
 
 pub fn register() -> CreateCommand{
-    CreateCommand::new("leave").description("参加しているVCから切断します")
+    // `default_member_permissions`で隠すとロールに与えた権限が使えないので、権限は`run`で確認する
+    CreateCommand::new("leave")
+        .description("参加しているVCから切断します")
 }
\ No newline at end of file
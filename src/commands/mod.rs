@@ -1,3 +1,15 @@
 pub mod dictionary;
+pub mod dictionary_accent;
+pub mod dictionary_convert;
+pub mod dictionary_form;
+pub mod dictionary_history;
+pub mod forget_me;
+pub mod ignore;
 pub mod join;
 pub mod leave;
+pub mod name;
+pub mod optout;
+pub mod preset;
+pub mod reading;
+pub mod settings;
+pub mod status;
\ No newline at end of file
@@ -0,0 +1,98 @@
+use crate::database;
+use crate::embed;
+use anyhow::Result;
+use serenity::{
+    builder::{CreateCommand, CreateCommandOption, CreateEmbed, CreateInteractionResponseFollowup},
+    model::application::{CommandDataOptionValue, CommandInteraction, CommandOptionType},
+    prelude::*,
+};
+use sqlx::SqlitePool;
+use tracing::{debug, error};
+
+pub async fn run(ctx: &Context, interaction: &CommandInteraction, pool: &SqlitePool) -> Result<()> {
+    interaction.defer_ephemeral(&ctx.http).await?;
+
+    let response_embed = process_name_command(ctx, interaction, pool).await;
+
+    let builder = CreateInteractionResponseFollowup::new().embed(response_embed).ephemeral(true);
+
+    interaction.create_followup(&ctx.http, builder).await?;
+
+    Ok(())
+}
+
+async fn process_name_command(ctx: &Context, interaction: &CommandInteraction, pool: &SqlitePool) -> CreateEmbed {
+    let guild_id = match interaction.guild_id {
+        Some(guild_id) => guild_id,
+        None => {
+            return embed::simple_embed(ctx, "エラー", "このコマンドはギルド内でのみ使えます", 0xff0000).await;
+        }
+    };
+    let user_id = interaction.user.id;
+
+    let Some(subcommand) = interaction.data.options.first() else {
+        return embed::simple_embed(ctx, "エラー", "サブコマンドを指定してください。", 0xff0000).await;
+    };
+    let CommandDataOptionValue::SubCommand(args) = &subcommand.value else {
+        return embed::simple_embed(ctx, "エラー", "サブコマンドの引数を正しく取得できませんでした。", 0xff0000).await;
+    };
+
+    match subcommand.name.as_str() {
+        "set" => {
+            let Some(reading) = args.iter().find(|opt| opt.name == "reading").and_then(|opt| opt.value.as_str()) else {
+                return embed::simple_embed(ctx, "エラー", "'reading' オプションが見つかりません。", 0xff0000).await;
+            };
+            debug!("Setting name reading: {}", reading);
+
+            match database::set_name_reading(pool, guild_id, user_id, reading.trim()).await {
+                Ok(()) => embed::simple_embed(ctx, "名前の読みを登録しました", &format!("**読み:** {}", reading.trim()), 0x00ff00).await,
+                Err(e) => {
+                    error!("Failed to set name reading: {}", e);
+                    embed::simple_embed(ctx, "エラー", &format!("名前の読みの登録に失敗しました: {}", e), 0xff0000).await
+                }
+            }
+        }
+        "reset" => {
+            debug!("Deleting name reading");
+
+            match database::delete_name_reading(pool, guild_id, user_id).await {
+                Ok(true) => embed::simple_embed(ctx, "名前の読みを削除しました", "表示名をそのまま読み上げます", 0x00ff00).await,
+                Ok(false) => embed::simple_embed(ctx, "エラー", "名前の読みは登録されていません", 0xff0000).await,
+                Err(e) => {
+                    error!("Failed to delete name reading: {}", e);
+                    embed::simple_embed(ctx, "エラー", &format!("名前の読みの削除に失敗しました: {}", e), 0xff0000).await
+                }
+            }
+        }
+        "show" => {
+            match database::fetch_name_reading(pool, guild_id, user_id).await {
+                Ok(Some(reading)) => embed::simple_embed(ctx, "名前の読み", &format!("**読み:** {}", reading), 0x0099ff).await,
+                Ok(None) => embed::simple_embed(ctx, "名前の読み", "名前の読みは登録されていません", 0x0099ff).await,
+                Err(e) => {
+                    error!("Failed to fetch name reading: {}", e);
+                    embed::simple_embed(ctx, "エラー", &format!("名前の読みの取得に失敗しました: {}", e), 0xff0000).await
+                }
+            }
+        }
+        _ => embed::simple_embed(ctx, "エラー", &format!("「{}」は不明なコマンドです。", subcommand.name), 0xff0000).await,
+    }
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("name")
+        .description("読み上げ時の自分の名前の読みを設定します")
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "set", "名前の読みを登録します")
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::String, "reading", "名前の読み方")
+                        .required(true)
+                        .max_length(50)
+                )
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "reset", "登録した名前の読みを削除します")
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "show", "登録した名前の読みを表示します")
+        )
+}
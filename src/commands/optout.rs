@@ -0,0 +1,56 @@
+use crate::database;
+use crate::embed;
+use anyhow::Result;
+use serenity::{
+    builder::{CreateCommand, CreateCommandOption, CreateEmbed, CreateInteractionResponseFollowup},
+    model::application::{CommandInteraction, CommandOptionType},
+    prelude::*,
+};
+use sqlx::SqlitePool;
+use tracing::{debug, error};
+
+pub async fn run(ctx: &Context, interaction: &CommandInteraction, pool: &SqlitePool) -> Result<()> {
+    interaction.defer_ephemeral(&ctx.http).await?;
+
+    let response_embed = process_optout_command(ctx, interaction, pool).await;
+
+    let builder = CreateInteractionResponseFollowup::new().embed(response_embed).ephemeral(true);
+
+    interaction.create_followup(&ctx.http, builder).await?;
+
+    Ok(())
+}
+
+async fn process_optout_command(ctx: &Context, interaction: &CommandInteraction, pool: &SqlitePool) -> CreateEmbed {
+    let undo = interaction.data.options
+        .iter()
+        .find(|opt| opt.name == "undo")
+        .and_then(|opt| opt.value.as_bool())
+        .unwrap_or(false);
+
+    debug!(undo, "Updating opt-out state");
+
+    match database::set_opted_out(pool, interaction.user.id, !undo).await {
+        Ok(changed) => {
+            let (title, description) = match (undo, changed) {
+                (false, true) => ("読み上げを停止しました", "すべてのサーバーで、あなたのメッセージと名前を読み上げません\n再開するには `/optout undo:True` を実行してください"),
+                (false, false) => ("読み上げは停止済みです", "すでにあなたのメッセージと名前は読み上げない設定になっています"),
+                (true, true) => ("読み上げを再開しました", "あなたのメッセージと名前を再び読み上げます"),
+                (true, false) => ("読み上げは停止されていません", "あなたのメッセージは読み上げの対象です"),
+            };
+            embed::simple_embed(ctx, title, description, 0x00ff00).await
+        }
+        Err(e) => {
+            error!("Failed to update opt-out state: {}", e);
+            embed::simple_embed(ctx, "エラー", &format!("設定の変更に失敗しました: {}", e), 0xff0000).await
+        }
+    }
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("optout")
+        .description("すべてのサーバーで自分のメッセージと名前を読み上げないようにします")
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::Boolean, "undo", "読み上げを再開する")
+        )
+}
@@ -0,0 +1,359 @@
+use crate::voice::voicevox::client::{Client as VoicevoxClient, Speaker};
+use crate::voice::voicevox::preset::{self, VoicePreset};
+use crate::voice::voicevox::resilience::EnginesPending;
+use crate::embed;
+use crate::permissions::{self, Permission};
+use anyhow::Result;
+use serenity::{
+    all::GuildId,
+    builder::{CreateAutocompleteResponse, CreateCommand, CreateCommandOption, CreateEmbed, CreateInteractionResponse, CreateInteractionResponseFollowup},
+    model::application::{CommandDataOption, CommandDataOptionValue, CommandInteraction, CommandOptionType},
+    prelude::*,
+};
+use sqlx::SqlitePool;
+use tracing::{debug, error, info, warn};
+
+/// Discordが受け付ける補完候補の最大数
+const MAX_AUTOCOMPLETE_CHOICES: usize = 25;
+const MAX_NAME_LENGTH: u16 = 32;
+
+pub async fn run(ctx: &Context, interaction: &CommandInteraction, pool: &SqlitePool, voicevox_client: &VoicevoxClient) -> Result<()> {
+    let subcommand_name = interaction.data.options.first().map(|opt| opt.name.as_str());
+
+    if matches!(subcommand_name, Some("add" | "edit" | "delete"))
+        && !permissions::require(ctx, pool, interaction, Permission::Settings).await?
+    {
+        return Ok(());
+    }
+
+    // 自分の声の設定は本人にだけ表示する
+    let ephemeral = matches!(subcommand_name, Some("use" | "reset"));
+    if ephemeral {
+        interaction.defer_ephemeral(&ctx.http).await?;
+    } else {
+        interaction.defer(&ctx.http).await?;
+    }
+
+    let response_embed = process_preset_command(ctx, interaction, pool, voicevox_client).await;
+
+    let builder = CreateInteractionResponseFollowup::new().embed(response_embed).ephemeral(ephemeral);
+
+    interaction.create_followup(&ctx.http, builder).await?;
+
+    Ok(())
+}
+
+/// `name`にはこのサーバーのプリセットを、`style`にはエンジンの話者のスタイルを補完する
+pub async fn autocomplete(ctx: &Context, interaction: &CommandInteraction, pool: &SqlitePool, voicevox_client: &VoicevoxClient) -> Result<()> {
+    let (Some(focused), Some(guild_id)) = (interaction.data.autocomplete(), interaction.guild_id) else {
+        return Ok(());
+    };
+    let partial = focused.value.trim().to_lowercase();
+
+    let response = match focused.name {
+        "name" => preset::fetch_all(pool, guild_id)
+            .await?
+            .into_iter()
+            .filter(|preset| preset.name.to_lowercase().contains(&partial))
+            .take(MAX_AUTOCOMPLETE_CHOICES)
+            .fold(CreateAutocompleteResponse::new(), |response, preset| response.add_string_choice(preset.name.clone(), preset.name)),
+        "style" => style_choices(&voicevox_client.speakers().await?)
+            .into_iter()
+            .filter(|(label, _)| label.to_lowercase().contains(&partial))
+            .take(MAX_AUTOCOMPLETE_CHOICES)
+            .fold(CreateAutocompleteResponse::new(), |response, (label, style_id)| response.add_int_choice(label, style_id)),
+        _ => CreateAutocompleteResponse::new(),
+    };
+    interaction.create_response(&ctx.http, CreateInteractionResponse::Autocomplete(response)).await?;
+    Ok(())
+}
+
+/// (話者とスタイルの表示名, スタイルID)。合成の話者IDに使えるものだけを返す
+fn style_choices(speakers: &[Speaker]) -> Vec<(String, i64)> {
+    speakers
+        .iter()
+        .flat_map(|speaker| speaker.styles.iter().map(move |style| (format!("{}（{}）", speaker.name, style.name), style.id)))
+        .filter(|(_, style_id)| u8::try_from(*style_id).is_ok())
+        .collect()
+}
+
+async fn process_preset_command(ctx: &Context, interaction: &CommandInteraction, pool: &SqlitePool, voicevox_client: &VoicevoxClient) -> CreateEmbed {
+    let Some(guild_id) = interaction.guild_id else {
+        return embed::simple_embed(ctx, "エラー", "このコマンドはギルド内でのみ使えます", 0xff0000).await;
+    };
+    let Some(subcommand) = interaction.data.options.first() else {
+        return embed::simple_embed(ctx, "エラー", "サブコマンドを指定してください。", 0xff0000).await;
+    };
+    let args: &[CommandDataOption] = match &subcommand.value {
+        CommandDataOptionValue::SubCommand(args) => args,
+        _ => &[],
+    };
+    let name = args.iter().find(|opt| opt.name == "name").and_then(|opt| opt.value.as_str()).map(str::trim);
+
+    match (subcommand.name.as_str(), name) {
+        ("add", Some(name)) => add_preset(ctx, pool, voicevox_client, guild_id, name, args).await,
+        ("edit", Some(name)) => edit_preset(ctx, pool, voicevox_client, guild_id, name, args).await,
+        ("delete", Some(name)) => delete_preset(ctx, pool, voicevox_client, guild_id, name).await,
+        ("use", Some(name)) => use_preset(ctx, interaction, pool, voicevox_client, guild_id, name).await,
+        ("reset", _) => match preset::clear_selection(pool, guild_id, interaction.user.id).await {
+            Ok(true) => embed::simple_embed(ctx, "声を既定に戻しました", "既定の声で読み上げます", 0x00ff00).await,
+            Ok(false) => embed::simple_embed(ctx, "エラー", "プリセットは選ばれていません", 0xff0000).await,
+            Err(e) => {
+                error!("Failed to clear preset selection: {}", e);
+                embed::simple_embed(ctx, "エラー", &format!("プリセットの解除に失敗しました: {}", e), 0xff0000).await
+            }
+        },
+        ("list", _) => list_presets(ctx, interaction, pool, voicevox_client, guild_id).await,
+        ("add" | "edit" | "delete" | "use", None) => embed::simple_embed(ctx, "エラー", "'name' オプションが見つかりません。", 0xff0000).await,
+        _ => embed::simple_embed(ctx, "エラー", &format!("「{}」は不明なコマンドです。", subcommand.name), 0xff0000).await,
+    }
+}
+
+async fn add_preset(ctx: &Context, pool: &SqlitePool, voicevox_client: &VoicevoxClient, guild_id: GuildId, name: &str, args: &[CommandDataOption]) -> CreateEmbed {
+    debug!("Adding voice preset: {}", name);
+
+    if name.is_empty() || name.contains('/') {
+        return embed::simple_embed(ctx, "エラー", "プリセット名は空にできず、`/`は使えません", 0xff0000).await;
+    }
+    let Some(style_id) = args.iter().find(|opt| opt.name == "style").and_then(|opt| opt.value.as_i64()) else {
+        return embed::simple_embed(ctx, "エラー", "'style' オプションが見つかりません。", 0xff0000).await;
+    };
+
+    let mut voice_preset = VoicePreset::new(name, style_id);
+    apply_args(&mut voice_preset, args);
+
+    let speakers = match voicevox_client.speakers().await {
+        Ok(speakers) => speakers,
+        Err(e) => return embed::simple_embed(ctx, "エラー", &format!("話者の一覧の取得に失敗しました: {}", e), 0xff0000).await,
+    };
+    let Some(speaker_uuid) = speaker_uuid(&speakers, style_id) else {
+        return embed::simple_embed(ctx, "エラー", &format!("スタイルID {} の話者が見つかりません", style_id), 0xff0000).await;
+    };
+
+    match preset::insert(pool, guild_id, &voice_preset).await {
+        Ok(true) => {}
+        Ok(false) => return embed::simple_embed(ctx, "エラー", "既に同じ名前のプリセットが存在します。`/preset edit` で編集してください", 0xff0000).await,
+        Err(e) => return embed::simple_embed(ctx, "エラー", &format!("プリセットの追加に失敗しました: {}", e), 0xff0000).await,
+    }
+
+    let engine_result = voicevox_client.put_preset(&voice_preset.to_engine_json(guild_id, speaker_uuid)).await;
+    engine_result_embed(ctx, "プリセットを追加しました", describe_preset(&voice_preset, &speakers), engine_result).await
+}
+
+async fn edit_preset(ctx: &Context, pool: &SqlitePool, voicevox_client: &VoicevoxClient, guild_id: GuildId, name: &str, args: &[CommandDataOption]) -> CreateEmbed {
+    debug!("Editing voice preset: {}", name);
+
+    let mut voice_preset = match preset::fetch(pool, guild_id, name).await {
+        Ok(Some(voice_preset)) => voice_preset,
+        Ok(None) => return embed::simple_embed(ctx, "エラー", &format!("プリセット「{}」は存在しません", name), 0xff0000).await,
+        Err(e) => return embed::simple_embed(ctx, "エラー", &format!("プリセットの取得に失敗しました: {}", e), 0xff0000).await,
+    };
+    if let Some(style_id) = args.iter().find(|opt| opt.name == "style").and_then(|opt| opt.value.as_i64()) {
+        voice_preset.style_id = style_id;
+    }
+    apply_args(&mut voice_preset, args);
+
+    let speakers = match voicevox_client.speakers().await {
+        Ok(speakers) => speakers,
+        Err(e) => return embed::simple_embed(ctx, "エラー", &format!("話者の一覧の取得に失敗しました: {}", e), 0xff0000).await,
+    };
+    let Some(speaker_uuid) = speaker_uuid(&speakers, voice_preset.style_id) else {
+        return embed::simple_embed(ctx, "エラー", &format!("スタイルID {} の話者が見つかりません", voice_preset.style_id), 0xff0000).await;
+    };
+
+    match preset::update(pool, guild_id, &voice_preset).await {
+        Ok(true) => {}
+        Ok(false) => return embed::simple_embed(ctx, "エラー", &format!("プリセット「{}」は存在しません", name), 0xff0000).await,
+        Err(e) => return embed::simple_embed(ctx, "エラー", &format!("プリセットの更新に失敗しました: {}", e), 0xff0000).await,
+    }
+
+    let engine_result = voicevox_client.put_preset(&voice_preset.to_engine_json(guild_id, speaker_uuid)).await;
+    engine_result_embed(ctx, "プリセットを更新しました", describe_preset(&voice_preset, &speakers), engine_result).await
+}
+
+async fn delete_preset(ctx: &Context, pool: &SqlitePool, voicevox_client: &VoicevoxClient, guild_id: GuildId, name: &str) -> CreateEmbed {
+    debug!("Deleting voice preset: {}", name);
+
+    match preset::delete(pool, guild_id, name).await {
+        Ok(true) => {}
+        Ok(false) => return embed::simple_embed(ctx, "エラー", &format!("プリセット「{}」は存在しません", name), 0xff0000).await,
+        Err(e) => return embed::simple_embed(ctx, "エラー", &format!("プリセットの削除に失敗しました: {}", e), 0xff0000).await,
+    }
+
+    let engine_result = voicevox_client.delete_preset(&VoicePreset::engine_name(guild_id, name)).await;
+    let description = format!("**プリセット:** {}\n選んでいたユーザーは既定の声に戻ります", name);
+    engine_result_embed(ctx, "プリセットを削除しました", description, engine_result).await
+}
+
+async fn use_preset(ctx: &Context, interaction: &CommandInteraction, pool: &SqlitePool, voicevox_client: &VoicevoxClient, guild_id: GuildId, name: &str) -> CreateEmbed {
+    debug!("Selecting voice preset: {}", name);
+
+    let voice_preset = match preset::fetch(pool, guild_id, name).await {
+        Ok(Some(voice_preset)) => voice_preset,
+        Ok(None) => return embed::simple_embed(ctx, "エラー", &format!("プリセット「{}」は存在しません。`/preset list` で確認してください", name), 0xff0000).await,
+        Err(e) => return embed::simple_embed(ctx, "エラー", &format!("プリセットの取得に失敗しました: {}", e), 0xff0000).await,
+    };
+
+    if let Err(e) = preset::select(pool, guild_id, interaction.user.id, name).await {
+        error!("Failed to select preset: {}", e);
+        return embed::simple_embed(ctx, "エラー", &format!("プリセットの設定に失敗しました: {}", e), 0xff0000).await;
+    }
+
+    // 最初の読み上げでモデルの読み込みを待たないよう、先に読み込んでおく
+    if let Ok(speaker) = u8::try_from(voice_preset.style_id) {
+        voicevox_client.warm_up(&[speaker]);
+    }
+
+    let speakers = voicevox_client.speakers().await.unwrap_or_else(|e| {
+        warn!("Failed to fetch speakers: {}", e);
+        Vec::new()
+    });
+    let description = describe_preset(&voice_preset, &speakers);
+    embed::simple_embed(ctx, "声のプリセットを設定しました", &description, 0x00ff00).await
+}
+
+async fn list_presets(ctx: &Context, interaction: &CommandInteraction, pool: &SqlitePool, voicevox_client: &VoicevoxClient, guild_id: GuildId) -> CreateEmbed {
+    let presets = match preset::fetch_all(pool, guild_id).await {
+        Ok(presets) => presets,
+        Err(e) => return embed::simple_embed(ctx, "エラー", &format!("プリセットの取得に失敗しました: {}", e), 0xff0000).await,
+    };
+    if presets.is_empty() {
+        return embed::simple_embed(ctx, "プリセット一覧", "プリセットはまだありません。`/preset add` で追加できます", 0x0099ff).await;
+    }
+
+    let speakers = voicevox_client.speakers().await.unwrap_or_else(|e| {
+        warn!("Failed to fetch speakers: {}", e);
+        Vec::new()
+    });
+    let selected = preset::selected(pool, guild_id, interaction.user.id).await.ok().flatten().map(|voice_preset| voice_preset.name);
+
+    let description = presets
+        .iter()
+        .map(|voice_preset| {
+            let mark = if selected.as_deref() == Some(voice_preset.name.as_str()) { " (使用中)" } else { "" };
+            format!("{}{}", describe_preset(voice_preset, &speakers), mark)
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    embed::simple_embed(ctx, "プリセット一覧", &description, 0x0099ff).await
+}
+
+/// エンジンのプリセットに必要な、スタイルを持つ話者のUUID
+fn speaker_uuid(speakers: &[Speaker], style_id: i64) -> Option<&str> {
+    speakers
+        .iter()
+        .find(|speaker| speaker.styles.iter().any(|style| style.id == style_id))
+        .map(|speaker| speaker.speaker_uuid.as_str())
+}
+
+/// 指定されたオプションだけをプリセットに反映する
+fn apply_args(voice_preset: &mut VoicePreset, args: &[CommandDataOption]) {
+    for arg in args {
+        let Some(value) = arg.value.as_f64() else {
+            continue;
+        };
+        match arg.name.as_str() {
+            "speed" => voice_preset.speed_scale = value,
+            "pitch" => voice_preset.pitch_scale = value,
+            "intonation" => voice_preset.intonation_scale = value,
+            "volume" => voice_preset.volume_scale = value,
+            "pre_phoneme" => voice_preset.pre_phoneme_length = value,
+            "post_phoneme" => voice_preset.post_phoneme_length = value,
+            "pause" => voice_preset.pause_length_scale = value,
+            _ => {}
+        }
+    }
+}
+
+fn describe_preset(voice_preset: &VoicePreset, speakers: &[Speaker]) -> String {
+    let style = style_choices(speakers)
+        .into_iter()
+        .find(|(_, style_id)| *style_id == voice_preset.style_id)
+        .map_or_else(|| format!("スタイルID {}", voice_preset.style_id), |(label, _)| label);
+    format!(
+        "**{}**: {}\n話速 {:.2} / 音高 {:.2} / 抑揚 {:.2} / 音量 {:.2} / 前後の無音 {:.2}秒・{:.2}秒 / 間 {:.2}",
+        voice_preset.name,
+        style,
+        voice_preset.speed_scale,
+        voice_preset.pitch_scale,
+        voice_preset.intonation_scale,
+        voice_preset.volume_scale,
+        voice_preset.pre_phoneme_length,
+        voice_preset.post_phoneme_length,
+        voice_preset.pause_length_scale,
+    )
+}
+
+/// データベースには保存できたがエンジンへの反映に失敗した場合は、起動時の同期で反映されることを伝える
+async fn engine_result_embed(ctx: &Context, title: &str, description: String, engine_result: Result<()>) -> CreateEmbed {
+    match engine_result {
+        Ok(()) => embed::simple_embed(ctx, title, &description, 0x00ff00).await,
+        Err(e) if e.is::<EnginesPending>() => {
+            info!("{}", e);
+            let description = format!("{}\n\n停止中のエンジンには、復帰したときに反映します", description);
+            embed::simple_embed(ctx, title, &description, 0x00ff00).await
+        }
+        Err(e) => {
+            warn!("Failed to apply preset change to engine: {}", e);
+            let description = format!("{}\n\nエンジンへの反映に失敗しました。エンジンが応答するようになったら再度反映します: {}", description, e);
+            embed::simple_embed(ctx, title, &description, 0xffaa00).await
+        }
+    }
+}
+
+fn name_option(description: &str) -> CreateCommandOption {
+    CreateCommandOption::new(CommandOptionType::String, "name", description)
+        .required(true)
+        .max_length(MAX_NAME_LENGTH)
+}
+
+/// 声の調整のオプション。範囲はVOICEVOXエディターのスライダーと揃える
+fn voice_options(subcommand: CreateCommandOption) -> CreateCommandOption {
+    let number = |name: &str, description: &str, min: f64, max: f64| {
+        CreateCommandOption::new(CommandOptionType::Number, name, description).min_number_value(min).max_number_value(max)
+    };
+    subcommand
+        .add_sub_option(number("speed", "話速 (既定 1.0)", 0.5, 2.0))
+        .add_sub_option(number("pitch", "音高 (既定 0.0)", -0.15, 0.15))
+        .add_sub_option(number("intonation", "抑揚 (既定 1.0)", 0.0, 2.0))
+        .add_sub_option(number("volume", "音量 (既定 1.0)", 0.0, 2.0))
+        .add_sub_option(number("pre_phoneme", "開始前の無音の秒数 (既定 0.1)", 0.0, 1.5))
+        .add_sub_option(number("post_phoneme", "終了後の無音の秒数 (既定 0.1)", 0.0, 1.5))
+        .add_sub_option(number("pause", "句読点などの間の長さの倍率 (既定 1.0)", 0.0, 2.0))
+}
+
+fn style_option(description: &str) -> CreateCommandOption {
+    CreateCommandOption::new(CommandOptionType::Integer, "style", description)
+        .min_int_value(0)
+        .max_int_value(u8::MAX as u64)
+        .set_autocomplete(true)
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("preset")
+        .description("話者や話速をまとめた声のプリセットを管理します")
+        .add_option(voice_options(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "add", "プリセットを追加します")
+                .add_sub_option(name_option("プリセット名"))
+                .add_sub_option(style_option("話者のスタイル").required(true))
+        ))
+        .add_option(voice_options(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "edit", "プリセットを編集します。指定した項目だけを変更します")
+                .add_sub_option(name_option("編集するプリセット").set_autocomplete(true))
+                .add_sub_option(style_option("話者のスタイル"))
+        ))
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "delete", "プリセットを削除します")
+                .add_sub_option(name_option("削除するプリセット").set_autocomplete(true))
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "list", "このサーバーのプリセットを表示します")
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "use", "自分のメッセージを読み上げる声のプリセットを選びます")
+                .add_sub_option(name_option("使うプリセット").set_autocomplete(true))
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "reset", "声を既定に戻します")
+        )
+}
@@ -0,0 +1,87 @@
+use crate::embed;
+use crate::voice::playback;
+use crate::voice::voicevox::accent;
+use crate::voice::voicevox::client::Client as VoicevoxClient;
+use crate::voice::voicevox::dictionary::{DictionaryIndex, GuildDictionary};
+use anyhow::Result;
+use serenity::{
+    builder::{CreateCommand, CreateCommandOption, CreateEmbed, CreateInteractionResponseFollowup},
+    model::application::{CommandInteraction, CommandOptionType},
+    prelude::*,
+};
+use tracing::{debug, error, warn};
+
+pub async fn run(ctx: &Context, interaction: &CommandInteraction, voicevox_client: &VoicevoxClient, dictionary_index: &DictionaryIndex) -> Result<()> {
+    interaction.defer(&ctx.http).await?;
+
+    let response_embed = process_reading_command(ctx, interaction, voicevox_client, dictionary_index).await;
+
+    let builder = CreateInteractionResponseFollowup::new().embed(response_embed);
+
+    interaction.create_followup(&ctx.http, builder).await?;
+
+    Ok(())
+}
+
+async fn process_reading_command(ctx: &Context, interaction: &CommandInteraction, voicevox_client: &VoicevoxClient, dictionary_index: &DictionaryIndex) -> CreateEmbed {
+    let Some(text) = interaction.data.options.iter().find(|opt| opt.name == "text").and_then(|opt| opt.value.as_str()) else {
+        return embed::simple_embed(ctx, "エラー", "'text' オプションが見つかりません。", 0xff0000).await;
+    };
+    debug!("Previewing reading: {}", text);
+
+    // 読み上げと同じく、サーバーの辞書を適用してからエンジンに渡す
+    let (replaced, replaced_words) = match interaction.guild_id {
+        Some(guild_id) => dictionary_index.guild_dictionary(guild_id).replace(text),
+        None => (text.to_string(), Vec::new()),
+    };
+
+    let audio_query = match voicevox_client.create_audio_query(&replaced, playback::SPEAKER_ID, playback::SPEED_SCALE).await {
+        Ok(audio_query) => audio_query,
+        Err(e) => {
+            error!("Failed to create audio query: {}", e);
+            return embed::simple_embed(ctx, "エラー", &format!("音声クエリの生成に失敗しました: {}", e), 0xff0000).await;
+        }
+    };
+    let audio_query = GuildDictionary::apply_accents(voicevox_client, &audio_query, &replaced_words, playback::SPEAKER_ID)
+        .await
+        .unwrap_or_else(|e| {
+            warn!("Failed to apply guild dictionary accents: {}", e);
+            audio_query
+        });
+    let (kana, accent_phrases) = match accent::parse_audio_query(&audio_query) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            error!("Failed to parse audio query: {}", e);
+            return embed::simple_embed(ctx, "エラー", &format!("音声クエリの読み込みに失敗しました: {}", e), 0xff0000).await;
+        }
+    };
+
+    let description = format!(
+        "**テキスト:** {}\n{}**読み:** {}\n\n**アクセント句** (＼の後で音が下がります)\n{}",
+        text,
+        if replaced != text { format!("**辞書の適用後:** {}\n", replaced) } else { String::new() },
+        kana,
+        describe_accent_phrases(&accent_phrases),
+    );
+    embed::simple_embed(ctx, "読みの確認", &description, 0x0099ff).await
+}
+
+/// アクセント句を1行ずつ、辞書に登録するときのアクセントの値と並べる
+pub fn describe_accent_phrases(accent_phrases: &[accent::AccentPhrase]) -> String {
+    accent_phrases
+        .iter()
+        .enumerate()
+        .map(|(index, phrase)| format!("`{}.` {} (アクセント: {})", index + 1, phrase.marked(), phrase.accent_type()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("reading")
+        .description("VOICEVOXがテキストをどう読むか、読みとアクセントを表示します")
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::String, "text", "確認するテキスト")
+                .required(true)
+                .max_length(200)
+        )
+}
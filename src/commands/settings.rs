@@ -0,0 +1,272 @@
+use crate::database::GuildSettings;
+use crate::embed;
+use crate::permissions::{self, Permission};
+use anyhow::Result;
+use serenity::{
+    all::{GuildId, RoleId},
+    builder::{CreateCommand, CreateCommandOption, CreateEmbed, CreateInteractionResponseFollowup},
+    model::application::{CommandDataOption, CommandDataOptionValue, CommandInteraction, CommandOptionType},
+    prelude::*,
+};
+use sqlx::SqlitePool;
+use tracing::{debug, error};
+
+pub async fn run(ctx: &Context, interaction: &CommandInteraction, pool: &SqlitePool) -> Result<()> {
+    // ロールの設定はロールを持つだけのメンバーが自分の権限を広げられないように、サーバーの管理権限を必須にする
+    match interaction.data.options.first().map(|opt| opt.name.as_str()) {
+        Some("show") | None => {}
+        Some("permission") => {
+            if interaction.guild_id.is_some() && !permissions::is_guild_manager(interaction) {
+                permissions::deny(ctx, interaction, "ロールの権限はDiscordの「サーバー管理」権限を持つメンバーのみ変更できます").await?;
+                return Ok(());
+            }
+        }
+        Some(_) => {
+            if !permissions::require(ctx, pool, interaction, Permission::Settings).await? {
+                return Ok(());
+            }
+        }
+    }
+
+    interaction.defer(&ctx.http).await?;
+
+    let response_embed = process_settings_command(ctx, interaction, pool).await;
+
+    let builder = CreateInteractionResponseFollowup::new().embed(response_embed);
+
+    interaction.create_followup(&ctx.http, builder).await?;
+
+    Ok(())
+}
+
+async fn process_settings_command(ctx: &Context, interaction: &CommandInteraction, pool: &SqlitePool) -> CreateEmbed {
+    let guild_id = match interaction.guild_id {
+        Some(guild_id) => guild_id,
+        None => {
+            return embed::simple_embed(ctx, "エラー", "このコマンドはギルド内でのみ使えます", 0xff0000).await;
+        }
+    };
+
+    let Some(subcommand) = interaction.data.options.first() else {
+        return embed::simple_embed(ctx, "エラー", "サブコマンドを指定してください。", 0xff0000).await;
+    };
+    if subcommand.name == "permission" {
+        return process_permission_command(ctx, pool, guild_id, subcommand).await;
+    }
+    let CommandDataOptionValue::SubCommand(args) = &subcommand.value else {
+        return embed::simple_embed(ctx, "エラー", "サブコマンドの引数を正しく取得できませんでした。", 0xff0000).await;
+    };
+
+    match subcommand.name.as_str() {
+        "show" => show_settings(ctx, pool, guild_id).await,
+        "idle_timeout" => {
+            let minutes = args.iter().find(|opt| opt.name == "minutes").and_then(|opt| opt.value.as_i64());
+            set_idle_timeout(ctx, pool, guild_id, minutes).await
+        }
+        "read_name" => {
+            let enabled = args.iter().find(|opt| opt.name == "enabled").and_then(|opt| opt.value.as_bool());
+            let window_secs = args.iter().find(|opt| opt.name == "window_secs").and_then(|opt| opt.value.as_i64());
+            set_read_name(ctx, pool, guild_id, enabled, window_secs).await
+        }
+        _ => embed::simple_embed(ctx, "エラー", &format!("「{}」は不明なコマンドです。", subcommand.name), 0xff0000).await,
+    }
+}
+
+async fn show_settings(ctx: &Context, pool: &SqlitePool, guild_id: GuildId) -> CreateEmbed {
+    debug!("Showing guild settings");
+
+    match GuildSettings::fetch(pool, guild_id).await {
+        Ok(settings) => {
+            let idle_timeout = match settings.idle_timeout_secs {
+                None => "既定値".to_string(),
+                Some(0) => "無効".to_string(),
+                Some(secs) => format!("{}分", secs / 60),
+            };
+            let description = format!(
+                "**自動切断までの時間:** {}\n**名前の読み上げ:** {}\n**名前を省略する間隔:** {}秒",
+                idle_timeout,
+                if settings.read_name { "有効" } else { "無効" },
+                settings.name_window_secs,
+            );
+            embed::simple_embed(ctx, "サーバー設定", &description, 0x0099ff).await
+        }
+        Err(e) => {
+            error!("Failed to fetch guild settings: {}", e);
+            embed::simple_embed(ctx, "エラー", &format!("設定の取得に失敗しました: {}", e), 0xff0000).await
+        }
+    }
+}
+
+async fn set_idle_timeout(ctx: &Context, pool: &SqlitePool, guild_id: GuildId, minutes: Option<i64>) -> CreateEmbed {
+    debug!("Setting idle timeout: {:?}", minutes);
+
+    match GuildSettings::set_idle_timeout(pool, guild_id, minutes.map(|minutes| minutes * 60)).await {
+        Ok(()) => {
+            let description = match minutes {
+                None => "自動切断までの時間を既定値に戻しました".to_string(),
+                Some(0) => "自動切断を無効にしました".to_string(),
+                Some(minutes) => format!("{}分間読み上げがない場合にVCから切断します", minutes),
+            };
+            embed::simple_embed(ctx, "設定を変更しました", &description, 0x00ff00).await
+        }
+        Err(e) => {
+            error!("Failed to update idle timeout: {}", e);
+            embed::simple_embed(ctx, "エラー", &format!("設定の変更に失敗しました: {}", e), 0xff0000).await
+        }
+    }
+}
+
+async fn set_read_name(ctx: &Context, pool: &SqlitePool, guild_id: GuildId, enabled: Option<bool>, window_secs: Option<i64>) -> CreateEmbed {
+    debug!("Setting read name: {:?}, window: {:?}", enabled, window_secs);
+
+    let Some(enabled) = enabled else {
+        return embed::simple_embed(ctx, "エラー", "'enabled' オプションが見つかりません。", 0xff0000).await;
+    };
+
+    if let Err(e) = GuildSettings::set_read_name(pool, guild_id, enabled).await {
+        error!("Failed to update read name: {}", e);
+        return embed::simple_embed(ctx, "エラー", &format!("設定の変更に失敗しました: {}", e), 0xff0000).await;
+    }
+
+    if let Some(window_secs) = window_secs
+        && let Err(e) = GuildSettings::set_name_window(pool, guild_id, window_secs).await
+    {
+        error!("Failed to update name window: {}", e);
+        return embed::simple_embed(ctx, "エラー", &format!("設定の変更に失敗しました: {}", e), 0xff0000).await;
+    }
+
+    let mut description = if enabled {
+        "メッセージの前に発言者の名前を読み上げます".to_string()
+    } else {
+        "発言者の名前を読み上げないようにしました".to_string()
+    };
+    if let Some(window_secs) = window_secs {
+        description.push_str(&format!("\n同じ人が{}秒以内に続けて発言した場合は名前を省略します", window_secs));
+    }
+
+    embed::simple_embed(ctx, "設定を変更しました", &description, 0x00ff00).await
+}
+
+async fn process_permission_command(ctx: &Context, pool: &SqlitePool, guild_id: GuildId, option: &CommandDataOption) -> CreateEmbed {
+    let CommandDataOptionValue::SubCommandGroup(subcommands) = &option.value else {
+        return embed::simple_embed(ctx, "エラー", "サブコマンドの引数を正しく取得できませんでした。", 0xff0000).await;
+    };
+    let Some(subcommand) = subcommands.first() else {
+        return embed::simple_embed(ctx, "エラー", "サブコマンドを指定してください。", 0xff0000).await;
+    };
+    let CommandDataOptionValue::SubCommand(args) = &subcommand.value else {
+        return embed::simple_embed(ctx, "エラー", "サブコマンドの引数を正しく取得できませんでした。", 0xff0000).await;
+    };
+
+    if subcommand.name == "list" {
+        return list_permissions(ctx, pool, guild_id).await;
+    }
+
+    let permission = args.iter().find(|opt| opt.name == "permission").and_then(|opt| opt.value.as_str()).and_then(|value| value.parse::<Permission>().ok());
+    let role_id = args.iter().find(|opt| opt.name == "role").and_then(|opt| opt.value.as_role_id());
+    let (Some(permission), Some(role_id)) = (permission, role_id) else {
+        return embed::simple_embed(ctx, "エラー", "'permission' と 'role' オプションを指定してください。", 0xff0000).await;
+    };
+    debug!("Updating permission role: {} {:?} {}", subcommand.name, permission, role_id);
+
+    match subcommand.name.as_str() {
+        "add" => match permissions::add_role(pool, guild_id, permission, role_id).await {
+            Ok(true) => {
+                let description = format!("<@&{}>が「{}」を行えるようになりました", role_id, permission.label());
+                embed::simple_embed(ctx, "権限を変更しました", &description, 0x00ff00).await
+            }
+            Ok(false) => embed::simple_embed(ctx, "エラー", &format!("<@&{}>には既に「{}」の権限があります", role_id, permission.label()), 0xff0000).await,
+            Err(e) => permission_error(ctx, e).await,
+        },
+        "remove" => match permissions::remove_role(pool, guild_id, permission, role_id).await {
+            Ok(true) => embed::simple_embed(ctx, "権限を変更しました", &format!("<@&{}>から「{}」の権限を外しました", role_id, permission.label()), 0x00ff00).await,
+            Ok(false) => embed::simple_embed(ctx, "エラー", &format!("<@&{}>には「{}」の権限が設定されていません", role_id, permission.label()), 0xff0000).await,
+            Err(e) => permission_error(ctx, e).await,
+        },
+        name => embed::simple_embed(ctx, "エラー", &format!("「{}」は不明なコマンドです。", name), 0xff0000).await,
+    }
+}
+
+async fn permission_error(ctx: &Context, e: anyhow::Error) -> CreateEmbed {
+    error!("Failed to update permission role: {}", e);
+    embed::simple_embed(ctx, "エラー", &format!("権限の変更に失敗しました: {}", e), 0xff0000).await
+}
+
+async fn list_permissions(ctx: &Context, pool: &SqlitePool, guild_id: GuildId) -> CreateEmbed {
+    debug!("Listing permission roles");
+
+    let mut lines = Vec::new();
+    for permission in Permission::ALL {
+        let roles = match permissions::fetch_roles(pool, guild_id, permission).await {
+            Ok(roles) => roles,
+            Err(e) => {
+                error!("Failed to fetch permission roles: {}", e);
+                return embed::simple_embed(ctx, "エラー", &format!("権限の取得に失敗しました: {}", e), 0xff0000).await;
+            }
+        };
+        let roles = if roles.is_empty() {
+            format!("未設定 (Discordの権限: {})", permission.default_label())
+        } else {
+            roles.iter().map(|role_id: &RoleId| format!("<@&{}>", role_id)).collect::<Vec<_>>().join(", ")
+        };
+        lines.push(format!("**{}:** {}", permission.label(), roles));
+    }
+
+    let description = format!(
+        "{}\n\nDiscordの「サーバー管理」権限を持つメンバーはすべての操作ができます\nコマンドはすべてのメンバーに表示され、実行時にBOTが上記のロール・権限を確認します",
+        lines.join("\n"),
+    );
+    embed::simple_embed(ctx, "コマンドの権限", &description, 0x0099ff).await
+}
+
+fn permission_option() -> CreateCommandOption {
+    Permission::ALL.into_iter().fold(
+        CreateCommandOption::new(CommandOptionType::String, "permission", "対象の操作").required(true),
+        |option, permission| option.add_string_choice(permission.label(), permission.as_str()),
+    )
+}
+
+pub fn register() -> CreateCommand {
+    // `default_member_permissions`で隠すとロールに与えた権限が使えないので、権限は`run`で確認する
+    CreateCommand::new("settings")
+        .description("サーバーごとの読み上げ設定を変更します")
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "show", "現在の設定を表示します")
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "idle_timeout", "読み上げがない場合に自動で切断するまでの時間を設定します")
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::Integer, "minutes", "切断までの分数 (0で無効、省略で既定値)")
+                        .min_int_value(0)
+                        .max_int_value(1440)
+                )
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "read_name", "メッセージの前に発言者の名前を読み上げるか設定します")
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::Boolean, "enabled", "名前を読み上げるか")
+                        .required(true)
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::Integer, "window_secs", "同じ人の連続した発言で名前を省略する秒数")
+                        .min_int_value(0)
+                        .max_int_value(3600)
+                )
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommandGroup, "permission", "コマンドを使えるロールを管理します")
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::SubCommand, "add", "操作を許可するロールを追加します")
+                        .add_sub_option(permission_option())
+                        .add_sub_option(CreateCommandOption::new(CommandOptionType::Role, "role", "対象のロール").required(true))
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::SubCommand, "remove", "操作を許可するロールを削除します")
+                        .add_sub_option(permission_option())
+                        .add_sub_option(CreateCommandOption::new(CommandOptionType::Role, "role", "対象のロール").required(true))
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::SubCommand, "list", "操作ごとに許可されているロールを表示します")
+                )
+        )
+}
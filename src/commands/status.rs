@@ -0,0 +1,108 @@
+use crate::voice::manager::VoiceManager;
+use crate::voice::voicevox::client::Client as VoicevoxClient;
+use crate::embed;
+use anyhow::Result;
+use serenity::{
+    builder::{CreateCommand, CreateInteractionResponseFollowup},
+    gateway::ShardManager,
+    model::application::CommandInteraction,
+    prelude::*,
+};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+/// ゲートウェイの応答時間を調べるために`Context::data`へ入れておくシャードマネージャー
+pub struct ShardManagerContainer;
+
+impl TypeMapKey for ShardManagerContainer {
+    type Value = Arc<ShardManager>;
+}
+
+/// VOICEVOXエンジンとボットの状態を表示する
+pub async fn run(ctx: &Context, interaction: &CommandInteraction, voicevox_client: &VoicevoxClient, voice_manager: &VoiceManager, started_at: Instant) -> Result<()> {
+    interaction.defer(&ctx.http).await?;
+    debug!("Collecting bot status");
+
+    let mut description = String::from("**VOICEVOXエンジン**\n");
+    let (mut hits, mut misses) = (0, 0);
+    for engine in voicevox_client.engines() {
+        // 定期的なヘルスチェックを止めている場合は、ここで確認する
+        if engine.status().checked_at.is_none() {
+            engine.check_health().await;
+        }
+        let status = engine.status();
+        let (engine_hits, engine_misses) = engine.uuid_cache_stats();
+        hits += engine_hits;
+        misses += engine_misses;
+
+        description.push_str(&format!(
+            "{} {} ({})\n",
+            if status.reachable && engine.is_available() { "🟢" } else if status.reachable { "🟡" } else { "🔴" },
+            engine.url(),
+            status.engine_name.as_deref().unwrap_or("不明なエンジン"),
+        ));
+        description.push_str(&format!(
+            "　バージョン: {} / コア: {}\n",
+            status.version.as_deref().unwrap_or("不明"),
+            if status.core_versions.is_empty() { "不明".to_string() } else { status.core_versions.join(", ") },
+        ));
+        description.push_str(&format!(
+            "　応答時間: {} (平均 {}) / 処理中のリクエスト: {}\n",
+            format_latency(status.latency),
+            format_latency(status.average_latency),
+            engine.outstanding(),
+        ));
+    }
+
+    let gateway_latency = match ctx.data.read().await.get::<ShardManagerContainer>() {
+        Some(shard_manager) => shard_manager.runners.lock().await.get(&ctx.shard_id).and_then(|runner| runner.latency),
+        None => None,
+    };
+
+    let songbird = songbird::get(ctx).await;
+    let mut queues = Vec::new();
+    for guild_id in voice_manager.guild_ids() {
+        let Some(call) = songbird.as_ref().and_then(|manager| manager.get(guild_id)) else {
+            continue;
+        };
+        let queue_len = call.lock().await.queue().len();
+        let guild_name = guild_id.name(&ctx.cache).unwrap_or_else(|| guild_id.to_string());
+        queues.push(format!("　{}: {}件", guild_name, queue_len));
+    }
+
+    description.push_str(&format!("\n**ゲートウェイの応答時間:** {}\n", format_latency(gateway_latency)));
+    description.push_str(&format!("**接続中のボイスチャンネル:** {}\n", queues.len()));
+    if !queues.is_empty() {
+        description.push_str(&format!("**読み上げ待ち**\n{}\n", queues.join("\n")));
+    }
+    description.push_str(&format!(
+        "**辞書の単語IDキャッシュのヒット率:** {}\n",
+        if hits + misses == 0 { "-".to_string() } else { format!("{:.1}% ({}/{})", hits as f64 * 100.0 / (hits + misses) as f64, hits, hits + misses) },
+    ));
+    description.push_str(&format!("**稼働時間:** {}", format_uptime(started_at.elapsed())));
+
+    let response_embed = embed::simple_embed(ctx, "ステータス", &description, 0x0099ff).await;
+    interaction.create_followup(&ctx.http, CreateInteractionResponseFollowup::new().embed(response_embed)).await?;
+    Ok(())
+}
+
+fn format_latency(latency: Option<Duration>) -> String {
+    latency.map_or_else(|| "-".to_string(), |latency| format!("{}ms", latency.as_millis()))
+}
+
+fn format_uptime(uptime: Duration) -> String {
+    let secs = uptime.as_secs();
+    let (days, hours, minutes) = (secs / 86400, secs / 3600 % 24, secs / 60 % 60);
+    if days > 0 {
+        format!("{}日{}時間{}分", days, hours, minutes)
+    } else if hours > 0 {
+        format!("{}時間{}分", hours, minutes)
+    } else {
+        format!("{}分{}秒", minutes, secs % 60)
+    }
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("status").description("VOICEVOXエンジンとボットの状態を表示します")
+}
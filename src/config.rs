@@ -15,30 +15,106 @@ pub struct Config {
     #[serde(rename = "GUILD_ID")]
     pub guild_id: String,
 
+    /// `http://engine1:50021|2,http://engine2:50021`のようにカンマで区切って複数指定できる。`|`の後は重み(省略すると1)
     #[serde(rename = "VOICEVOX_URL")]
-    #[serde(deserialize_with = "deserialize_url")]
-    pub voicevox_url: Url,
+    #[serde(deserialize_with = "deserialize_endpoints")]
+    pub voicevox_endpoints: Vec<EngineEndpoint>,
 
     #[serde(default = "default_speaker_id")]
     pub default_speaker_id: u8,
 
+    /// 起動時にモデルを読み込んでおく話者のID。`2,3,8`のようにカンマで区切る
+    #[serde(rename = "WARMUP_SPEAKER_IDS", default)]
+    #[serde(deserialize_with = "deserialize_speaker_ids")]
+    pub warmup_speaker_ids: Vec<u8>,
+
     #[serde(default = "default_speed_scale")]
     pub default_speed_scale: f64,
 
     #[serde(default = "default_timeout")]
     pub request_timeout_secs: u64,
+
+    #[serde(default = "default_idle_timeout")]
+    pub idle_timeout_secs: u64,
+
+    /// VOICEVOXへのリクエストの試行回数(最初の1回を含む)
+    #[serde(default = "default_retry_max_attempts")]
+    pub retry_max_attempts: u32,
+
+    #[serde(default = "default_retry_base_delay")]
+    pub retry_base_delay_ms: u64,
+
+    /// 何回続けて失敗したらリクエストを止めるか
+    #[serde(default = "default_circuit_failure_threshold")]
+    pub circuit_failure_threshold: u32,
+
+    #[serde(default = "default_circuit_open")]
+    pub circuit_open_secs: u64,
+
+    /// エンジンのヘルスチェックの間隔。0で無効
+    #[serde(default = "default_health_check_interval")]
+    pub health_check_interval_secs: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EngineEndpoint {
+    pub url: Url,
+    /// 合成を振り分ける割合
+    pub weight: u32,
+}
+
+impl std::fmt::Display for EngineEndpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (weight {})", self.url, self.weight)
+    }
+}
+
+impl FromStr for EngineEndpoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // URLのクエリに含まれる`=`と区別するため、重みは`|`で区切る
+        let (url, weight) = match s.rsplit_once('|') {
+            Some((url, weight)) => (url, weight.trim().parse::<u32>().map_err(|e| format!("Invalid weight for {}: {}", url, e))?),
+            None => (s, 1),
+        };
+        let url = Url::from_str(url.trim()).map_err(|e| format!("Invalid URL {}: {}", url, e))?;
+        Ok(Self { url, weight })
+    }
 }
 
 fn default_speaker_id() -> u8 { 1 }
 fn default_speed_scale() -> f64 { 1.0 }
 fn default_timeout() -> u64 { 10 }
+fn default_idle_timeout() -> u64 { 600 }
+fn default_retry_max_attempts() -> u32 { 3 }
+fn default_retry_base_delay() -> u64 { 200 }
+fn default_circuit_failure_threshold() -> u32 { 5 }
+fn default_circuit_open() -> u64 { 30 }
+fn default_health_check_interval() -> u64 { 10 }
+
+fn deserialize_endpoints<'de, D>(deserializer: D) -> Result<Vec<EngineEndpoint>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    s.split(',')
+        .map(str::trim)
+        .filter(|endpoint| !endpoint.is_empty())
+        .map(|endpoint| endpoint.parse().map_err(serde::de::Error::custom))
+        .collect()
+}
 
-fn deserialize_url<'de, D>(deserializer: D) -> Result<Url, D::Error>
+fn deserialize_speaker_ids<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
 where
     D: Deserializer<'de>,
 {
     let s = String::deserialize(deserializer)?;
-    Url::from_str(&s).map_err(serde::de::Error::custom)
+    s.split(',')
+        .map(str::trim)
+        .filter(|speaker_id| !speaker_id.is_empty())
+        .map(|speaker_id| speaker_id.parse().map_err(|e| serde::de::Error::custom(format!("Invalid speaker ID {}: {}", speaker_id, e))))
+        .collect()
 }
 
 impl Config {
@@ -73,6 +149,53 @@ impl Config {
             return Err("Speed scale must be between 0.0 and 2.0".to_string());
         }
 
+        if self.voicevox_endpoints.is_empty() {
+            return Err("At least one voicevox URL is required".to_string());
+        }
+
+        if self.voicevox_endpoints.iter().any(|endpoint| endpoint.weight == 0) {
+            return Err("Voicevox engine weight must be at least 1".to_string());
+        }
+
+        if self.retry_max_attempts == 0 {
+            return Err("Retry max attempts must be at least 1".to_string());
+        }
+
+        if self.circuit_failure_threshold == 0 {
+            return Err("Circuit failure threshold must be at least 1".to_string());
+        }
+
         Ok(())
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn engine_endpoint_parses_weight_after_pipe() {
+        let endpoint = "http://engine1:50021|3".parse::<EngineEndpoint>().unwrap();
+        assert_eq!(endpoint, EngineEndpoint { url: Url::parse("http://engine1:50021").unwrap(), weight: 3 });
+
+        let endpoint = " http://engine2:50021 ".parse::<EngineEndpoint>().unwrap();
+        assert_eq!(endpoint, EngineEndpoint { url: Url::parse("http://engine2:50021").unwrap(), weight: 1 });
+    }
+
+    #[test]
+    fn engine_endpoint_keeps_equals_sign_in_query() {
+        let endpoint = "http://host/?a=b".parse::<EngineEndpoint>().unwrap();
+        assert_eq!(endpoint.url.as_str(), "http://host/?a=b");
+        assert_eq!(endpoint.weight, 1);
+
+        let endpoint = "http://host/?token=2|4".parse::<EngineEndpoint>().unwrap();
+        assert_eq!(endpoint.url.as_str(), "http://host/?token=2");
+        assert_eq!(endpoint.weight, 4);
+    }
+
+    #[test]
+    fn engine_endpoint_rejects_invalid_weight_and_url() {
+        assert!("http://engine1:50021|heavy".parse::<EngineEndpoint>().is_err());
+        assert!("http://engine1:50021|-1".parse::<EngineEndpoint>().is_err());
+        assert!("engine1|2".parse::<EngineEndpoint>().is_err());
+    }
+}
@@ -0,0 +1,125 @@
+use crate::embed;
+use anyhow::Result;
+use serenity::{
+    all::UserId,
+    builder::{CreateActionRow, CreateButton, CreateInteractionResponse, CreateInteractionResponseFollowup, CreateInteractionResponseMessage},
+    model::application::{ButtonStyle, CommandInteraction, ComponentInteraction},
+    prelude::*,
+};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// 確認ボタンを押すまでの制限時間
+pub const CONFIRM_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// 取り消せない操作の前に確認ボタンを出し、押されるまで操作を保持する
+///
+/// ボタンのカスタムIDは`接頭辞:confirm:ID`と`接頭辞:cancel:ID`で、
+/// ハンドラーから`handles`で振り分けて`resolve`を呼ぶ
+pub struct Confirmations<T> {
+    prefix: &'static str,
+    pending: Arc<Mutex<HashMap<u64, Pending<T>>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+struct Pending<T> {
+    user_id: UserId,
+    action: T,
+}
+
+impl<T> Clone for Confirmations<T> {
+    fn clone(&self) -> Self {
+        Self { prefix: self.prefix, pending: self.pending.clone(), next_id: self.next_id.clone() }
+    }
+}
+
+impl<T: Send + 'static> Confirmations<T> {
+    pub fn new(prefix: &'static str) -> Self {
+        Self { prefix, pending: Arc::default(), next_id: Arc::new(AtomicU64::new(1)) }
+    }
+
+    pub fn handles(&self, custom_id: &str) -> bool {
+        custom_id.strip_prefix(self.prefix).is_some_and(|rest| rest.starts_with(':'))
+    }
+
+    /// 確認の埋め込みにボタンを付けて送る。時間内に押されなければメッセージを書き換えて操作を破棄する
+    ///
+    /// 本人にだけ見えるように、コマンドは`defer_ephemeral`しておく
+    pub async fn request(&self, ctx: &Context, interaction: &CommandInteraction, builder: CreateInteractionResponseFollowup, confirm_label: &str, action: T) -> Result<()> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.pending.lock().unwrap().insert(id, Pending { user_id: interaction.user.id, action });
+
+        let buttons = CreateActionRow::Buttons(vec![
+            CreateButton::new(format!("{}:confirm:{}", self.prefix, id)).label(confirm_label).style(ButtonStyle::Danger),
+            CreateButton::new(format!("{}:cancel:{}", self.prefix, id)).label("キャンセル").style(ButtonStyle::Secondary),
+        ]);
+        let message = match interaction.create_followup(&ctx.http, builder.components(vec![buttons]).ephemeral(true)).await {
+            Ok(message) => message,
+            Err(e) => {
+                self.pending.lock().unwrap().remove(&id);
+                return Err(e.into());
+            }
+        };
+        debug!(id, prefix = self.prefix, "Waiting for confirmation");
+
+        let pending = self.pending.clone();
+        let ctx = ctx.clone();
+        let interaction = interaction.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(CONFIRM_TIMEOUT).await;
+            if pending.lock().unwrap().remove(&id).is_none() {
+                return;
+            }
+
+            debug!(id, "Confirmation timed out");
+            let embed = embed::simple_embed(&ctx, "確認の期限が切れました", "時間内に確認されなかったため、操作は行われていません", 0xffaa00).await;
+            let builder = CreateInteractionResponseFollowup::new().embed(embed).components(vec![]);
+            if let Err(e) = interaction.edit_followup(&ctx.http, message.id, builder).await {
+                warn!("Failed to update timed out confirmation: {}", e);
+            }
+        });
+
+        Ok(())
+    }
+
+    /// ボタンが押されたときに呼ぶ。確定された場合は応答を保留して操作を返すので、
+    /// 呼び出し側で実行して`edit_response`で結果を表示する
+    pub async fn resolve(&self, ctx: &Context, component: &ComponentInteraction) -> Result<Option<T>> {
+        let mut parts = component.data.custom_id.splitn(3, ':').skip(1);
+        let confirmed = parts.next() == Some("confirm");
+        let id = parts.next().and_then(|id| id.parse::<u64>().ok()).unwrap_or_default();
+
+        let pending = {
+            let mut pending = self.pending.lock().unwrap();
+            match pending.get(&id) {
+                Some(entry) if entry.user_id != component.user.id => Err(()),
+                Some(_) => Ok(pending.remove(&id)),
+                None => Ok(None),
+            }
+        };
+
+        let (title, description, color) = match pending {
+            Err(()) => {
+                let embed = embed::simple_embed(ctx, "エラー", "この確認はコマンドを実行した人のみ操作できます", 0xff0000).await;
+                let response = CreateInteractionResponseMessage::new().embed(embed).ephemeral(true);
+                component.create_response(&ctx.http, CreateInteractionResponse::Message(response)).await?;
+                return Ok(None);
+            }
+            Ok(Some(entry)) if confirmed => {
+                // 操作に時間がかかることがあるため、先に応答してから結果で書き換える
+                component.create_response(&ctx.http, CreateInteractionResponse::Acknowledge).await?;
+                return Ok(Some(entry.action));
+            }
+            Ok(Some(_)) => ("キャンセルしました", "操作は行われていません", 0x0099ff),
+            Ok(None) => ("確認の期限が切れました", "もう一度コマンドを実行してください", 0xffaa00),
+        };
+
+        let embed = embed::simple_embed(ctx, title, description, color).await;
+        let response = CreateInteractionResponseMessage::new().embed(embed).components(vec![]);
+        component.create_response(&ctx.http, CreateInteractionResponse::UpdateMessage(response)).await?;
+        Ok(None)
+    }
+}
@@ -0,0 +1,343 @@
+use anyhow::{Context, Result};
+use serenity::model::id::{GuildId, UserId};
+use sqlx::{Sqlite, SqlitePool};
+use tracing::info;
+
+pub const DEFAULT_NAME_WINDOW_SECS: i64 = 60;
+
+pub async fn init_schema(pool: &SqlitePool) -> Result<()> {
+    sqlx::query("CREATE TABLE IF NOT EXISTS sub_channel (id INTEGER PRIMARY KEY, guild_id INTEGER, voice_channel_id INTEGER, message_channel_id INTEGER)")
+        .execute(pool)
+        .await
+        .context("Failed to create database schema")?;
+
+    sqlx::query("CREATE TABLE IF NOT EXISTS guild_settings (guild_id INTEGER PRIMARY KEY)")
+        .execute(pool)
+        .await
+        .context("Failed to create database schema")?;
+    ensure_column(pool, "guild_settings", "idle_timeout_secs", "INTEGER").await?;
+    ensure_column(pool, "guild_settings", "read_name", "INTEGER NOT NULL DEFAULT 0").await?;
+    ensure_column(pool, "guild_settings", "name_window_secs", &format!("INTEGER NOT NULL DEFAULT {}", DEFAULT_NAME_WINDOW_SECS)).await?;
+
+    sqlx::query("CREATE TABLE IF NOT EXISTS name_reading (guild_id INTEGER, user_id INTEGER, reading TEXT NOT NULL, PRIMARY KEY (guild_id, user_id))")
+        .execute(pool)
+        .await
+        .context("Failed to create database schema")?;
+
+    sqlx::query("CREATE TABLE IF NOT EXISTS ignore_rule (guild_id INTEGER, kind TEXT NOT NULL, value TEXT NOT NULL, PRIMARY KEY (guild_id, kind, value))")
+        .execute(pool)
+        .await
+        .context("Failed to create database schema")?;
+    // `/forget-me`で本人が設定したルールだけを削除するために、ルールを追加したユーザーを残す
+    ensure_column(pool, "ignore_rule", "created_by", "INTEGER").await?;
+
+    sqlx::query("CREATE TABLE IF NOT EXISTS user_optout (user_id INTEGER PRIMARY KEY)")
+        .execute(pool)
+        .await
+        .context("Failed to create database schema")?;
+
+    // permissionは`permissions::Permission::as_str`の値
+    sqlx::query("CREATE TABLE IF NOT EXISTS permission_role (guild_id INTEGER NOT NULL, permission TEXT NOT NULL, role_id INTEGER NOT NULL, PRIMARY KEY (guild_id, permission, role_id))")
+        .execute(pool)
+        .await
+        .context("Failed to create database schema")?;
+
+    // guild_idが0の単語は全体の辞書
+    sqlx::query("CREATE TABLE IF NOT EXISTS dictionary_entry (guild_id INTEGER NOT NULL, surface TEXT NOT NULL, pronunciation TEXT NOT NULL, accent_type INTEGER NOT NULL, word_type TEXT NOT NULL DEFAULT 'PROPER_NOUN', priority INTEGER NOT NULL DEFAULT 10, PRIMARY KEY (guild_id, surface))")
+        .execute(pool)
+        .await
+        .context("Failed to create database schema")?;
+
+    // 辞書の変更履歴。単語ごとの変更前と変更後をJSONで保存し、無い状態はNULL
+    sqlx::query("CREATE TABLE IF NOT EXISTS dictionary_change (id INTEGER PRIMARY KEY AUTOINCREMENT, guild_id INTEGER NOT NULL, user_id INTEGER NOT NULL, action TEXT NOT NULL, created_at INTEGER NOT NULL, undone INTEGER NOT NULL DEFAULT 0)")
+        .execute(pool)
+        .await
+        .context("Failed to create database schema")?;
+
+    sqlx::query("CREATE TABLE IF NOT EXISTS dictionary_change_entry (change_id INTEGER NOT NULL REFERENCES dictionary_change (id) ON DELETE CASCADE, surface TEXT NOT NULL, before_word TEXT, after_word TEXT)")
+        .execute(pool)
+        .await
+        .context("Failed to create database schema")?;
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS dictionary_change_entry_change_id ON dictionary_change_entry (change_id)")
+        .execute(pool)
+        .await
+        .context("Failed to create database schema")?;
+
+    sqlx::query("CREATE TABLE IF NOT EXISTS dictionary_snapshot (id INTEGER PRIMARY KEY AUTOINCREMENT, guild_id INTEGER NOT NULL, user_id INTEGER NOT NULL, label TEXT NOT NULL, created_at INTEGER NOT NULL, word_count INTEGER NOT NULL, words TEXT NOT NULL)")
+        .execute(pool)
+        .await
+        .context("Failed to create database schema")?;
+
+    // エンジンには`{guild_id}/{name}`の名前で同期する
+    sqlx::query("CREATE TABLE IF NOT EXISTS voice_preset (guild_id INTEGER NOT NULL, name TEXT NOT NULL, style_id INTEGER NOT NULL, speed_scale REAL NOT NULL, pitch_scale REAL NOT NULL, intonation_scale REAL NOT NULL, volume_scale REAL NOT NULL, pre_phoneme_length REAL NOT NULL, post_phoneme_length REAL NOT NULL, pause_length_scale REAL NOT NULL, PRIMARY KEY (guild_id, name))")
+        .execute(pool)
+        .await
+        .context("Failed to create database schema")?;
+
+    sqlx::query("CREATE TABLE IF NOT EXISTS user_preset (guild_id INTEGER NOT NULL, user_id INTEGER NOT NULL, name TEXT NOT NULL, PRIMARY KEY (guild_id, user_id))")
+        .execute(pool)
+        .await
+        .context("Failed to create database schema")?;
+
+    // 一度だけ行うデータの移行。名前が残っていれば実行済み
+    sqlx::query("CREATE TABLE IF NOT EXISTS applied_migration (name TEXT PRIMARY KEY, applied_at INTEGER NOT NULL)")
+        .execute(pool)
+        .await
+        .context("Failed to create database schema")?;
+
+    info!("Database schema created");
+    Ok(())
+}
+
+/// 一度だけ行うデータの移行が実行済みか
+pub async fn migration_applied(pool: &SqlitePool, name: &str) -> Result<bool> {
+    sqlx::query_scalar::<_, bool>("SELECT EXISTS(SELECT 1 FROM applied_migration WHERE name = ?)")
+        .bind(name)
+        .fetch_one(pool)
+        .await
+        .context("Failed to fetch applied migrations")
+}
+
+pub async fn mark_migration_applied(pool: &SqlitePool, name: &str) -> Result<()> {
+    sqlx::query("INSERT OR IGNORE INTO applied_migration (name, applied_at) VALUES (?, unixepoch())")
+        .bind(name)
+        .execute(pool)
+        .await
+        .context("Failed to record applied migration")?;
+    Ok(())
+}
+
+/// テスト用に、スキーマを作ったインメモリのデータベースを開く
+///
+/// インメモリのデータベースは接続ごとに別になるので、接続を1つにする
+#[cfg(test)]
+pub async fn memory_pool() -> SqlitePool {
+    let pool = sqlx::sqlite::SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .expect("Failed to open in-memory database");
+    init_schema(&pool).await.expect("Failed to create database schema");
+    pool
+}
+
+/// 既存のデータベースに後から追加したカラムが無ければ追加する
+async fn ensure_column(pool: &SqlitePool, table: &str, column: &str, definition: &str) -> Result<()> {
+    let exists = sqlx::query_scalar::<_, bool>("SELECT EXISTS(SELECT 1 FROM pragma_table_info(?) WHERE name = ?)")
+        .bind(table)
+        .bind(column)
+        .fetch_one(pool)
+        .await
+        .context("Failed to inspect database schema")?;
+
+    if !exists {
+        sqlx::query(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))
+            .execute(pool)
+            .await
+            .with_context(|| format!("Failed to add column {}.{}", table, column))?;
+        info!("Added column {}.{}", table, column);
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct GuildSettings {
+    /// NULLの場合は設定ファイルの値を使う。0は無効
+    pub idle_timeout_secs: Option<i64>,
+    /// メッセージの前に発言者の名前を読み上げるか
+    pub read_name: bool,
+    /// 同じ人が続けて発言した場合に名前を省略する秒数
+    pub name_window_secs: i64,
+}
+
+impl Default for GuildSettings {
+    fn default() -> Self {
+        Self {
+            idle_timeout_secs: None,
+            read_name: false,
+            name_window_secs: DEFAULT_NAME_WINDOW_SECS,
+        }
+    }
+}
+
+impl GuildSettings {
+    pub async fn fetch(pool: &SqlitePool, guild_id: GuildId) -> Result<Self> {
+        let settings = sqlx::query_as::<_, GuildSettings>("SELECT idle_timeout_secs, read_name, name_window_secs FROM guild_settings WHERE guild_id = ?")
+            .bind(guild_id.get() as i64)
+            .fetch_optional(pool)
+            .await
+            .context("Failed to fetch guild settings")?;
+
+        Ok(settings.unwrap_or_default())
+    }
+
+    pub async fn set_idle_timeout(pool: &SqlitePool, guild_id: GuildId, idle_timeout_secs: Option<i64>) -> Result<()> {
+        Self::update(pool, guild_id, "idle_timeout_secs", idle_timeout_secs).await
+    }
+
+    pub async fn set_read_name(pool: &SqlitePool, guild_id: GuildId, read_name: bool) -> Result<()> {
+        Self::update(pool, guild_id, "read_name", read_name).await
+    }
+
+    pub async fn set_name_window(pool: &SqlitePool, guild_id: GuildId, name_window_secs: i64) -> Result<()> {
+        Self::update(pool, guild_id, "name_window_secs", name_window_secs).await
+    }
+
+    async fn update<T>(pool: &SqlitePool, guild_id: GuildId, column: &str, value: T) -> Result<()>
+    where
+        T: for<'q> sqlx::Encode<'q, Sqlite> + sqlx::Type<Sqlite> + Send,
+    {
+        sqlx::query(&format!(
+            "INSERT INTO guild_settings (guild_id, {column}) VALUES (?, ?) ON CONFLICT(guild_id) DO UPDATE SET {column} = excluded.{column}",
+        ))
+            .bind(guild_id.get() as i64)
+            .bind(value)
+            .execute(pool)
+            .await
+            .context("Failed to update guild settings")?;
+
+        Ok(())
+    }
+}
+
+pub async fn fetch_name_reading(pool: &SqlitePool, guild_id: GuildId, user_id: UserId) -> Result<Option<String>> {
+    sqlx::query_scalar::<_, String>("SELECT reading FROM name_reading WHERE guild_id = ? AND user_id = ?")
+        .bind(guild_id.get() as i64)
+        .bind(user_id.get() as i64)
+        .fetch_optional(pool)
+        .await
+        .context("Failed to fetch name reading")
+}
+
+pub async fn set_name_reading(pool: &SqlitePool, guild_id: GuildId, user_id: UserId, reading: &str) -> Result<()> {
+    sqlx::query("INSERT OR REPLACE INTO name_reading (guild_id, user_id, reading) VALUES (?, ?, ?)")
+        .bind(guild_id.get() as i64)
+        .bind(user_id.get() as i64)
+        .bind(reading)
+        .execute(pool)
+        .await
+        .context("Failed to update name reading")?;
+
+    Ok(())
+}
+
+pub async fn delete_name_reading(pool: &SqlitePool, guild_id: GuildId, user_id: UserId) -> Result<bool> {
+    let result = sqlx::query("DELETE FROM name_reading WHERE guild_id = ? AND user_id = ?")
+        .bind(guild_id.get() as i64)
+        .bind(user_id.get() as i64)
+        .execute(pool)
+        .await
+        .context("Failed to delete name reading")?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn is_opted_out(pool: &SqlitePool, user_id: UserId) -> Result<bool> {
+    sqlx::query_scalar::<_, bool>("SELECT EXISTS(SELECT 1 FROM user_optout WHERE user_id = ?)")
+        .bind(user_id.get() as i64)
+        .fetch_one(pool)
+        .await
+        .context("Failed to fetch opt-out state")
+}
+
+pub async fn set_opted_out(pool: &SqlitePool, user_id: UserId, opted_out: bool) -> Result<bool> {
+    let query = if opted_out {
+        "INSERT OR IGNORE INTO user_optout (user_id) VALUES (?)"
+    } else {
+        "DELETE FROM user_optout WHERE user_id = ?"
+    };
+
+    let result = sqlx::query(query)
+        .bind(user_id.get() as i64)
+        .execute(pool)
+        .await
+        .context("Failed to update opt-out state")?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// ユーザーに関するデータをすべてのテーブルから削除し、テーブルごとの削除件数を返す
+///
+/// ユーザーIDを保存するテーブルを追加した場合はここにも追加すること
+pub async fn forget_user(pool: &SqlitePool, user_id: UserId) -> Result<Vec<(&'static str, u64)>> {
+    // 辞書の履歴は他の人の変更を取り消すのにも使うので、削除せずに変更者を匿名にする。
+    // 読み上げルールも、本人が自分に設定したもの以外はモデレーターの判断なので残して追加者だけを匿名にする。
+    // 読み上げの停止設定は本人のプライバシーの選択なので、削除すると読み上げが再開してしまわないように残す
+    let queries: [(&'static str, &str); 6] = [
+        ("name_reading", "DELETE FROM name_reading WHERE user_id = ?"),
+        ("user_preset", "DELETE FROM user_preset WHERE user_id = ?"),
+        ("ignore_rule", "DELETE FROM ignore_rule WHERE created_by = ? AND kind = 'user' AND value = CAST(created_by AS TEXT)"),
+        ("ignore_rule_created_by", "UPDATE ignore_rule SET created_by = NULL WHERE created_by = ?"),
+        ("dictionary_change", "UPDATE dictionary_change SET user_id = 0 WHERE user_id = ?"),
+        ("dictionary_snapshot", "UPDATE dictionary_snapshot SET user_id = 0 WHERE user_id = ?"),
+    ];
+
+    let mut tx = pool.begin().await.context("Failed to begin transaction")?;
+    let mut removed = Vec::new();
+
+    for (table, query) in queries {
+        let result = sqlx::query(query)
+            .bind(user_id.get() as i64)
+            .execute(&mut *tx)
+            .await
+            .with_context(|| format!("Failed to delete user data from {}", table))?;
+        removed.push((table, result.rows_affected()));
+    }
+
+    tx.commit().await.context("Failed to commit transaction")?;
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ignore::{self, IgnoreRules, RuleKind};
+
+    #[tokio::test]
+    async fn migrations_are_recorded_once() {
+        let pool = memory_pool().await;
+        assert!(!migration_applied(&pool, "example").await.unwrap());
+
+        mark_migration_applied(&pool, "example").await.unwrap();
+        mark_migration_applied(&pool, "example").await.unwrap();
+
+        assert!(migration_applied(&pool, "example").await.unwrap());
+        assert!(!migration_applied(&pool, "other").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn forget_user_keeps_rules_set_by_moderators() {
+        let pool = memory_pool().await;
+        let guild_id = GuildId::new(1);
+        let (user, moderator) = (UserId::new(10), UserId::new(20));
+        ignore::add_rule(&pool, guild_id, RuleKind::User, &user.to_string(), user).await.unwrap();
+        ignore::add_rule(&pool, GuildId::new(2), RuleKind::User, &user.to_string(), moderator).await.unwrap();
+        ignore::add_rule(&pool, guild_id, RuleKind::Prefix, ";", user).await.unwrap();
+
+        let removed = forget_user(&pool, user).await.unwrap();
+
+        assert!(removed.contains(&("ignore_rule", 1)));
+        assert!(removed.contains(&("ignore_rule_created_by", 1)));
+        assert!(IgnoreRules::fetch(&pool, guild_id).await.unwrap().users.is_empty());
+        assert_eq!(IgnoreRules::fetch(&pool, GuildId::new(2)).await.unwrap().users.len(), 1);
+        assert_eq!(IgnoreRules::fetch(&pool, guild_id).await.unwrap().prefixes.len(), 1);
+        let created_by = sqlx::query_scalar::<_, Option<i64>>("SELECT created_by FROM ignore_rule WHERE kind = 'prefix'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(created_by, None);
+    }
+
+    #[tokio::test]
+    async fn forget_user_keeps_opt_out() {
+        let pool = memory_pool().await;
+        let user = UserId::new(10);
+        set_opted_out(&pool, user, true).await.unwrap();
+
+        forget_user(&pool, user).await.unwrap();
+
+        assert!(is_opted_out(&pool, user).await.unwrap());
+    }
+}
@@ -1,8 +1,13 @@
 use crate::Config;
+use crate::database::{self, GuildSettings};
+use crate::ignore::IgnoreRules;
 use crate::voice::manager::VoiceManager;
-use crate::voice::voicevox::client::Client as VoicevoxClient;
+use crate::voice::voicevox::client::{Client as VoicevoxClient, Engine};
 use crate::voice::playback;
+use crate::commands::dictionary::DictionaryPrompts;
+use crate::voice::voicevox::dictionary::{self, DictionaryIndex};
 use crate::voice::voicevox::format;
+use crate::voice::voicevox::preset;
 use anyhow::{Context, Result};
 use serenity::{
     all::Context as SerenityContext,
@@ -13,14 +18,12 @@ use serenity::{
         channel::Message,
         event::ResumedEvent,
         gateway::Ready,
-        guild::Member,
-        id::{GuildId, UserId},
-        user::User,
+        id::GuildId,
     },
-    prelude::*,
 };
 use serenity::all::Interaction;
 use std::path::Path;
+use std::time::{Duration, Instant};
 use sqlx::SqlitePool;
 use tracing::{debug, info, warn, error, instrument};
 
@@ -29,6 +32,11 @@ pub struct Handler {
     pool: SqlitePool,
     voice_manager: VoiceManager,
     voicevox_client: VoicevoxClient,
+    dictionary_index: DictionaryIndex,
+    dictionary_prompts: DictionaryPrompts,
+    started_at: Instant,
+    /// 起動時にモデルを読み込んでおく話者
+    warmup_speakers: Vec<u8>,
 }
 
 impl Handler {
@@ -39,16 +47,22 @@ impl Handler {
 
         let pool = SqlitePool::connect(&config.database_url).await.context("Failed to connect to database")?;
 
-        sqlx::query("CREATE TABLE IF NOT EXISTS sub_channel (id INTEGER PRIMARY KEY, guild_id INTEGER, voice_channel_id INTEGER, message_channel_id INTEGER)")
-            .execute(&pool)
-            .await
-            .context("Failed to create database schema")?;
-        info!("Database schema created");
+        database::init_schema(&pool).await?;
 
-        let voice_manager = VoiceManager::new(pool.clone())?;
+        let voice_manager = VoiceManager::new(pool.clone(), &config)?;
 
         let voicevox_client = VoicevoxClient::new(config.clone())?;
+        if config.health_check_interval_secs > 0 {
+            let resync_pool = pool.clone();
+            voicevox_client.spawn_health_checks(Duration::from_secs(config.health_check_interval_secs), move |engine| {
+                let pool = resync_pool.clone();
+                async move { resync_engine(&pool, &engine).await }
+            });
+        }
         
+        let mut warmup_speakers = vec![playback::SPEAKER_ID, config.default_speaker_id];
+        warmup_speakers.extend(&config.warmup_speaker_ids);
+
         debug!("Handler initialized");
         
         Ok(Self {
@@ -56,8 +70,33 @@ impl Handler {
             pool,
             voice_manager,
             voicevox_client,
+            dictionary_index: DictionaryIndex::default(),
+            dictionary_prompts: DictionaryPrompts::new(),
+            started_at: Instant::now(),
+            warmup_speakers,
         })
     }
+
+    async fn name_prefix(&self, guild_id: GuildId, msg: &Message) -> Option<String> {
+        let settings = match GuildSettings::fetch(&self.pool, guild_id).await {
+            Ok(settings) => settings,
+            Err(e) => {
+                error!("Failed to fetch guild settings: {}", e);
+                return None;
+            }
+        };
+
+        if !settings.read_name {
+            return None;
+        }
+
+        let window = Duration::from_secs(settings.name_window_secs.max(0) as u64);
+        if !self.voice_manager.should_read_name(guild_id, msg.author.id, window) {
+            return None;
+        }
+
+        Some(format::author_name(&self.pool, guild_id, msg).await)
+    }
 }
 
 #[async_trait]
@@ -68,8 +107,16 @@ impl EventHandler for Handler {
         content = %msg.content
     ))]
     async fn message(&self, ctx: serenity::all::Context, msg: Message) {
-        if msg.author.bot {
-            return;
+        match database::is_opted_out(&self.pool, msg.author.id).await {
+            Ok(true) => {
+                debug!("Author opted out of reading");
+                return;
+            }
+            Ok(false) => {}
+            Err(e) => {
+                error!("Failed to fetch opt-out state: {}", e);
+                return;
+            }
         }
 
         let is_voice_channel = sqlx::query_scalar::<_, bool>(
@@ -83,21 +130,43 @@ impl EventHandler for Handler {
 
         match is_voice_channel {
             Ok(true) => {
+                let guild_id = msg.guild_id.unwrap();
+                match IgnoreRules::fetch(&self.pool, guild_id).await {
+                    Ok(rules) if !rules.allows(&msg, ctx.cache.current_user().id) => {
+                        debug!("Message ignored by rules");
+                        return;
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        error!("Failed to fetch ignore rules: {}", e);
+                        return;
+                    }
+                }
+
                 if msg.content.as_str() == "!skip" {
                     info!("Received skip command in voice channel: {}", msg.content);
-                    let guild_id = msg.guild_id.unwrap();
                     if let Err(e) = playback::skip_current_voice(&ctx, guild_id).await {
                         error!("Failed to skip audio: {}", e);
                     } else {
                         debug!("Audio skip request successfully");
                     }
+                } else if let Some(outage) = self.voicevox_client.outage() {
+                    // エンジンが止まっている間はメッセージごとにエラーを出さず、読み上げを止めて1回だけ知らせる
+                    debug!("VOICEVOX engine is unavailable; skipping message");
+                    self.voice_manager.notify_engine_outage(&ctx, guild_id, outage).await;
                 } else {
                     info!("Received voicevox request: {}", msg.content);
-                    let guild_id = msg.guild_id.unwrap();
-                    let formatted_text = format::format_voicevox_message(&ctx, &msg).await;
+                    let mut formatted_text = format::format_voicevox_message(&ctx, &self.pool, &msg).await;
+
+                    if let Some(name) = self.name_prefix(guild_id, &msg).await {
+                        formatted_text = format!("{}、{}", name, formatted_text);
+                    }
 
-                    if let Err(e) = playback::play(&ctx, &self.voicevox_client, guild_id, formatted_text).await {
+                    if let Err(e) = playback::play(&ctx, &self.voicevox_client, &self.voice_manager, &self.dictionary_index, guild_id, Some(msg.author.id), formatted_text).await {
                         error!("Failed to play audio: {}", e);
+                        if let Some(outage) = self.voicevox_client.outage() {
+                            self.voice_manager.notify_engine_outage(&ctx, guild_id, outage).await;
+                        }
                     } else {
                         debug!("Audio play request successfully");
                     }
@@ -122,10 +191,29 @@ impl EventHandler for Handler {
                 crate::commands::join::register(),
                 crate::commands::leave::register(),
                 crate::commands::dictionary::register(),
+                crate::commands::settings::register(),
+                crate::commands::reading::register(),
+                crate::commands::name::register(),
+                crate::commands::ignore::register(),
+                crate::commands::optout::register(),
+                crate::commands::forget_me::register(),
+                crate::commands::dictionary_form::register(),
+                crate::commands::status::register(),
+                crate::commands::preset::register(),
             ]).await;
 
         info!("Registered commands: {:?}", commands);
-        init_app(&self.voicevox_client).await.unwrap();
+        init_app(&self.pool, &self.voicevox_client, &self.dictionary_index).await.unwrap();
+
+        // 誰かがプリセットで選んでいるスタイルも読み込んでおく
+        let mut warmup_speakers = self.warmup_speakers.clone();
+        match preset::selected_style_ids(&self.pool).await {
+            Ok(style_ids) => warmup_speakers.extend(style_ids.into_iter().filter_map(|style_id| u8::try_from(style_id).ok())),
+            Err(e) => warn!("Failed to fetch preset styles: {}", e),
+        }
+        warmup_speakers.sort_unstable();
+        warmup_speakers.dedup();
+        self.voicevox_client.warm_up(&warmup_speakers);
         info!("Ready!");
     }
 
@@ -136,43 +224,115 @@ impl EventHandler for Handler {
 
     #[instrument(skip(self, ctx, interaction))]
     async fn interaction_create(&self, ctx: serenity::all::Context, interaction: Interaction) {
-        if let Interaction::Command(command) = interaction {
-            let options = command.data.options();
-            let options_len = options.len();
-
-            info!("Received command: {:?}", command.data.name);
-            debug!(user_id = %command.user.id, channel_id = %command.channel_id, options_len, "Processing command interaction");
-
-            if let Err(why) = match command.data.name.as_str() {
-                "join" => {
-                    crate::commands::join::run(&ctx, &command, &self.voicevox_client, &self.voice_manager).await
-                },
-                "leave" => {
-                    crate::commands::leave::run(&ctx, &command, &self.voice_manager).await
-                },
-                "dictionary" => {
-                    crate::commands::dictionary::run(&ctx, &command, &self.voicevox_client).await
+        match interaction {
+            Interaction::Command(command) => {
+                let options = command.data.options();
+                let options_len = options.len();
+
+                info!("Received command: {:?}", command.data.name);
+                debug!(user_id = %command.user.id, channel_id = %command.channel_id, options_len, "Processing command interaction");
+
+                if let Err(why) = match command.data.name.as_str() {
+                    "join" => {
+                        crate::commands::join::run(&ctx, &command, &self.voicevox_client, &self.voice_manager, &self.dictionary_index).await
+                    },
+                    "leave" => {
+                        crate::commands::leave::run(&ctx, &command, &self.pool, &self.voice_manager).await
+                    },
+                    "dictionary" => {
+                        crate::commands::dictionary::run(&ctx, &command, &self.pool, &self.voicevox_client, &self.voice_manager, &self.dictionary_index, &self.dictionary_prompts).await
+                    }
+                    "settings" => {
+                        crate::commands::settings::run(&ctx, &command, &self.pool).await
+                    }
+                    "reading" => {
+                        crate::commands::reading::run(&ctx, &command, &self.voicevox_client, &self.dictionary_index).await
+                    }
+                    "name" => {
+                        crate::commands::name::run(&ctx, &command, &self.pool).await
+                    }
+                    "ignore" => {
+                        crate::commands::ignore::run(&ctx, &command, &self.pool).await
+                    }
+                    "optout" => {
+                        crate::commands::optout::run(&ctx, &command, &self.pool).await
+                    }
+                    "forget-me" => {
+                        crate::commands::forget_me::run(&ctx, &command, &self.pool).await
+                    }
+                    "preset" => {
+                        crate::commands::preset::run(&ctx, &command, &self.pool, &self.voicevox_client).await
+                    }
+                    "status" => {
+                        crate::commands::status::run(&ctx, &command, &self.voicevox_client, &self.voice_manager, self.started_at).await
+                    }
+                    crate::commands::dictionary_form::COMMAND_NAME => {
+                        crate::commands::dictionary_form::run(&ctx, &command, &self.pool, &self.voicevox_client).await
+                    }
+                    _ => {
+                        warn!("Unknown command: {}", command.data.name);
+                        let data = CreateInteractionResponseMessage::new().content("不明なコマンドです");
+                        let builder = CreateInteractionResponse::Message(data);
+                        command.create_response(&ctx.http, builder).await.map_err(|e| anyhow::anyhow!(e))
+                    },
+                } {
+                    error!("Error during command execution: {:?}", why);
+                }
+            }
+            Interaction::Autocomplete(autocomplete) => {
+                let result = match autocomplete.data.name.as_str() {
+                    "dictionary" => crate::commands::dictionary::autocomplete(&ctx, &autocomplete, &self.pool, &self.dictionary_index).await,
+                    "preset" => crate::commands::preset::autocomplete(&ctx, &autocomplete, &self.pool, &self.voicevox_client).await,
+                    _ => Ok(()),
+                };
+
+                if let Err(why) = result {
+                    error!("Error during autocomplete: {:?}", why);
+                }
+            }
+            Interaction::Component(component) => {
+                debug!(user_id = %component.user.id, custom_id = %component.data.custom_id, "Processing component interaction");
+
+                let result = if self.dictionary_prompts.confirmations.handles(&component.data.custom_id) {
+                    crate::commands::dictionary::handle_confirm_component(&ctx, &component, &self.pool, &self.voicevox_client, &self.dictionary_index, &self.dictionary_prompts.confirmations).await
+                } else if component.data.custom_id.starts_with(crate::commands::dictionary::LIST_COMPONENT_PREFIX) {
+                    crate::commands::dictionary::handle_list_component(&ctx, &component, &self.pool).await
+                } else if component.data.custom_id.starts_with(crate::commands::dictionary_accent::ACCENT_COMPONENT_PREFIX) {
+                    crate::commands::dictionary_accent::handle_accent_component(&ctx, &component, &self.pool, &self.voicevox_client, &self.voice_manager, &self.dictionary_index, &self.dictionary_prompts.accent_editors).await
+                } else if component.data.custom_id.starts_with(crate::commands::dictionary_history::HISTORY_COMPONENT_PREFIX) {
+                    crate::commands::dictionary_history::handle_history_component(&ctx, &component, &self.pool).await
+                } else {
+                    Ok(())
+                };
+
+                if let Err(why) = result {
+                    error!("Error during component interaction: {:?}", why);
                 }
-                _ => {
-                    warn!("Unknown command: {}", command.data.name);
-                    let data = CreateInteractionResponseMessage::new().content("不明なコマンドです");
-                    let builder = CreateInteractionResponse::Message(data);
-                    command.create_response(&ctx.http, builder).await.map_err(|e| anyhow::anyhow!(e))
-                },
-            } {
-                error!("Error during command execution: {:?}", why);
             }
-        } else {
-            debug!("Received non-command interaction; ignoring");
+            Interaction::Modal(modal) => {
+                debug!(user_id = %modal.user.id, custom_id = %modal.data.custom_id, "Processing modal submission");
+
+                let result = if modal.data.custom_id == crate::commands::dictionary_form::FORM_MODAL_ID {
+                    crate::commands::dictionary_form::handle_modal(&ctx, &modal, &self.pool, &self.voicevox_client, &self.dictionary_index).await
+                } else {
+                    Ok(())
+                };
+
+                if let Err(why) = result {
+                    error!("Error during modal submission: {:?}", why);
+                }
+            }
+            _ => {
+                debug!("Received unsupported interaction; ignoring");
+            }
         }
     }
 }
 
-async fn init_app(voicevox_client: &VoicevoxClient) -> Result<()> {
+async fn init_app(pool: &SqlitePool, voicevox_client: &VoicevoxClient, dictionary_index: &DictionaryIndex) -> Result<()> {
     info!("Initializing application");
-    let pool = SqlitePool::connect("sqlite://discord.db").await?;
     match sqlx::query("DELETE FROM sub_channel")
-        .execute(&pool)
+        .execute(pool)
         .await
     {
         Ok(_) => {
@@ -207,15 +367,30 @@ async fn init_app(voicevox_client: &VoicevoxClient) -> Result<()> {
         task.await??;
     }
 
-    match tokio::fs::read_to_string("user_dict.json").await {
-        Ok(dict_data) => {
-            voicevox_client.import_dict(dict_data.as_str()).await?;
-            info!("Application initialized");
-            Ok(())
-        }
-        Err(e) => {
-            error!("Failed to read user_dict.json: {}", e);
-            Err(anyhow::anyhow!("Failed to initialize application"))
-        }
+    if let Err(e) = dictionary::seed_if_empty(pool, voicevox_client).await {
+        error!("Failed to import existing dictionary: {}", e);
+    }
+
+    if let Err(e) = dictionary_index.refresh(pool).await {
+        error!("Failed to load dictionary index: {}", e);
     }
-}
\ No newline at end of file
+
+    // エンジンへの同期に失敗しても、データベースの辞書は次回の同期で反映される
+    if let Err(e) = dictionary::sync_to_engine(pool, voicevox_client).await {
+        error!("Failed to synchronize dictionary to engine: {}", e);
+    }
+
+    if let Err(e) = preset::sync_to_engine(pool, voicevox_client).await {
+        error!("Failed to synchronize presets to engine: {}", e);
+    }
+
+    info!("Application initialized");
+    Ok(())
+}
+
+/// 再起動したエンジンに、データベースの全体の辞書とプリセットを反映し直す
+async fn resync_engine(pool: &SqlitePool, engine: &Engine) -> Result<()> {
+    dictionary::resync_engine(pool, engine).await?;
+    preset::resync_engine(pool, engine).await?;
+    Ok(())
+}
@@ -0,0 +1,193 @@
+use anyhow::{Context, Result};
+use serenity::all::{GuildId, Message, UserId};
+use sqlx::SqlitePool;
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuleKind {
+    /// このプレフィックスで始まるメッセージは読み上げない
+    Prefix,
+    /// このユーザーのメッセージは読み上げない
+    User,
+    /// このロールを持つユーザーのメッセージは読み上げない
+    Role,
+    /// このBOTのメッセージは読み上げる
+    Bot,
+    /// このWebhookのメッセージは読み上げる
+    Webhook,
+}
+
+impl RuleKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RuleKind::Prefix => "prefix",
+            RuleKind::User => "user",
+            RuleKind::Role => "role",
+            RuleKind::Bot => "bot",
+            RuleKind::Webhook => "webhook",
+        }
+    }
+}
+
+impl fmt::Display for RuleKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for RuleKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "prefix" => Ok(RuleKind::Prefix),
+            "user" => Ok(RuleKind::User),
+            "role" => Ok(RuleKind::Role),
+            "bot" => Ok(RuleKind::Bot),
+            "webhook" => Ok(RuleKind::Webhook),
+            _ => Err(anyhow::anyhow!("Unknown rule kind: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct IgnoreRules {
+    pub prefixes: Vec<String>,
+    pub users: HashSet<u64>,
+    pub roles: HashSet<u64>,
+    pub bots: HashSet<u64>,
+    pub webhooks: HashSet<u64>,
+}
+
+impl IgnoreRules {
+    pub async fn fetch(pool: &SqlitePool, guild_id: GuildId) -> Result<Self> {
+        let rows = sqlx::query_as::<_, (String, String)>("SELECT kind, value FROM ignore_rule WHERE guild_id = ? ORDER BY kind, value")
+            .bind(guild_id.get() as i64)
+            .fetch_all(pool)
+            .await
+            .context("Failed to fetch ignore rules")?;
+
+        let mut rules = IgnoreRules::default();
+        for (kind, value) in rows {
+            let Ok(kind) = kind.parse::<RuleKind>() else {
+                continue;
+            };
+            match kind {
+                RuleKind::Prefix => rules.prefixes.push(value),
+                RuleKind::User => rules.users.extend(value.parse::<u64>().ok()),
+                RuleKind::Role => rules.roles.extend(value.parse::<u64>().ok()),
+                RuleKind::Bot => rules.bots.extend(value.parse::<u64>().ok()),
+                RuleKind::Webhook => rules.webhooks.extend(value.parse::<u64>().ok()),
+            }
+        }
+
+        Ok(rules)
+    }
+
+    /// メッセージを読み上げるべきか判定する
+    pub fn allows(&self, msg: &Message, current_user_id: UserId) -> bool {
+        if msg.author.id == current_user_id {
+            return false;
+        }
+
+        if let Some(webhook_id) = msg.webhook_id {
+            if !self.webhooks.contains(&webhook_id.get()) {
+                return false;
+            }
+        } else if msg.author.bot && !self.bots.contains(&msg.author.id.get()) {
+            return false;
+        }
+
+        if self.users.contains(&msg.author.id.get()) {
+            return false;
+        }
+
+        if let Some(member) = &msg.member
+            && member.roles.iter().any(|role_id| self.roles.contains(&role_id.get()))
+        {
+            return false;
+        }
+
+        !self.prefixes.iter().any(|prefix| msg.content.starts_with(prefix.as_str()))
+    }
+}
+
+pub async fn add_rule(pool: &SqlitePool, guild_id: GuildId, kind: RuleKind, value: &str, created_by: UserId) -> Result<bool> {
+    let result = sqlx::query("INSERT OR IGNORE INTO ignore_rule (guild_id, kind, value, created_by) VALUES (?, ?, ?, ?)")
+        .bind(guild_id.get() as i64)
+        .bind(kind.as_str())
+        .bind(value)
+        .bind(created_by.get() as i64)
+        .execute(pool)
+        .await
+        .context("Failed to add ignore rule")?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// ルールを削除する
+///
+/// `created_by`を指定すると、そのユーザーが追加したルールだけを削除する
+pub async fn remove_rule(pool: &SqlitePool, guild_id: GuildId, kind: RuleKind, value: &str, created_by: Option<UserId>) -> Result<bool> {
+    let created_by = created_by.map(|user_id| user_id.get() as i64);
+    let result = sqlx::query("DELETE FROM ignore_rule WHERE guild_id = ? AND kind = ? AND value = ? AND (? IS NULL OR created_by = ?)")
+        .bind(guild_id.get() as i64)
+        .bind(kind.as_str())
+        .bind(value)
+        .bind(created_by)
+        .bind(created_by)
+        .execute(pool)
+        .await
+        .context("Failed to remove ignore rule")?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// ルールを追加したユーザーを取得する。ルールが無ければ`None`を返す
+pub async fn rule_creator(pool: &SqlitePool, guild_id: GuildId, kind: RuleKind, value: &str) -> Result<Option<Option<UserId>>> {
+    let created_by = sqlx::query_scalar::<_, Option<i64>>("SELECT created_by FROM ignore_rule WHERE guild_id = ? AND kind = ? AND value = ?")
+        .bind(guild_id.get() as i64)
+        .bind(kind.as_str())
+        .bind(value)
+        .fetch_optional(pool)
+        .await
+        .context("Failed to fetch ignore rule")?;
+
+    Ok(created_by.map(|created_by| created_by.map(|user_id| UserId::new(user_id as u64))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::memory_pool;
+
+    #[tokio::test]
+    async fn target_cannot_remove_self_rule_added_by_moderator() {
+        let pool = memory_pool().await;
+        let guild_id = GuildId::new(1);
+        let (user, moderator) = (UserId::new(10), UserId::new(20));
+        let value = user.to_string();
+        add_rule(&pool, guild_id, RuleKind::User, &value, moderator).await.unwrap();
+
+        assert_eq!(rule_creator(&pool, guild_id, RuleKind::User, &value).await.unwrap(), Some(Some(moderator)));
+        assert!(!remove_rule(&pool, guild_id, RuleKind::User, &value, Some(user)).await.unwrap());
+        assert!(IgnoreRules::fetch(&pool, guild_id).await.unwrap().users.contains(&user.get()));
+
+        assert!(remove_rule(&pool, guild_id, RuleKind::User, &value, None).await.unwrap());
+        assert_eq!(rule_creator(&pool, guild_id, RuleKind::User, &value).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn user_can_remove_own_self_rule() {
+        let pool = memory_pool().await;
+        let guild_id = GuildId::new(1);
+        let user = UserId::new(10);
+        let value = user.to_string();
+        add_rule(&pool, guild_id, RuleKind::User, &value, user).await.unwrap();
+
+        assert!(remove_rule(&pool, guild_id, RuleKind::User, &value, Some(user)).await.unwrap());
+        assert!(IgnoreRules::fetch(&pool, guild_id).await.unwrap().users.is_empty());
+    }
+}
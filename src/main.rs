@@ -3,25 +3,24 @@ mod config;
 mod error;
 mod voice;
 mod commands;
+mod confirmation;
+mod database;
+mod ignore;
+mod permissions;
 mod embed;
-mod cache;
 
+use crate::commands::status::ShardManagerContainer;
 use crate::config::Config;
 use crate::handler::Handler;
-use crate::voice::voicevox::client::Client as VoicevoxClient;
 
 use anyhow::{Context, Result};
 use serenity::{
     Client,
 };
 use songbird::SerenityInit;
-use std::{
-    env,
-    path::Path,
-};
+use std::env;
 use serenity::all::GatewayIntents;
-use sqlx::SqlitePool;
-use tracing::{debug, info, warn, error};
+use tracing::{debug, info, error};
 use tracing_subscriber::{fmt, EnvFilter};
 
 #[tokio::main]
@@ -55,10 +54,19 @@ async fn main() -> Result<()> {
     info!("Database URL: {}", config.database_url);
     info!("Discord Token: {}", if config.discord_token.is_empty() { "(empty)" } else { "(set)" });
     info!("Guild ID: {}", config.guild_id);
-    info!("Voicevox URL: {}", config.voicevox_url);
+    for endpoint in &config.voicevox_endpoints {
+        info!("Voicevox URL: {}", endpoint);
+    }
     info!("Default Speaker ID: {}", config.default_speaker_id);
+    info!("Warm-up Speaker IDs: {:?}", config.warmup_speaker_ids);
     info!("Default Speed Scale: {}", config.default_speed_scale);
     info!("Request Timeout (secs): {}", config.request_timeout_secs);
+    info!("Idle Timeout (secs): {}", config.idle_timeout_secs);
+    info!("Retry Max Attempts: {}", config.retry_max_attempts);
+    info!("Retry Base Delay (ms): {}", config.retry_base_delay_ms);
+    info!("Circuit Failure Threshold: {}", config.circuit_failure_threshold);
+    info!("Circuit Open (secs): {}", config.circuit_open_secs);
+    info!("Health Check Interval (secs): {}", config.health_check_interval_secs);
     info!("-----------------------");
 
     info!("Starting bot...");
@@ -77,6 +85,8 @@ async fn main() -> Result<()> {
         .context("Failed to create client")?;
     info!("Created serenity client");
 
+    client.data.write().await.insert::<ShardManagerContainer>(client.shard_manager.clone());
+
     tokio::select! {
         res = client.start() => {
             info!("Discord client stopped: {:?}", res);
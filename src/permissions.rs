@@ -0,0 +1,193 @@
+use crate::embed;
+use anyhow::{Context as _, Result};
+use serenity::{
+    all::{GuildId, Permissions, RoleId, UserId},
+    builder::{CreateInteractionResponse, CreateInteractionResponseMessage},
+    model::application::CommandInteraction,
+    prelude::*,
+};
+use sqlx::SqlitePool;
+use tracing::{debug, warn};
+
+/// サーバーごとにロールを設定できる操作
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    /// 辞書の単語の追加・編集・削除とインポート
+    DictionaryEdit,
+    /// 辞書のリセット・復元・取り消しと置き換えでのインポート
+    DictionaryManage,
+    /// サーバー設定・声のプリセット・読み上げルールの変更
+    Settings,
+    /// `/leave`でVCから切断する
+    Disconnect,
+}
+
+impl Permission {
+    pub const ALL: [Permission; 4] = [Permission::DictionaryEdit, Permission::DictionaryManage, Permission::Settings, Permission::Disconnect];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Permission::DictionaryEdit => "dictionary_edit",
+            Permission::DictionaryManage => "dictionary_manage",
+            Permission::Settings => "settings",
+            Permission::Disconnect => "disconnect",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Permission::DictionaryEdit => "辞書の編集",
+            Permission::DictionaryManage => "辞書のリセット・復元",
+            Permission::Settings => "サーバー設定・読み上げルールの変更",
+            Permission::Disconnect => "VCからの切断",
+        }
+    }
+
+    /// ロールが設定されていない場合に必要なDiscordの権限
+    pub fn default_permissions(&self) -> Permissions {
+        match self {
+            Permission::DictionaryEdit => Permissions::empty(),
+            Permission::DictionaryManage | Permission::Settings => Permissions::MANAGE_GUILD,
+            Permission::Disconnect => Permissions::MOVE_MEMBERS,
+        }
+    }
+
+    /// ロールが設定されていない場合に必要な権限の表示名
+    pub fn default_label(&self) -> &'static str {
+        match self {
+            Permission::DictionaryEdit => "なし",
+            Permission::DictionaryManage | Permission::Settings => "サーバー管理",
+            Permission::Disconnect => "メンバーを移動",
+        }
+    }
+}
+
+impl std::str::FromStr for Permission {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Permission::ALL
+            .into_iter()
+            .find(|permission| permission.as_str() == s)
+            .ok_or_else(|| anyhow::anyhow!("Unknown permission: {}", s))
+    }
+}
+
+/// BOTのオーナー(チームの場合はメンバー)かどうか
+pub async fn is_bot_owner(ctx: &Context, user_id: UserId) -> Result<bool> {
+    let info = ctx.http
+        .get_current_application_info()
+        .await
+        .context("Failed to get application info")?;
+
+    if info.owner.is_some_and(|owner| owner.id == user_id) {
+        return Ok(true);
+    }
+
+    Ok(info.team.is_some_and(|team| team.members.iter().any(|member| member.user.id == user_id)))
+}
+
+/// サーバーの管理権限を持っているか。ロールの設定に関係なくすべての操作ができる
+pub fn is_guild_manager(interaction: &CommandInteraction) -> bool {
+    interaction
+        .member
+        .as_ref()
+        .and_then(|member| member.permissions)
+        .is_some_and(|permissions| permissions.administrator() || permissions.manage_guild())
+}
+
+pub async fn fetch_roles(pool: &SqlitePool, guild_id: GuildId, permission: Permission) -> Result<Vec<RoleId>> {
+    let roles = sqlx::query_scalar::<_, i64>("SELECT role_id FROM permission_role WHERE guild_id = ? AND permission = ? ORDER BY role_id")
+        .bind(guild_id.get() as i64)
+        .bind(permission.as_str())
+        .fetch_all(pool)
+        .await
+        .context("Failed to fetch permission roles")?;
+
+    Ok(roles.into_iter().map(|role_id| RoleId::new(role_id as u64)).collect())
+}
+
+/// ロールを追加する。既に追加されている場合は`false`を返す
+pub async fn add_role(pool: &SqlitePool, guild_id: GuildId, permission: Permission, role_id: RoleId) -> Result<bool> {
+    let result = sqlx::query("INSERT OR IGNORE INTO permission_role (guild_id, permission, role_id) VALUES (?, ?, ?)")
+        .bind(guild_id.get() as i64)
+        .bind(permission.as_str())
+        .bind(role_id.get() as i64)
+        .execute(pool)
+        .await
+        .context("Failed to add permission role")?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn remove_role(pool: &SqlitePool, guild_id: GuildId, permission: Permission, role_id: RoleId) -> Result<bool> {
+    let result = sqlx::query("DELETE FROM permission_role WHERE guild_id = ? AND permission = ? AND role_id = ?")
+        .bind(guild_id.get() as i64)
+        .bind(permission.as_str())
+        .bind(role_id.get() as i64)
+        .execute(pool)
+        .await
+        .context("Failed to remove permission role")?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// コマンドを実行したメンバーが操作を許可されているか
+///
+/// ロールが設定されていればそのどれかを持っている必要があり、無ければDiscordの権限で判定する。
+/// ギルド外ではサーバーごとの操作は無いので許可する
+pub async fn has_permission(pool: &SqlitePool, interaction: &CommandInteraction, permission: Permission) -> Result<bool> {
+    let (Some(guild_id), Some(member)) = (interaction.guild_id, interaction.member.as_ref()) else {
+        return Ok(true);
+    };
+    if is_guild_manager(interaction) {
+        return Ok(true);
+    }
+
+    let roles = fetch_roles(pool, guild_id, permission).await?;
+    if roles.is_empty() {
+        let member_permissions = member.permissions.unwrap_or_else(Permissions::empty);
+        return Ok(member_permissions.contains(permission.default_permissions()));
+    }
+
+    Ok(member.roles.iter().any(|role_id| roles.contains(role_id)))
+}
+
+/// 権限を確認し、許可されていなければ本人にだけ見えるエラーを返して`false`を返す
+///
+/// 応答を送るので、`defer`より前に呼ぶ
+pub async fn require(ctx: &Context, pool: &SqlitePool, interaction: &CommandInteraction, permission: Permission) -> Result<bool> {
+    let allowed = match has_permission(pool, interaction, permission).await {
+        Ok(allowed) => allowed,
+        Err(e) => {
+            warn!("Failed to check permission: {}", e);
+            deny(ctx, interaction, &format!("権限の確認に失敗しました: {}", e)).await?;
+            return Ok(false);
+        }
+    };
+    if allowed {
+        return Ok(true);
+    }
+
+    debug!(user_id = %interaction.user.id, permission = permission.as_str(), "Denied command");
+    let roles = match interaction.guild_id {
+        Some(guild_id) => fetch_roles(pool, guild_id, permission).await.unwrap_or_default(),
+        None => Vec::new(),
+    };
+    let required = if roles.is_empty() {
+        format!("Discordの「{}」権限", permission.default_label())
+    } else {
+        roles.iter().map(|role_id| format!("<@&{}>", role_id)).collect::<Vec<_>>().join(", ")
+    };
+    let description = format!("「{}」を行う権限がありません\n\n**必要なロール・権限:** {}", permission.label(), required);
+    deny(ctx, interaction, &description).await?;
+    Ok(false)
+}
+
+/// 権限が無いことを本人にだけ見えるメッセージで返す
+pub async fn deny(ctx: &Context, interaction: &CommandInteraction, description: &str) -> Result<()> {
+    let embed = embed::simple_embed(ctx, "権限がありません", description, 0xff0000).await;
+    let response = CreateInteractionResponseMessage::new().embed(embed).ephemeral(true);
+    interaction.create_response(&ctx.http, CreateInteractionResponse::Message(response)).await?;
+    Ok(())
+}
@@ -0,0 +1,206 @@
+use crate::embed;
+use crate::voice::manager::VoiceManager;
+use serenity::{
+    all::{ChannelId, Context, GuildId},
+    async_trait,
+    builder::CreateMessage,
+};
+use songbird::events::{
+    context_data::{DisconnectKind, DisconnectReason},
+    Event, EventContext, EventHandler as VoiceEventHandler,
+};
+use songbird::model::CloseCode;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+};
+use std::time::Duration;
+use tracing::{debug, error, info, info_span, warn, Instrument};
+
+const MAX_RECONNECT_ATTEMPTS: u32 = 5;
+const INITIAL_BACKOFF_SECS: u64 = 2;
+const MAX_BACKOFF_SECS: u64 = 60;
+/// 切断のクローズコードを受けてから、移動先のチャンネルが届くのを待つ時間
+const MOVE_GRACE_SECS: u64 = 2;
+
+/// Songbirdのドライバーイベントを監視し、切断時に再接続を試みる
+///
+/// モデレーターに切断された場合は再接続せずに終了し、別のチャンネルに移動された場合は移動先に接続し直す
+#[derive(Clone)]
+pub struct ConnectionMonitor {
+    ctx: Context,
+    voice_manager: VoiceManager,
+    guild_id: GuildId,
+    /// 移動されると移動先に変わるので、クローンした監視とも共有する
+    voice_channel_id: Arc<Mutex<ChannelId>>,
+    message_channel_id: ChannelId,
+    reconnecting: Arc<AtomicBool>,
+}
+
+impl ConnectionMonitor {
+    pub fn new(ctx: Context, voice_manager: VoiceManager, guild_id: GuildId, voice_channel_id: ChannelId, message_channel_id: ChannelId) -> Self {
+        Self {
+            ctx,
+            voice_manager,
+            guild_id,
+            voice_channel_id: Arc::new(Mutex::new(voice_channel_id)),
+            message_channel_id,
+            reconnecting: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    fn voice_channel_id(&self) -> ChannelId {
+        *self.voice_channel_id.lock().unwrap()
+    }
+
+    /// 別のチャンネルに移動されたら、再接続や読み上げの対象を移動先に変える
+    async fn follow_move(&self, voice_channel_id: ChannelId) {
+        {
+            let mut current = self.voice_channel_id.lock().unwrap();
+            if *current == voice_channel_id {
+                return;
+            }
+            *current = voice_channel_id;
+        }
+        info!(%voice_channel_id, "Following move to another voice channel");
+        if let Err(e) = self.voice_manager.move_voice_channel(self.guild_id, voice_channel_id).await {
+            error!("Failed to update voice channel record: {}", e);
+        }
+    }
+
+    async fn reconnect(self) {
+        for attempt in 1..=MAX_RECONNECT_ATTEMPTS {
+            let backoff = backoff_duration(attempt);
+            info!(attempt, backoff_secs = backoff.as_secs(), "Waiting before reconnecting to voice channel");
+            tokio::time::sleep(backoff).await;
+
+            let manager = match songbird::get(&self.ctx).await {
+                Some(manager) => manager,
+                None => {
+                    error!("Failed to get songbird manager");
+                    break;
+                }
+            };
+
+            // /leaveなどで既にセッションが破棄されている場合は再接続しない
+            let Some(call) = manager.get(self.guild_id) else {
+                info!("Voice session was removed while reconnecting; giving up");
+                self.reconnecting.store(false, Ordering::SeqCst);
+                return;
+            };
+
+            // 別のチャンネルに移動されていれば、元のチャンネルではなく今いるチャンネルに接続する
+            let voice_channel_id = call.lock().await.current_channel().map_or_else(|| self.voice_channel_id(), |channel_id| ChannelId::new(channel_id.0.get()));
+            match manager.join(self.guild_id, voice_channel_id).await {
+                Ok(_) => {
+                    info!(attempt, %voice_channel_id, "Reconnected to voice channel");
+                    self.follow_move(voice_channel_id).await;
+                    self.reconnecting.store(false, Ordering::SeqCst);
+                    return;
+                }
+                Err(e) => {
+                    warn!(attempt, "Failed to reconnect to voice channel: {}", e);
+                }
+            }
+        }
+
+        error!("Giving up reconnecting to voice channel after {} attempts", MAX_RECONNECT_ATTEMPTS);
+        self.end("VCとの接続が切れ、再接続にも失敗しました。\n再度 `/join` を実行してください。").await;
+        self.reconnecting.store(false, Ordering::SeqCst);
+    }
+
+    /// Discordは切断されたときと別のチャンネルに移動されたときに同じクローズコードを送る
+    ///
+    /// 移動の場合はSongbirdが移動先に接続し直すので、少し待ってもチャンネルに入っていなければ切断されたものとして終了する
+    async fn handle_disconnected(self) {
+        tokio::time::sleep(Duration::from_secs(MOVE_GRACE_SECS)).await;
+
+        let current_channel = match songbird::get(&self.ctx).await.and_then(|manager| manager.get(self.guild_id)) {
+            Some(call) => call.lock().await.current_channel(),
+            None => {
+                info!("Voice session was already removed");
+                self.reconnecting.store(false, Ordering::SeqCst);
+                return;
+            }
+        };
+
+        match current_channel {
+            Some(channel_id) => {
+                info!(channel_id = channel_id.0.get(), "Moved to another voice channel; not reconnecting");
+                self.follow_move(ChannelId::new(channel_id.0.get())).await;
+            }
+            None => {
+                info!("Disconnected from voice channel by Discord; ending session");
+                self.end("VCから切断されました。\n再度読み上げるには `/join` を実行してください。").await;
+            }
+        }
+        self.reconnecting.store(false, Ordering::SeqCst);
+    }
+
+    /// セッションを片付け、読み上げ先のテキストチャンネルに知らせる
+    async fn end(&self, description: &str) {
+        if let Err(e) = self.voice_manager.disconnect(&self.ctx, self.guild_id, self.message_channel_id).await {
+            error!("Failed to clean up voice session: {}", e);
+        }
+
+        let embed = embed::simple_embed(&self.ctx, "切断されました", description, 0xff0000).await;
+
+        if let Err(e) = self.message_channel_id.send_message(&self.ctx.http, CreateMessage::new().embed(embed)).await {
+            error!("Failed to send disconnect notice: {}", e);
+        }
+    }
+}
+
+#[async_trait]
+impl VoiceEventHandler for ConnectionMonitor {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        match ctx {
+            EventContext::DriverDisconnect(data) => {
+                let span = info_span!("voice_reconnect", guild_id = %self.guild_id, voice_channel_id = %self.voice_channel_id());
+                let guard = span.enter();
+
+                match data.reason {
+                    None | Some(DisconnectReason::Requested) | Some(DisconnectReason::AttemptDiscarded) => {
+                        debug!(kind = ?data.kind, "Driver disconnected by request; not reconnecting");
+                        return None;
+                    }
+                    Some(reason) => {
+                        warn!(kind = ?data.kind, ?reason, "Voice driver disconnected");
+                    }
+                }
+
+                if data.kind == DisconnectKind::Connect {
+                    // 初回接続の失敗は/joinの呼び出し元でエラーになる
+                    debug!("Initial connection failed; not reconnecting");
+                    return None;
+                }
+
+                if self.reconnecting.swap(true, Ordering::SeqCst) {
+                    debug!("Reconnect already in progress");
+                    return None;
+                }
+
+                drop(guard);
+                // 切断や移動はモデレーターの操作なので、元のチャンネルに再接続しない
+                if matches!(data.reason, Some(DisconnectReason::WsClosed(Some(CloseCode::Disconnected)))) {
+                    tokio::spawn(self.clone().handle_disconnected().instrument(span));
+                } else {
+                    tokio::spawn(self.clone().reconnect().instrument(span));
+                }
+            }
+            EventContext::DriverReconnect(data) => {
+                info!(guild_id = %self.guild_id, channel_id = ?data.channel_id, "Voice driver reconnected");
+                if let Some(channel_id) = data.channel_id {
+                    self.follow_move(ChannelId::new(channel_id.0.get())).await;
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+fn backoff_duration(attempt: u32) -> Duration {
+    let secs = INITIAL_BACKOFF_SECS.saturating_mul(1 << (attempt - 1).min(16));
+    Duration::from_secs(secs.min(MAX_BACKOFF_SECS))
+}
@@ -1,26 +1,68 @@
+use crate::config::Config;
+use crate::database::GuildSettings;
+use crate::embed;
+use crate::voice::connection::ConnectionMonitor;
 use anyhow::Result;
+use serenity::{
+    all::{ChannelId, Context},
+    builder::CreateMessage,
+    model::id::{GuildId, UserId},
+};
+use songbird::{CoreEvent, Event};
 use sqlx::SqlitePool;
-use tracing::{debug, info, warn, error, instrument};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn, error, info_span, Instrument};
 
+const IDLE_CHECK_INTERVAL_SECS: u64 = 15;
+const IDLE_WARNING_SECS: u64 = 60;
+
+struct VoiceSession {
+    last_activity: Instant,
+    last_author: Option<(UserId, Instant)>,
+    message_channel_id: ChannelId,
+    /// 最後に停止を知らせたエンジンの停止の回数
+    notified_outage: Option<u64>,
+    idle_task: JoinHandle<()>,
+}
+
+#[derive(Clone)]
 pub struct VoiceManager {
     pub pool: SqlitePool,
+    idle_timeout_secs: u64,
+    sessions: Arc<Mutex<HashMap<GuildId, VoiceSession>>>,
 }
 
 impl VoiceManager {
-    pub fn new(pool: SqlitePool) -> Result<Self> {
-        Ok(Self { pool })
+    pub fn new(pool: SqlitePool, config: &Config) -> Result<Self> {
+        Ok(Self {
+            pool,
+            idle_timeout_secs: config.idle_timeout_secs,
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+        })
     }
 
     pub async fn connect(&self, ctx: &serenity::all::Context, guild_id: serenity::model::id::GuildId, message_channel_id: serenity::all::ChannelId, voice_channel_id: serenity::model::id::ChannelId) -> Result<()> {
-        let manager = songbird::get(&ctx)
+        let manager = songbird::get(ctx)
             .await
             .ok_or_else(|| anyhow::anyhow!("Failed to get songbird manager"))?;
 
-        let _handler = manager.join(guild_id, voice_channel_id).await.map_err(|e| {
+        let handler = manager.join(guild_id, voice_channel_id).await.map_err(|e| {
             error!("Failed to connect to voice channel: {}", e);
             anyhow::anyhow!("Failed to connect to voice channel: {}", e)
         })?;
 
+        {
+            let mut call = handler.lock().await;
+            // 再度/joinされた場合にイベントが重複しないようにする
+            call.remove_all_global_events();
+            let monitor = ConnectionMonitor::new(ctx.clone(), self.clone(), guild_id, voice_channel_id, message_channel_id);
+            call.add_global_event(Event::Core(CoreEvent::DriverDisconnect), monitor.clone());
+            call.add_global_event(Event::Core(CoreEvent::DriverReconnect), monitor);
+        }
+
         let voice_channel_url = format!("https://discord.com/channels/{}/{}", guild_id.get(), voice_channel_id.get());
         info!("Connected to voice channel {}", voice_channel_url);
 
@@ -38,15 +80,19 @@ impl VoiceManager {
             })?;
 
         info!("Recorded subscribe channel in the database");
+
+        self.start_session(ctx, guild_id, message_channel_id);
         Ok(())
     }
 
     pub async fn disconnect(&self, ctx: &serenity::all::Context, guild_id: serenity::model::id::GuildId, channel_id: serenity::all::ChannelId) -> Result<()> {
-        let manager = songbird::get(&ctx)
+        self.end_session(guild_id);
+
+        let manager = songbird::get(ctx)
             .await
             .ok_or_else(|| anyhow::anyhow!("Failed to get songbird manager"))?;
 
-        let _handler = manager.remove(guild_id).await.map_err(|e| {
+        manager.remove(guild_id).await.map_err(|e| {
             error!("Failed to disconnect from voice channel: {}", e);
             anyhow::anyhow!("Failed to disconnect from voice channel: {}", e)
         })?;
@@ -65,4 +111,176 @@ impl VoiceManager {
         info!("Remove voice channel record from database");
         Ok(())
     }
+
+    /// 別のボイスチャンネルに移動されたとき、記録している接続先を移動先に変える
+    pub async fn move_voice_channel(&self, guild_id: GuildId, voice_channel_id: ChannelId) -> Result<()> {
+        sqlx::query("UPDATE sub_channel SET voice_channel_id = ? WHERE guild_id = ?")
+            .bind(voice_channel_id.get() as i64)
+            .bind(guild_id.get() as i64)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to update voice channel record: {}", e))?;
+
+        info!("Updated voice channel record in the database");
+        Ok(())
+    }
+
+    /// 読み上げがあったことを記録し、アイドルタイマーをリセットする
+    pub fn touch(&self, guild_id: GuildId) {
+        if let Some(session) = self.sessions.lock().unwrap().get_mut(&guild_id) {
+            session.last_activity = Instant::now();
+        }
+    }
+
+    /// 発言者の名前を読み上げるか判定し、最後の発言者を記録する
+    ///
+    /// 同じ人が`window`以内に続けて発言した場合は名前を省略する
+    pub fn should_read_name(&self, guild_id: GuildId, user_id: UserId, window: Duration) -> bool {
+        let mut sessions = self.sessions.lock().unwrap();
+        let Some(session) = sessions.get_mut(&guild_id) else {
+            return true;
+        };
+
+        let now = Instant::now();
+        let read_name = !matches!(
+            session.last_author,
+            Some((last_user_id, at)) if last_user_id == user_id && now.duration_since(at) < window
+        );
+        session.last_author = Some((user_id, now));
+        read_name
+    }
+
+    /// エンジンが止まっていることを読み上げ先のテキストチャンネルに知らせる。同じ停止では1回だけ送る
+    pub async fn notify_engine_outage(&self, ctx: &Context, guild_id: GuildId, outage: u64) {
+        let message_channel_id = {
+            let mut sessions = self.sessions.lock().unwrap();
+            let Some(session) = sessions.get_mut(&guild_id) else {
+                return;
+            };
+            if session.notified_outage == Some(outage) {
+                return;
+            }
+            session.notified_outage = Some(outage);
+            session.message_channel_id
+        };
+
+        info!("Notifying VOICEVOX outage");
+        let embed = embed::simple_embed(
+            ctx,
+            "読み上げを一時停止しています",
+            "VOICEVOXエンジンが応答しないため、読み上げを止めています。復旧すると自動で再開します",
+            0xffaa00,
+        )
+            .await;
+        if let Err(e) = message_channel_id.send_message(&ctx.http, CreateMessage::new().embed(embed)).await {
+            error!("Failed to send engine outage notice: {}", e);
+        }
+    }
+
+    /// ボイスチャンネルに接続しているギルド
+    pub fn guild_ids(&self) -> Vec<GuildId> {
+        self.sessions.lock().unwrap().keys().copied().collect()
+    }
+
+    fn idle_duration(&self, guild_id: GuildId) -> Option<Duration> {
+        self.sessions.lock().unwrap().get(&guild_id).map(|session| session.last_activity.elapsed())
+    }
+
+    fn start_session(&self, ctx: &Context, guild_id: GuildId, message_channel_id: ChannelId) {
+        self.end_session(guild_id);
+
+        let span = info_span!("idle_watcher", guild_id = %guild_id);
+        let idle_task = tokio::spawn(self.clone().watch_idle(ctx.clone(), guild_id, message_channel_id).instrument(span));
+
+        self.sessions.lock().unwrap().insert(guild_id, VoiceSession {
+            last_activity: Instant::now(),
+            last_author: None,
+            message_channel_id,
+            notified_outage: None,
+            idle_task,
+        });
+    }
+
+    fn end_session(&self, guild_id: GuildId) {
+        if let Some(session) = self.sessions.lock().unwrap().remove(&guild_id) {
+            // アイドルタイマー自身から切断された場合は中断しない
+            if tokio::task::try_id() != Some(session.idle_task.id()) {
+                session.idle_task.abort();
+            }
+        }
+    }
+
+    async fn idle_timeout(&self, guild_id: GuildId) -> Option<Duration> {
+        let secs = match GuildSettings::fetch(&self.pool, guild_id).await {
+            Ok(settings) => settings.idle_timeout_secs.map_or(self.idle_timeout_secs, |secs| secs.max(0) as u64),
+            Err(e) => {
+                warn!("Failed to fetch idle timeout: {}", e);
+                self.idle_timeout_secs
+            }
+        };
+
+        (secs > 0).then(|| Duration::from_secs(secs))
+    }
+
+    async fn is_playing(ctx: &Context, guild_id: GuildId) -> bool {
+        let Some(manager) = songbird::get(ctx).await else {
+            return false;
+        };
+        let Some(call) = manager.get(guild_id) else {
+            return false;
+        };
+        !call.lock().await.queue().is_empty()
+    }
+
+    async fn watch_idle(self, ctx: Context, guild_id: GuildId, message_channel_id: ChannelId) {
+        let mut warned = false;
+
+        loop {
+            tokio::time::sleep(Duration::from_secs(IDLE_CHECK_INTERVAL_SECS)).await;
+
+            if Self::is_playing(&ctx, guild_id).await {
+                self.touch(guild_id);
+            }
+
+            let Some(idle) = self.idle_duration(guild_id) else {
+                return;
+            };
+            let Some(timeout) = self.idle_timeout(guild_id).await else {
+                warned = false;
+                continue;
+            };
+
+            if idle >= timeout {
+                info!(idle_secs = idle.as_secs(), "Leaving voice channel due to inactivity");
+                if let Err(e) = self.disconnect(&ctx, guild_id, message_channel_id).await {
+                    error!("Failed to disconnect idle voice session: {}", e);
+                    return;
+                }
+                let embed = embed::simple_embed(&ctx, "切断しました", "一定時間読み上げがなかったため、VCから切断しました", 0xff0000).await;
+                if let Err(e) = message_channel_id.send_message(&ctx.http, CreateMessage::new().embed(embed)).await {
+                    error!("Failed to send idle disconnect notice: {}", e);
+                }
+                return;
+            }
+
+            let warning_at = timeout.saturating_sub(Duration::from_secs(IDLE_WARNING_SECS));
+            if idle < warning_at {
+                warned = false;
+            } else if !warned {
+                warned = true;
+                debug!(idle_secs = idle.as_secs(), "Sending idle warning");
+                let remaining = timeout.saturating_sub(idle).as_secs();
+                let embed = embed::simple_embed(
+                    &ctx,
+                    "まもなく切断します",
+                    &format!("読み上げがないため、約{}秒後にVCから切断します", remaining),
+                    0xffaa00,
+                )
+                    .await;
+                if let Err(e) = message_channel_id.send_message(&ctx.http, CreateMessage::new().embed(embed)).await {
+                    error!("Failed to send idle warning: {}", e);
+                }
+            }
+        }
+    }
 }
@@ -1,3 +1,4 @@
+pub mod connection;
 pub mod manager;
 pub mod playback;
 pub mod voicevox;
\ No newline at end of file
@@ -1,8 +1,10 @@
-use crate::config::Config;
+use crate::voice::manager::VoiceManager;
 use crate::voice::voicevox::client::Client as VoicevoxClient;
+use crate::voice::voicevox::dictionary::{DictionaryIndex, DictionaryWord, GuildDictionary};
+use crate::voice::voicevox::preset::{self, VoicePreset};
 use anyhow::Result;
 use serenity::{
-    all::{Context, GuildId},
+    all::{Context, GuildId, UserId},
     async_trait
 };
 use songbird::{
@@ -11,7 +13,7 @@ use songbird::{
 };
 use std::path::PathBuf;
 use tokio::fs;
-use tracing::{debug, info, warn, error, instrument};
+use tracing::{debug, warn};
 
 struct DeleteFileOnEnd {
     path: PathBuf,
@@ -20,33 +22,74 @@ struct DeleteFileOnEnd {
 #[async_trait]
 impl VoiceEventHandler for DeleteFileOnEnd {
     async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
-        match ctx {
-            EventContext::Track(_) => {
-                if let Err(e) = fs::remove_file(&self.path).await {
-                    warn!("Failed to deleted temp file: {} ({e})", self.path.display());
-                } else {
-                    debug!("Deleted temp file: {}", self.path.display());
-                }
+        if let EventContext::Track(_) = ctx {
+            if let Err(e) = fs::remove_file(&self.path).await {
+                warn!("Failed to deleted temp file: {} ({e})", self.path.display());
+            } else {
+                debug!("Deleted temp file: {}", self.path.display());
             }
-            _ => {}
         }
         None
     }
 }
 
-pub async fn play(ctx: &Context, voicevox_client: &VoicevoxClient, guild_id: GuildId, text: String) -> Result<()> {
-    let manager = songbird::get(ctx).await
-        .ok_or_else(|| anyhow::anyhow!("Songbirdマネージャーの取得に失敗しました"))?;
-    let call = manager.get(guild_id)
-        .ok_or_else(|| anyhow::anyhow!("ボイスチャンネルに接続されていません"))?;
+/// 読み上げに使う話者
+pub const SPEAKER_ID: u8 = 8;
+pub const SPEED_SCALE: f64 = 1.1;
+
+/// `author`が声のプリセットを選んでいれば、そのプリセットで読み上げる
+pub async fn play(ctx: &Context, voicevox_client: &VoicevoxClient, voice_manager: &VoiceManager, dictionary_index: &DictionaryIndex, guild_id: GuildId, author: Option<UserId>, text: String) -> Result<()> {
+    let (text, replaced) = dictionary_index.guild_dictionary(guild_id).replace(&text);
+
+    let selected = match author {
+        Some(user_id) => preset::selected(&voice_manager.pool, guild_id, user_id).await.unwrap_or_else(|e| {
+            warn!("Failed to fetch selected preset: {}", e);
+            None
+        }),
+        None => None,
+    };
+    if let Some(selected) = selected
+        && let Ok(speaker) = u8::try_from(selected.style_id)
+    {
+        match voicevox_client.create_audio_query_from_preset(&text, &VoicePreset::engine_name(guild_id, &selected.name)).await {
+            Ok(audio_query) => {
+                let audio_query = apply_accents(voicevox_client, audio_query, &replaced, speaker).await;
+                return play_audio_query(ctx, voicevox_client, voice_manager, guild_id, &audio_query, speaker).await;
+            }
+            // エンジンにプリセットが無い場合などは既定の声で読み上げる
+            Err(e) => warn!(preset = %selected.name, "Failed to create audio query from preset; using default voice: {}", e),
+        }
+    }
 
     let audio_query = voicevox_client
-        .create_audio_query(&text, 8, 1.1)
+        .create_audio_query(&text, SPEAKER_ID, SPEED_SCALE)
         .await
         .map_err(|e| anyhow::anyhow!("音声クエリの生成に失敗しました: {}", e))?;
+    let audio_query = apply_accents(voicevox_client, audio_query, &replaced, SPEAKER_ID).await;
+
+    play_audio_query(ctx, voicevox_client, voice_manager, guild_id, &audio_query, SPEAKER_ID).await
+}
+
+/// サーバーの辞書のアクセントを反映する。失敗した場合はエンジンのアクセントのまま読み上げる
+async fn apply_accents(voicevox_client: &VoicevoxClient, audio_query: String, replaced: &[DictionaryWord], speaker: u8) -> String {
+    match GuildDictionary::apply_accents(voicevox_client, &audio_query, replaced, speaker).await {
+        Ok(audio_query) => audio_query,
+        Err(e) => {
+            warn!("Failed to apply guild dictionary accents: {}", e);
+            audio_query
+        }
+    }
+}
+
+/// 作成済みの音声クエリを`speaker`の声で合成して再生キューに入れる
+pub async fn play_audio_query(ctx: &Context, voicevox_client: &VoicevoxClient, voice_manager: &VoiceManager, guild_id: GuildId, audio_query: &str, speaker: u8) -> Result<()> {
+    let manager = songbird::get(ctx).await
+        .ok_or_else(|| anyhow::anyhow!("Songbirdマネージャーの取得に失敗しました"))?;
+    let call = manager.get(guild_id)
+        .ok_or_else(|| anyhow::anyhow!("ボイスチャンネルに接続されていません"))?;
 
     let wav_data = voicevox_client
-        .synthesis(&audio_query, 8)
+        .synthesis(audio_query, speaker)
         .await
         .map_err(|e| anyhow::anyhow!("音声合成に失敗しました: {}", e))?;
 
@@ -74,6 +117,8 @@ pub async fn play(ctx: &Context, voicevox_client: &VoicevoxClient, guild_id: Gui
         DeleteFileOnEnd { path: path_buf },
     );
 
+    voice_manager.touch(guild_id);
+
     Ok(())
 }
 
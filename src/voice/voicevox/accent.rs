@@ -0,0 +1,77 @@
+use crate::voice::voicevox::kana;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::Value;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Mora {
+    pub text: String,
+}
+
+/// `/audio_query`のアクセント句。使わないフィールドは読み飛ばす
+#[derive(Debug, Clone, Deserialize)]
+pub struct AccentPhrase {
+    pub moras: Vec<Mora>,
+    /// アクセント核のモーラの位置(1から)。下がり目が無い場合は最後のモーラ
+    pub accent: usize,
+}
+
+impl AccentPhrase {
+    /// 辞書の`accent_type`としての値。下がり目が無い場合は平板型の0にする
+    pub fn accent_type(&self) -> usize {
+        if self.accent >= self.moras.len() { 0 } else { self.accent }
+    }
+
+    /// 下がり目のモーラの後に「＼」を付けた表記
+    pub fn marked(&self) -> String {
+        self.moras
+            .iter()
+            .enumerate()
+            .map(|(index, mora)| if index + 1 == self.accent_type() { format!("{}＼", mora.text) } else { mora.text.clone() })
+            .collect()
+    }
+}
+
+/// `/audio_query`の結果から読みのカナとアクセント句を取り出す
+pub fn parse_audio_query(audio_query: &str) -> Result<(String, Vec<AccentPhrase>)> {
+    let query: Value = serde_json::from_str(audio_query).context("Failed to parse audio query")?;
+    let kana = query["kana"].as_str().unwrap_or_default().to_string();
+    let accent_phrases = serde_json::from_value(query["accent_phrases"].clone()).context("Failed to parse accent phrases")?;
+    Ok((kana, accent_phrases))
+}
+
+/// 辞書の読みとアクセントを`/accent_phrases`が受け付けるAquesTalk風の記法にする
+///
+/// 平板型も下がり目の印が必要なので、最後のモーラに付ける
+pub fn to_kana(pronunciation: &str, accent_type: usize) -> String {
+    let morae = kana::split_morae(pronunciation);
+    let accent = if accent_type == 0 || accent_type > morae.len() { morae.len() } else { accent_type };
+
+    morae
+        .iter()
+        .enumerate()
+        .map(|(index, mora)| if index + 1 == accent { format!("{}'", mora) } else { mora.clone() })
+        .collect()
+}
+
+/// 音声クエリのアクセント句を差し替える。話速などの設定はそのまま残す
+pub fn replace_accent_phrases(audio_query: &str, accent_phrases: Value) -> Result<String> {
+    let mut query: Value = serde_json::from_str(audio_query).context("Failed to parse audio query")?;
+    query["accent_phrases"] = accent_phrases;
+    serde_json::to_string(&query).context("Failed to serialize audio query")
+}
+
+/// 複数のアクセント句に分かれた単語を1語として扱い、読みと最初の下がり目の位置を返す
+pub fn word_reading(accent_phrases: &[AccentPhrase]) -> (String, usize) {
+    let mut pronunciation = String::new();
+    let mut accent_type = 0;
+    let mut offset = 0;
+    for phrase in accent_phrases {
+        pronunciation.extend(phrase.moras.iter().map(|mora| mora.text.as_str()));
+        if accent_type == 0 && phrase.accent_type() != 0 {
+            accent_type = offset + phrase.accent_type();
+        }
+        offset += phrase.moras.len();
+    }
+    (pronunciation, accent_type)
+}
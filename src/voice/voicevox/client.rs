@@ -1,12 +1,26 @@
 use crate::config::Config;
+use crate::voice::voicevox::health::{self, EngineStatus};
+use crate::voice::voicevox::resilience::{CircuitBreaker, EnginesPending, RetryPolicy};
 use anyhow::{Context, Result};
-use reqwest::Client as HttpClient;
+use reqwest::{Client as HttpClient, RequestBuilder, Response};
+use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
-use tracing::{debug, info, warn, error, instrument};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{debug, info, warn, error, info_span, instrument, Instrument};
 use url::Url;
 
-#[derive(Debug)]
+/// 単語の優先度の既定値。0から10で、大きいほど優先される
+pub const DEFAULT_PRIORITY: u8 = 10;
+/// 話者のモデルの読み込みは合成より時間がかかるため、リクエストのタイムアウトを延ばす
+const INITIALIZE_TIMEOUT: Duration = Duration::from_secs(120);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum WordType {
+    #[default]
     ProperNoun,
     CommonNoun,
     Verb,
@@ -14,19 +28,132 @@ pub enum WordType {
     Suffix,
 }
 
-fn set_word_type(word_type: WordType) -> String {
-    match word_type {
-        WordType::ProperNoun => "PROPER_NOUN",
-        WordType::CommonNoun => "COMMON_NOUN",
-        WordType::Verb => "VERB",
-        WordType::Adjective => "ADJECTIVE",
-        WordType::Suffix => "SUFFIX",
-    }.to_string()
+impl WordType {
+    pub const ALL: [WordType; 5] = [WordType::ProperNoun, WordType::CommonNoun, WordType::Verb, WordType::Adjective, WordType::Suffix];
+
+    /// VOICEVOXの`word_type`パラメーターの値
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WordType::ProperNoun => "PROPER_NOUN",
+            WordType::CommonNoun => "COMMON_NOUN",
+            WordType::Verb => "VERB",
+            WordType::Adjective => "ADJECTIVE",
+            WordType::Suffix => "SUFFIX",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            WordType::ProperNoun => "固有名詞",
+            WordType::CommonNoun => "普通名詞",
+            WordType::Verb => "動詞",
+            WordType::Adjective => "形容詞",
+            WordType::Suffix => "語尾",
+        }
+    }
+
+    /// `/user_dict`が返す品詞から単語の種類を判定する
+    pub fn from_part_of_speech(part_of_speech: &str, detail: &str) -> Self {
+        match (part_of_speech, detail) {
+            ("動詞", _) => WordType::Verb,
+            ("形容詞", _) => WordType::Adjective,
+            ("名詞", "接尾") => WordType::Suffix,
+            ("名詞", "固有名詞") => WordType::ProperNoun,
+            ("名詞", _) => WordType::CommonNoun,
+            _ => WordType::ProperNoun,
+        }
+    }
+}
+
+impl std::str::FromStr for WordType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        WordType::ALL
+            .into_iter()
+            .find(|word_type| word_type.as_str() == s)
+            .ok_or_else(|| anyhow::anyhow!("Unknown word type: {}", s))
+    }
+}
+
+impl TryFrom<String> for WordType {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+/// VOICEVOXは登録時に表層形の半角英数記号を全角に変換するため、比較用に同じ変換をする
+pub fn engine_surface(surface: &str) -> String {
+    surface
+        .chars()
+        .map(|c| match c {
+            '!'..='~' => char::from_u32(c as u32 + 0xFEE0).unwrap_or(c),
+            _ => c,
+        })
+        .collect()
+}
+
+/// `/speakers`が返す話者
+#[derive(Debug, Clone, Deserialize)]
+pub struct Speaker {
+    pub name: String,
+    pub speaker_uuid: String,
+    pub styles: Vec<SpeakerStyle>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SpeakerStyle {
+    pub name: String,
+    pub id: i64,
 }
 
 pub struct Client {
+    engines: Vec<Arc<Engine>>,
+    /// すべてのエンジンが止まった回数と、前回確認したときに止まっていたか
+    outages: Mutex<(u64, bool)>,
+}
+
+/// エンジン1台分の接続。単語IDはエンジンごとに違うため、対応表もエンジンごとに持つ
+pub struct Engine {
     voicevox_client: HttpClient,
     voicevox_url: Url,
+    weight: u32,
+    retry: RetryPolicy,
+    breaker: CircuitBreaker,
+    /// ヘルスチェックの結果。起動直後は使えるものとして扱う
+    healthy: AtomicBool,
+    /// 応答を待っているリクエストの数
+    outstanding: AtomicUsize,
+    /// エンジンに登録されている表層形と単語IDの対応。`/user_dict`を毎回取得しないためのもの
+    word_uuids: Mutex<HashMap<String, String>>,
+    /// 単語IDを対応表から見つけられた回数と、見つけられずに辞書を取得し直した回数
+    uuid_hits: AtomicU64,
+    uuid_misses: AtomicU64,
+    status: Mutex<EngineStatus>,
+    /// モデルを読み込んでおく話者。エンジンが再起動したら読み込み直す
+    warm_speakers: Mutex<BTreeSet<u8>>,
+    /// プリセット名とプリセットIDの対応。IDもエンジンごとに違う
+    preset_ids: Mutex<HashMap<String, i64>>,
+    /// 辞書とプリセットをデータベースから同期し直す必要があるか
+    needs_sync: AtomicBool,
+}
+
+/// 応答を待っている間だけリクエストの数に含める
+struct Outstanding<'a>(&'a AtomicUsize);
+
+impl<'a> Outstanding<'a> {
+    fn start(count: &'a AtomicUsize) -> Self {
+        count.fetch_add(1, Ordering::Relaxed);
+        Self(count)
+    }
+}
+
+impl Drop for Outstanding<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
 }
 
 impl Client {
@@ -38,26 +165,170 @@ impl Client {
             .build()
             .context("Failed to create voicevox client")?;
 
-        let voicevox_url = config.voicevox_url.clone();
+        let engines = config.voicevox_endpoints
+            .iter()
+            .map(|endpoint| Arc::new(Engine {
+                voicevox_client: voicevox_client.clone(),
+                voicevox_url: endpoint.url.clone(),
+                weight: endpoint.weight.max(1),
+                retry: RetryPolicy::from_config(&config),
+                breaker: CircuitBreaker::from_config(&config),
+                healthy: AtomicBool::new(true),
+                outstanding: AtomicUsize::new(0),
+                word_uuids: Mutex::new(HashMap::new()),
+                uuid_hits: AtomicU64::new(0),
+                uuid_misses: AtomicU64::new(0),
+                status: Mutex::default(),
+                warm_speakers: Mutex::default(),
+                preset_ids: Mutex::default(),
+                needs_sync: AtomicBool::new(false),
+            }))
+            .collect::<Vec<_>>();
+        if engines.is_empty() {
+            anyhow::bail!("No voicevox engine is configured");
+        }
 
-        debug!("Voicevox handler initialized");
+        debug!(engines = engines.len(), "Voicevox handler initialized");
 
         Ok(Self {
-            voicevox_client,
-            voicevox_url,
+            engines,
+            outages: Mutex::new((0, false)),
         })
     }
 
+    pub fn engines(&self) -> &[Arc<Engine>] {
+        &self.engines
+    }
+
+    /// 最初に設定されたエンジン。辞書を読み出すときはこのエンジンを基準にする
+    pub fn primary(&self) -> &Engine {
+        &self.engines[0]
+    }
+
+    /// すべてのエンジンが使えない間は、何回目の停止かを返す
+    pub fn outage(&self) -> Option<u64> {
+        let down = !self.engines.iter().any(|engine| engine.is_available());
+        let mut outages = self.outages.lock().unwrap();
+        if down && !outages.1 {
+            outages.0 += 1;
+        }
+        outages.1 = down;
+        down.then_some(outages.0)
+    }
+
+    /// エンジンごとに定期的にヘルスチェックをし、応答しないエンジンには合成を振り分けない
+    ///
+    /// 同期し直す必要があるエンジンが使えるようになったら`resync`を呼び出す
+    pub fn spawn_health_checks<F, Fut>(&self, interval: Duration, resync: F)
+    where
+        F: Fn(Arc<Engine>) -> Fut + Clone + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send,
+    {
+        for engine in &self.engines {
+            let engine = engine.clone();
+            let resync = resync.clone();
+            let span = info_span!("health_check", url = %engine.voicevox_url);
+            tokio::spawn(async move {
+                loop {
+                    engine.check_health().await;
+                    if engine.is_available() && engine.needs_sync.swap(false, Ordering::Relaxed) {
+                        info!("Resynchronizing VOICEVOX engine");
+                        if let Err(e) = resync(engine.clone()).await {
+                            warn!("Failed to resynchronize VOICEVOX engine: {}", e);
+                            engine.mark_needs_sync();
+                        }
+                    }
+                    tokio::time::sleep(interval).await;
+                }
+            }.instrument(span));
+        }
+    }
+
+    /// すべてのエンジンで話者のモデルを読み込んでおき、最初の読み上げが遅くならないようにする。完了は待たない
+    pub fn warm_up(&self, speakers: &[u8]) {
+        for engine in &self.engines {
+            let engine = engine.clone();
+            let speakers = speakers.to_vec();
+            let span = info_span!("warm_up", url = %engine.voicevox_url);
+            tokio::spawn(async move { engine.warm_up(&speakers).await }.instrument(span));
+        }
+    }
+
+    /// 使えるエンジンの中から、重みあたりの待ち数が最も少ないものを選ぶ。`tried`のエンジンは他に無いときだけ選び、`skipped`のエンジンは選ばない
+    fn pick(&self, tried: &[usize], skipped: &[usize]) -> Option<usize> {
+        let available = self.engines.iter().enumerate().filter(|(index, engine)| engine.is_available() && !skipped.contains(index)).collect::<Vec<_>>();
+        let untried = available.iter().filter(|(index, _)| !tried.contains(index)).copied().collect::<Vec<_>>();
+        let candidates = if untried.is_empty() { available } else { untried };
+
+        // (待ち数 + 1) / 重み を、割り算せずに比べる
+        candidates
+            .into_iter()
+            .min_by(|(_, a), (_, b)| {
+                let a_load = (a.outstanding.load(Ordering::Relaxed) as u64 + 1) * b.weight as u64;
+                let b_load = (b.outstanding.load(Ordering::Relaxed) as u64 + 1) * a.weight as u64;
+                a_load.cmp(&b_load)
+            })
+            .map(|(index, _)| index)
+    }
+
+    /// 合成などのリクエストを空いているエンジンに送る。失敗したら別のエンジンで再試行する
+    ///
+    /// プリセットIDが無いなど、そのエンジン向けのリクエストを作れないときは、失敗として数えずに別のエンジンを選ぶ
+    async fn route(&self, request: impl Fn(&Engine) -> Result<RequestBuilder>) -> Result<Response> {
+        let retry = self.primary().retry;
+        let mut tried = Vec::new();
+        let mut skipped = Vec::new();
+        let mut skipped_error = None;
+
+        loop {
+            let Some(index) = self.pick(&tried, &skipped) else {
+                return Err(skipped_error.take().unwrap_or_else(|| anyhow::anyhow!("No voicevox engine is available")));
+            };
+            let engine = &self.engines[index];
+            let builder = match request(engine) {
+                Ok(builder) => builder,
+                Err(e) => {
+                    debug!(url = %engine.voicevox_url, "Skipping engine: {}", e);
+                    skipped.push(index);
+                    skipped_error = Some(e);
+                    continue;
+                }
+            };
+            tried.push(index);
+
+            let result = {
+                let _outstanding = Outstanding::start(&engine.outstanding);
+                builder.send().await
+            };
+            let failed = result.as_ref().map_or(true, |res| res.status().is_server_error());
+            if !failed || tried.len() as u32 >= retry.max_attempts {
+                // 1回の呼び出しでは、失敗したエンジンごとに1回だけ失敗として数える
+                tried.sort_unstable();
+                tried.dedup();
+                for &other in tried.iter().filter(|&&other| other != index) {
+                    self.engines[other].breaker.record_failure();
+                }
+                return engine.record(result);
+            }
+
+            let delay = retry.delay(tried.len() as u32);
+            warn!(attempt = tried.len(), url = %engine.voicevox_url, delay_ms = delay.as_millis() as u64, "VOICEVOX request failed; retrying");
+            tokio::time::sleep(delay).await;
+        }
+    }
+
     // Audio functionality
     #[instrument(skip(self, text, speaker_id, speed_scale), fields(text = %text, speaker_id = %speaker_id, speed_scale = %speed_scale))]
     pub async fn create_audio_query(&self, text: &str, speaker_id: u8, speed_scale: f64) -> Result<String> {
         debug!("Sending audio query create request to voicevox");
 
-        let mut audio_query_url = self.voicevox_url.join("/audio_query").context("Failed to join voicevox url")?;
-
-        audio_query_url.query_pairs_mut().append_pair("text", text).append_pair("speaker", speaker_id.to_string().as_str());
+        let response = self.route(|engine| {
+            let mut audio_query_url = engine.voicevox_url.join("/audio_query").context("Failed to join voicevox url")?;
+            audio_query_url.query_pairs_mut().append_pair("text", text).append_pair("speaker", speaker_id.to_string().as_str());
+            Ok(engine.voicevox_client.post(audio_query_url))
+        });
 
-        match self.voicevox_client.post(audio_query_url).send().await {
+        match response.await {
             Ok(res) => {
                 if res.status().is_success() {
                     info!("Audio query create successfully");
@@ -83,18 +354,119 @@ impl Client {
         }
     }
 
+    /// AquesTalk風の記法(`ミ'ドリン`)の読みからアクセント句を作る。辞書に登録する前の読みを確かめるのに使う
+    #[instrument(skip(self, kana, speaker_id), fields(kana = %kana, speaker_id = %speaker_id))]
+    pub async fn create_accent_phrases_from_kana(&self, kana: &str, speaker_id: u8) -> Result<Value> {
+        debug!("Sending accent phrases request to voicevox");
+
+        let response = self.route(|engine| {
+            let mut accent_phrases_url = engine.voicevox_url.join("/accent_phrases").context("Failed to join voicevox url")?;
+            accent_phrases_url.query_pairs_mut()
+                .append_pair("text", kana)
+                .append_pair("speaker", speaker_id.to_string().as_str())
+                .append_pair("is_kana", "true");
+            Ok(engine.voicevox_client.post(accent_phrases_url))
+        });
+
+        match response.await {
+            Ok(res) => {
+                if res.status().is_success() {
+                    info!("Accent phrases create successfully");
+                    res.json::<Value>().await.context("Failed to read accent phrases")
+                } else {
+                    warn!("Accent phrases create failed with status code {}", res.status());
+                    Err(anyhow::anyhow!("Accent phrases create failed with status code {}", res.status()))
+                }
+            }
+            Err(e) => {
+                error!("Failed to create accent phrases:\n{}", e);
+                Err(anyhow::anyhow!("Failed to create accent phrases:\n{}", e))
+            }
+        }
+    }
+
+    /// アクセント位置を書き換えたアクセント句の音高を計算し直す
+    #[instrument(skip(self, accent_phrases, speaker_id), fields(speaker_id = %speaker_id))]
+    pub async fn create_mora_pitch(&self, accent_phrases: &Value, speaker_id: u8) -> Result<Value> {
+        debug!("Sending mora pitch request to voicevox");
+
+        let response = self.route(|engine| {
+            let mut mora_pitch_url = engine.voicevox_url.join("/mora_pitch").context("Failed to join voicevox url")?;
+            mora_pitch_url.query_pairs_mut().append_pair("speaker", speaker_id.to_string().as_str());
+            Ok(engine.voicevox_client.post(mora_pitch_url).json(accent_phrases))
+        });
+
+        match response.await {
+            Ok(res) => {
+                if res.status().is_success() {
+                    info!("Mora pitch create successfully");
+                    res.json::<Value>().await.context("Failed to read mora pitch")
+                } else {
+                    warn!("Mora pitch create failed with status code {}", res.status());
+                    Err(anyhow::anyhow!("Mora pitch create failed with status code {}", res.status()))
+                }
+            }
+            Err(e) => {
+                error!("Failed to create mora pitch:\n{}", e);
+                Err(anyhow::anyhow!("Failed to create mora pitch:\n{}", e))
+            }
+        }
+    }
+
+    /// エンジンのプリセットの話速や抑揚で音声クエリを作る
+    #[instrument(skip(self, text), fields(text = %text))]
+    pub async fn create_audio_query_from_preset(&self, text: &str, preset_name: &str) -> Result<String> {
+        debug!("Sending audio query from preset request to voicevox");
+
+        // どのエンジンに振り分けてもプリセットIDが引けるよう、手元に無いものは先に取得しておく
+        for engine in &self.engines {
+            if engine.is_available()
+                && engine.cached_preset_id(preset_name).is_none()
+                && let Err(e) = engine.preset_id(preset_name).await
+            {
+                warn!(url = %engine.voicevox_url, "Failed to fetch presets: {}", e);
+            }
+        }
+
+        let response = self.route(|engine| {
+            let preset_id = engine.cached_preset_id(preset_name)
+                .ok_or_else(|| anyhow::anyhow!("Preset {} is not registered in {}", preset_name, engine.voicevox_url))?;
+            let mut audio_query_url = engine.voicevox_url.join("/audio_query_from_preset").context("Failed to join voicevox url")?;
+            audio_query_url.query_pairs_mut().append_pair("text", text).append_pair("preset_id", &preset_id.to_string());
+            Ok(engine.voicevox_client.post(audio_query_url))
+        });
+
+        match response.await {
+            Ok(res) => {
+                if res.status().is_success() {
+                    info!("Audio query from preset create successfully");
+                    Ok(res.text().await?)
+                } else {
+                    warn!("Audio query from preset create failed with status code {}", res.status());
+                    Err(anyhow::anyhow!("Audio query from preset create failed with status code {}", res.status()))
+                }
+            }
+            Err(e) => {
+                error!("Failed to create audio query from preset:\n{}", e);
+                Err(anyhow::anyhow!("Failed to create audio query from preset:\n{}", e))
+            }
+        }
+    }
+
     #[instrument(skip(self, audio_query, speaker), fields(speaker = %speaker))]
     pub async fn synthesis(&self, audio_query: &str, speaker: u8) -> Result<bytes::Bytes> {
         debug!("Sending synthesize request to voicevox");
 
-        let mut synthesis_url = self.voicevox_url
-            .join("/synthesis")
-            .context("Failed to join URL")?;
-
-        synthesis_url.query_pairs_mut()
-            .append_pair("speaker", &speaker.to_string());
+        let response = self.route(|engine| {
+            let mut synthesis_url = engine.voicevox_url
+                .join("/synthesis")
+                .context("Failed to join URL")?;
+            synthesis_url.query_pairs_mut()
+                .append_pair("speaker", &speaker.to_string());
+            Ok(engine.voicevox_client.post(synthesis_url).body(audio_query.to_string()))
+        });
 
-        match self.voicevox_client.post(synthesis_url).body(audio_query.to_string()).send().await {
+        match response.await {
             Ok(res) => {
                 let status_ok = res.status().is_success();
                 if status_ok {
@@ -136,24 +508,246 @@ impl Client {
     }
 
     // Dictionary functionality
-    #[instrument(skip(self, surface), fields(surface = %surface))]
-    pub async fn find_uuid_by_surface(&self, surface: &str) -> Result<Option<String>> {
-        debug!("Find uuid by surface");
+    // 読み方がエンジンによって変わらないよう、辞書の変更はすべてのエンジンに送る
+    pub async fn get_user_dict(&self) -> Result<String> {
+        self.primary().get_user_dict().await
+    }
 
-        let dict_content = self.get_user_dict().await?;
-        let dict_json: Value = serde_json::from_str(&dict_content)?;
+    pub async fn add_dict_word(&self, surface: &str, pronunciation: &str, accent_type: u8, word_type: Option<WordType>, priority: u8) -> Result<()> {
+        self.mirror(|engine| engine.add_dict_word(surface, pronunciation, accent_type, word_type, priority)).await
+    }
 
-        if let Some(dict_obj) = dict_json.as_object() {
-            for (uuid, word_data) in dict_obj {
-                if let Some(word_surface) = word_data.get("surface").and_then(|s| s.as_str()) {
-                    if word_surface == surface {
-                        return Ok(Some(uuid.clone()));
-                    }
+    pub async fn rewrite_dict_word(&self, surface: &str, pronunciation: &str, accent_type: u8, word_type: Option<WordType>, priority: u8) -> Result<()> {
+        self.mirror(|engine| engine.rewrite_dict_word(surface, pronunciation, accent_type, word_type, priority)).await
+    }
+
+    pub async fn delete_dict_word(&self, surface: &str) -> Result<()> {
+        self.mirror(|engine| engine.delete_dict_word(surface)).await
+    }
+
+    // Preset functionality
+    // 話者の一覧は最初に設定されたエンジンのものを使う
+    pub async fn speakers(&self) -> Result<Vec<Speaker>> {
+        self.primary().get_speakers().await
+    }
+
+    /// プリセットをすべてのエンジンに登録する。同じ名前のプリセットがあれば上書きする
+    pub async fn put_preset(&self, preset: &Value) -> Result<()> {
+        self.mirror(|engine| engine.put_preset(preset)).await
+    }
+
+    pub async fn delete_preset(&self, name: &str) -> Result<()> {
+        self.mirror(|engine| engine.delete_preset(name)).await
+    }
+
+    /// すべてのエンジンに同じ変更を送る。失敗したエンジンがあればまとめてエラーにする
+    ///
+    /// 止まっているエンジンには送らず`EnginesPending`を返す。届かなかったエンジンは、使えるようになったら同期し直す
+    async fn mirror<'a, F>(&'a self, change: impl Fn(&'a Engine) -> F) -> Result<()>
+    where
+        F: Future<Output = Result<()>>,
+    {
+        let mut errors = Vec::new();
+        let mut pending = 0;
+        for engine in &self.engines {
+            if !engine.is_available() {
+                debug!(url = %engine.voicevox_url, "Engine is unavailable; deferring change until it recovers");
+                engine.mark_needs_sync();
+                pending += 1;
+                continue;
+            }
+            if let Err(e) = change(engine).await {
+                engine.mark_needs_sync();
+                if self.engines.len() == 1 {
+                    return Err(e);
                 }
+                errors.push(format!("{}: {}", engine.voicevox_url, e));
+            }
+        }
+
+        if !errors.is_empty() {
+            Err(anyhow::anyhow!("Failed on {} of {} engines:\n{}", errors.len(), self.engines.len(), errors.join("\n")))
+        } else if pending > 0 {
+            Err(EnginesPending { pending, total: self.engines.len() }.into())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Engine {
+    pub fn url(&self) -> &Url {
+        &self.voicevox_url
+    }
+
+    /// ヘルスチェックに応答していて、サーキットブレーカーも閉じているか
+    pub fn is_available(&self) -> bool {
+        self.healthy.load(Ordering::Relaxed) && self.breaker.check().is_ok()
+    }
+
+    /// 応答を待っているリクエストの数
+    pub fn outstanding(&self) -> usize {
+        self.outstanding.load(Ordering::Relaxed)
+    }
+
+    /// 最後のヘルスチェックの結果
+    pub fn status(&self) -> EngineStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// 単語IDの対応表が使われた回数と、辞書を取得し直した回数
+    pub fn uuid_cache_stats(&self) -> (u64, u64) {
+        (self.uuid_hits.load(Ordering::Relaxed), self.uuid_misses.load(Ordering::Relaxed))
+    }
+
+    pub async fn check_health(&self) {
+        let previous = self.status();
+        let status = health::probe(&self.voicevox_client, &self.voicevox_url, &previous).await;
+        let healthy = status.reachable;
+        let upgraded = previous.version.is_some() && status.version.is_some() && status.version != previous.version;
+        if status.version.is_some() && status.version != previous.version {
+            info!(version = status.version.as_deref(), engine = status.engine_name.as_deref(), "Detected VOICEVOX engine version");
+        }
+        *self.status.lock().unwrap() = status;
+
+        let was_healthy = self.healthy.swap(healthy, Ordering::Relaxed);
+        if was_healthy != healthy {
+            if healthy {
+                info!("VOICEVOX engine passed health check");
+            } else {
+                warn!("VOICEVOX engine failed health check");
             }
         }
+        let recovered = healthy && !was_healthy;
+
+        // 止まっていた間に再起動した場合は、読み込んでいたモデルや登録した単語が消え、単語IDやプリセットIDも変わっている
+        if recovered || upgraded {
+            self.word_uuids.lock().unwrap().clear();
+            self.preset_ids.lock().unwrap().clear();
+            self.mark_needs_sync();
 
-        Err(anyhow::anyhow!("Surface not found"))
+            let speakers = self.warm_speakers.lock().unwrap().iter().copied().collect::<Vec<_>>();
+            self.warm_up(&speakers).await;
+        }
+    }
+
+    /// 次のヘルスチェックで辞書とプリセットを同期し直す
+    pub fn mark_needs_sync(&self) {
+        self.needs_sync.store(true, Ordering::Relaxed);
+    }
+
+    /// 話者のモデルを読み込み、以後のヘルスチェックで再起動に気付いたら読み込み直す
+    pub async fn warm_up(&self, speakers: &[u8]) {
+        self.warm_speakers.lock().unwrap().extend(speakers);
+        for &speaker in speakers {
+            match self.initialize_speaker(speaker).await {
+                Ok(true) => info!(speaker, "Initialized VOICEVOX speaker"),
+                Ok(false) => debug!(speaker, "VOICEVOX speaker is already initialized"),
+                Err(e) => warn!(speaker, "Failed to initialize VOICEVOX speaker: {}", e),
+            }
+        }
+    }
+
+    /// 話者のモデルがまだ読み込まれていなければ読み込む。読み込んだ場合はtrueを返す
+    #[instrument(skip(self))]
+    pub async fn initialize_speaker(&self, speaker: u8) -> Result<bool> {
+        let mut is_initialized_url = self.voicevox_url.join("/is_initialized_speaker").context("Failed to join URL")?;
+        is_initialized_url.query_pairs_mut().append_pair("speaker", &speaker.to_string());
+
+        let initialized = self.send_with_retry(|| self.voicevox_client.get(is_initialized_url.clone()))
+            .await?
+            .error_for_status()?
+            .json::<bool>()
+            .await
+            .context("Failed to read speaker initialization state")?;
+        if initialized {
+            return Ok(false);
+        }
+
+        let mut initialize_url = self.voicevox_url.join("/initialize_speaker").context("Failed to join URL")?;
+        initialize_url.query_pairs_mut()
+            .append_pair("speaker", &speaker.to_string())
+            .append_pair("skip_reinit", "true");
+
+        // skip_reinitを付けているので、何度送っても読み込みは1回だけ
+        self.send_with_retry(|| self.voicevox_client.post(initialize_url.clone()).timeout(INITIALIZE_TIMEOUT))
+            .await?
+            .error_for_status()?;
+        Ok(true)
+    }
+
+    /// 何度送っても結果が変わらないリクエストを送る。タイムアウトや接続の失敗、5xxのときは間隔を空けて再試行する
+    async fn send_with_retry(&self, request: impl Fn() -> RequestBuilder) -> Result<Response> {
+        self.breaker.check()?;
+
+        let mut attempt = 1;
+        loop {
+            let result = {
+                let _outstanding = Outstanding::start(&self.outstanding);
+                request().send().await
+            };
+            let failed = result.as_ref().map_or(true, |res| res.status().is_server_error());
+            if !failed || attempt >= self.retry.max_attempts {
+                return self.record(result);
+            }
+
+            let delay = self.retry.delay(attempt);
+            warn!(attempt, delay_ms = delay.as_millis() as u64, "VOICEVOX request failed; retrying");
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// 単語の追加のように、2回送ると結果が変わるリクエストは再試行せずに送る
+    async fn send_once(&self, request: RequestBuilder) -> Result<Response> {
+        self.breaker.check()?;
+        let result = {
+            let _outstanding = Outstanding::start(&self.outstanding);
+            request.send().await
+        };
+        self.record(result)
+    }
+
+    /// 4xxはエンジンが動いているので成功として数える
+    fn record(&self, result: reqwest::Result<Response>) -> Result<Response> {
+        match &result {
+            Ok(res) if !res.status().is_server_error() => self.breaker.record_success(),
+            _ => self.breaker.record_failure(),
+        }
+        Ok(result?)
+    }
+
+    /// 表層形から単語IDを探す。手元の対応表に無い場合のみエンジンの辞書を取得し直す
+    #[instrument(skip(self, surface), fields(surface = %surface))]
+    pub async fn find_uuid_by_surface(&self, surface: &str) -> Result<Option<String>> {
+        debug!("Find uuid by surface");
+
+        if let Some(uuid) = self.cached_uuid(surface) {
+            self.uuid_hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(Some(uuid));
+        }
+
+        self.uuid_misses.fetch_add(1, Ordering::Relaxed);
+        self.get_user_dict().await?;
+        Ok(self.cached_uuid(surface))
+    }
+
+    // エンジンは表層形を全角にして保存するため、変換後の表層形とも比較する
+    fn cached_uuid(&self, surface: &str) -> Option<String> {
+        let word_uuids = self.word_uuids.lock().unwrap();
+        word_uuids.get(surface).or_else(|| word_uuids.get(&engine_surface(surface))).cloned()
+    }
+
+    fn cache_user_dict(&self, user_dict_raw: &str) {
+        let Ok(Value::Object(dict)) = serde_json::from_str::<Value>(user_dict_raw) else {
+            return;
+        };
+
+        let word_uuids = dict
+            .into_iter()
+            .filter_map(|(uuid, word_data)| Some((word_data.get("surface")?.as_str()?.to_string(), uuid)))
+            .collect();
+        *self.word_uuids.lock().unwrap() = word_uuids;
     }
 
     #[instrument(skip(self))]
@@ -164,12 +758,13 @@ impl Client {
             .join("/user_dict")
             .context("Failed to join URL")?;
 
-        match self.voicevox_client.get(user_dict_url).send().await {
+        match self.send_with_retry(|| self.voicevox_client.get(user_dict_url.clone())).await {
             Ok(res) => {
                 let status_ok = res.status().is_success();
                 if status_ok {
                     let user_dict_raw = res.text().await?;
                     info!("User dict get successfully");
+                    self.cache_user_dict(&user_dict_raw);
                     Ok(user_dict_raw)
                 } else {
                     warn!("User dict get failed with status code {}", res.status());
@@ -183,15 +778,11 @@ impl Client {
         }
     }
 
-    #[instrument(skip(self, surface, pronunciation, accent_type, word_type), fields(surface = %surface, pronunciation = %pronunciation, accent_type = %accent_type, word_type = ?word_type))]
-    pub async fn add_dict_word(&self, surface: &str, pronunciation: &str, accent_type: u8, word_type: Option<WordType>) -> Result<()> {
+    #[instrument(skip(self, surface, pronunciation, accent_type, word_type, priority), fields(surface = %surface, pronunciation = %pronunciation, accent_type = %accent_type, word_type = ?word_type, priority = %priority))]
+    pub async fn add_dict_word(&self, surface: &str, pronunciation: &str, accent_type: u8, word_type: Option<WordType>, priority: u8) -> Result<()> {
         debug!("Sending add word user dict word request to voicevox");
 
-        let word_type_string = if let Some(word_type) = word_type {
-            set_word_type(word_type)
-        } else {
-            set_word_type(WordType::ProperNoun)
-        };
+        let word_type = word_type.unwrap_or_default();
 
         let mut user_dict_word_url = self.voicevox_url
             .join("/user_dict_word")
@@ -201,14 +792,21 @@ impl Client {
             .append_pair("surface", surface)
             .append_pair("pronunciation", pronunciation)
             .append_pair("accent_type", accent_type.to_string().as_str())
-            .append_pair("word_type", &word_type_string)
-            .append_pair("priority", "10");
+            .append_pair("word_type", word_type.as_str())
+            .append_pair("priority", priority.to_string().as_str());
 
-        match self.voicevox_client.post(user_dict_word_url).send().await {
+        match self.send_once(self.voicevox_client.post(user_dict_word_url)).await {
             Ok(res) => {
                 let status_ok = res.status().is_success();
                 if status_ok {
                     info!("User dict word add successfully");
+                    // 追加した単語のIDが返される
+                    match res.json::<String>().await {
+                        Ok(uuid) => {
+                            self.word_uuids.lock().unwrap().insert(engine_surface(surface), uuid);
+                        }
+                        Err(e) => warn!("Failed to read added word uuid: {}", e),
+                    }
                     Ok(())
                 } else {
                     warn!("User dict word add failed with status code {}", res.status());
@@ -222,31 +820,40 @@ impl Client {
         }
     }
 
-    pub async fn rewrite_dict_word(&self, surface: &str, pronunciation: &str, accent_type: u8, word_type: Option<WordType>) -> Result<()> {
-        debug!("Sending rewrite word user dict word request to voicevox");
-
-        let word_type_string = if let Some(word_type) = word_type {
-            set_word_type(word_type)
+    #[instrument(skip(self, surface, pronunciation, accent_type, word_type, priority), fields(surface = %surface, pronunciation = %pronunciation, accent_type = %accent_type, word_type = ?word_type, priority = %priority))]
+    pub async fn rewrite_dict_word(&self, surface: &str, pronunciation: &str, accent_type: u8, word_type: Option<WordType>, priority: u8) -> Result<()> {
+        let word_uuid = if let Some(word_uuid_raw) = self.find_uuid_by_surface(surface).await? {
+            word_uuid_raw
         } else {
-            set_word_type(WordType::ProperNoun)
+            return Err(anyhow::anyhow!("Word not found"))
         };
 
+        self.rewrite_dict_word_by_uuid(&word_uuid, surface, pronunciation, accent_type, word_type, priority).await
+    }
+
+    #[instrument(skip(self, surface, pronunciation, accent_type, word_type, priority))]
+    pub async fn rewrite_dict_word_by_uuid(&self, word_uuid: &str, surface: &str, pronunciation: &str, accent_type: u8, word_type: Option<WordType>, priority: u8) -> Result<()> {
+        debug!("Sending rewrite word user dict word request to voicevox");
+
+        let word_type = word_type.unwrap_or_default();
+
         let mut user_dict_word_url = self.voicevox_url
-            .join("/user_dict_word")
+            .join(format!("/user_dict_word/{}", word_uuid).as_str())
             .context("Failed to join URL")?;
 
         user_dict_word_url.query_pairs_mut()
             .append_pair("surface", surface)
             .append_pair("pronunciation", pronunciation)
             .append_pair("accent_type", accent_type.to_string().as_str())
-            .append_pair("word_type", &word_type_string)
-            .append_pair("priority", "10");
+            .append_pair("word_type", word_type.as_str())
+            .append_pair("priority", priority.to_string().as_str());
 
-        match self.voicevox_client.put(user_dict_word_url).send().await {
+        match self.send_with_retry(|| self.voicevox_client.put(user_dict_word_url.clone())).await {
             Ok(res) => {
                 let status_ok = res.status().is_success();
                 if status_ok {
                     info!("User dict word rewrite successfully");
+                    self.word_uuids.lock().unwrap().insert(engine_surface(surface), word_uuid.to_string());
                     Ok(())
                 } else {
                     warn!("User dict word rewrite failed with status code {}", res.status());
@@ -262,23 +869,29 @@ impl Client {
 
     #[instrument(skip(self, surface), fields(surface = %surface))]
     pub async fn delete_dict_word(&self, surface: &str) -> Result<()> {
-        debug!("Sending delete word user dict word request to voicevox");
-
         let word_uuid = if let Some(word_uuid_raw) = self.find_uuid_by_surface(surface).await? {
             word_uuid_raw
         } else {
             return Err(anyhow::anyhow!("Word not found"))
         };
 
+        self.delete_dict_word_by_uuid(&word_uuid).await
+    }
+
+    #[instrument(skip(self))]
+    pub async fn delete_dict_word_by_uuid(&self, word_uuid: &str) -> Result<()> {
+        debug!("Sending delete word user dict word request to voicevox");
+
         let user_dict_word_url = self.voicevox_url
             .join(format!("/user_dict_word/{}", word_uuid).as_str())
             .context("Failed to join URL")?;
 
-        match self.voicevox_client.delete(user_dict_word_url).send().await {
+        match self.send_once(self.voicevox_client.delete(user_dict_word_url)).await {
             Ok(res) => {
                 let status_ok = res.status().is_success();
                 if status_ok {
                     info!("User dict word delete successfully");
+                    self.word_uuids.lock().unwrap().retain(|_, uuid| uuid != word_uuid);
                     Ok(())
                 } else {
                     warn!("User dict word delete failed with status code {}", res.status());
@@ -292,54 +905,153 @@ impl Client {
         }
     }
 
-    #[instrument(skip(self, json_content))]
-    pub async fn import_dict(&self, json_content: &str) -> Result<()> {
-        debug!("Sending import user dict words request to voicevox");
+    #[instrument(skip(self))]
+    pub async fn get_speakers(&self) -> Result<Vec<Speaker>> {
+        debug!("Sending get speakers request to voicevox");
 
-        let mut user_dict_words_url = self.voicevox_url
-            .join("/import_user_dict")
+        let speakers_url = self.voicevox_url
+            .join("/speakers")
             .context("Failed to join URL")?;
 
-        user_dict_words_url.query_pairs_mut()
-            .append_pair("override", "true");
-
-        match self.voicevox_client.post(user_dict_words_url).body(json_content.to_string()).send().await {
+        match self.send_with_retry(|| self.voicevox_client.get(speakers_url.clone())).await {
             Ok(res) => {
-                let status_ok = res.status().is_success();
-                if status_ok {
-                    info!("User dict words import successfully");
-                    Ok(())
+                if res.status().is_success() {
+                    info!("Speakers get successfully");
+                    res.json::<Vec<Speaker>>().await.context("Failed to read speakers")
                 } else {
-                    warn!("User dict words import failed with status code {}", res.status());
-                    Err(anyhow::anyhow!("User dict words import failed with status code {}", res.status()))
+                    warn!("Speakers get failed with status code {}", res.status());
+                    Err(anyhow::anyhow!("Speakers get failed with status code {}", res.status()))
                 }
             }
             Err(e) => {
-                error!("Failed to import user dict words:\n{}", e);
-                Err(anyhow::anyhow!("Failed to import user dict words:\n{}", e))
+                error!("Failed to get speakers:\n{}", e);
+                Err(anyhow::anyhow!("Failed to get speakers:\n{}", e))
             }
         }
     }
 
+    /// エンジンのプリセットを取得し、名前とIDの対応を更新する
     #[instrument(skip(self))]
-    pub async fn reset_dict(&self) -> Result<()> {
-        debug!("Sending reset user dict request to voicevox");
+    pub async fn get_presets(&self) -> Result<Vec<Value>> {
+        debug!("Sending get presets request to voicevox");
 
-        let dict_content = self.get_user_dict().await?;
-        let dict_json: Value = serde_json::from_str(&dict_content)?;
-
-        let mut uuid_list = Vec::new();
+        let presets_url = self.voicevox_url
+            .join("/presets")
+            .context("Failed to join URL")?;
 
-        if let Some(dict_obj) = dict_json.as_object() {
-            for (uuid, _value) in dict_obj {
-                uuid_list.push(uuid.clone());
+        match self.send_with_retry(|| self.voicevox_client.get(presets_url.clone())).await {
+            Ok(res) => {
+                if res.status().is_success() {
+                    info!("Presets get successfully");
+                    let presets = res.json::<Vec<Value>>().await.context("Failed to read presets")?;
+                    *self.preset_ids.lock().unwrap() = presets
+                        .iter()
+                        .filter_map(|preset| Some((preset.get("name")?.as_str()?.to_string(), preset.get("id")?.as_i64()?)))
+                        .collect();
+                    Ok(presets)
+                } else {
+                    warn!("Presets get failed with status code {}", res.status());
+                    Err(anyhow::anyhow!("Presets get failed with status code {}", res.status()))
+                }
             }
+            Err(e) => {
+                error!("Failed to get presets:\n{}", e);
+                Err(anyhow::anyhow!("Failed to get presets:\n{}", e))
+            }
+        }
+    }
+
+    /// プリセット名からIDを探す。手元の対応表に無い場合のみエンジンのプリセットを取得し直す
+    pub async fn preset_id(&self, name: &str) -> Result<Option<i64>> {
+        if let Some(preset_id) = self.cached_preset_id(name) {
+            return Ok(Some(preset_id));
         }
 
-        for uuid in uuid_list {
-            self.delete_dict_word(&uuid).await?;
+        self.get_presets().await?;
+        Ok(self.cached_preset_id(name))
+    }
+
+    fn cached_preset_id(&self, name: &str) -> Option<i64> {
+        self.preset_ids.lock().unwrap().get(name).copied()
+    }
+
+    /// 同じ名前のプリセットがあれば`/update_preset`で上書きし、無ければ`/add_preset`で追加する
+    #[instrument(skip(self, preset), fields(name = ?preset.get("name")))]
+    pub async fn put_preset(&self, preset: &Value) -> Result<()> {
+        let name = preset.get("name").and_then(Value::as_str).context("Preset has no name")?;
+        let existing_id = self.preset_id(name).await?;
+        debug!(existing_id, "Sending put preset request to voicevox");
+
+        let mut preset = preset.clone();
+        let response = match existing_id {
+            Some(preset_id) => {
+                preset["id"] = json!(preset_id);
+                let update_preset_url = self.voicevox_url.join("/update_preset").context("Failed to join URL")?;
+                self.send_with_retry(|| self.voicevox_client.post(update_preset_url.clone()).json(&preset)).await
+            }
+            // 追加は2回送ると重複するため再試行しない
+            None => {
+                let add_preset_url = self.voicevox_url.join("/add_preset").context("Failed to join URL")?;
+                self.send_once(self.voicevox_client.post(add_preset_url).json(&preset)).await
+            }
+        };
+
+        match response {
+            Ok(res) => {
+                if res.status().is_success() {
+                    info!("Preset put successfully");
+                    // 登録したプリセットのIDが返される
+                    match res.json::<i64>().await {
+                        Ok(preset_id) => {
+                            self.preset_ids.lock().unwrap().insert(name.to_string(), preset_id);
+                        }
+                        Err(e) => warn!("Failed to read preset id: {}", e),
+                    }
+                    Ok(())
+                } else {
+                    warn!("Preset put failed with status code {}", res.status());
+                    Err(anyhow::anyhow!("Preset put failed with status code {}", res.status()))
+                }
+            }
+            Err(e) => {
+                error!("Failed to put preset:\n{}", e);
+                Err(anyhow::anyhow!("Failed to put preset:\n{}", e))
+            }
         }
+    }
 
-        Ok(())
+    /// プリセットを削除する。エンジンに無い場合は何もしない
+    #[instrument(skip(self))]
+    pub async fn delete_preset(&self, name: &str) -> Result<()> {
+        let Some(preset_id) = self.preset_id(name).await? else {
+            debug!("Preset is not registered in engine");
+            return Ok(());
+        };
+        debug!(preset_id, "Sending delete preset request to voicevox");
+
+        let mut delete_preset_url = self.voicevox_url
+            .join("/delete_preset")
+            .context("Failed to join URL")?;
+        delete_preset_url.query_pairs_mut().append_pair("id", &preset_id.to_string());
+
+        match self.send_once(self.voicevox_client.post(delete_preset_url)).await {
+            Ok(res) => {
+                if res.status().is_success() {
+                    info!("Preset delete successfully");
+                    self.preset_ids.lock().unwrap().remove(name);
+                    Ok(())
+                } else {
+                    warn!("Preset delete failed with status code {}", res.status());
+                    Err(anyhow::anyhow!("Preset delete failed with status code {}", res.status()))
+                }
+            }
+            Err(e) => {
+                error!("Failed to delete preset:\n{}", e);
+                Err(anyhow::anyhow!("Failed to delete preset:\n{}", e))
+            }
+        }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+pub(crate) mod tests;
@@ -0,0 +1,433 @@
+//! ローカルのモックサーバーに対してクライアントが送るリクエストを確認する
+
+use super::*;
+use crate::config::EngineEndpoint;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+const USER_DICT: &str = r#"{
+    "2b6f8d2e-0000-4000-8000-000000000001": {"surface": "ｍｉｄｏｒｉｎ", "pronunciation": "ミドリン", "accent_type": 1, "priority": 10, "part_of_speech": "名詞", "part_of_speech_detail_1": "固有名詞"},
+    "2b6f8d2e-0000-4000-8000-000000000002": {"surface": "鸚鵡", "pronunciation": "オウム", "accent_type": 0, "priority": 5, "part_of_speech": "名詞", "part_of_speech_detail_1": "一般"}
+}"#;
+const AUDIO_QUERY: &str = r#"{"accent_phrases": [], "speedScale": 1.0, "kana": ""}"#;
+const ADDED_WORD_UUID: &str = "2b6f8d2e-0000-4000-8000-000000000003";
+const VERSION: &str = "\"0.14.0\"";
+const PRESETS: &str = r#"[{"id": 3, "name": "1/通常", "speaker_uuid": "7ffcb7ce-00ec-4bdc-82cd-45a8889e43ff", "style_id": 8, "speedScale": 1.0}]"#;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct RecordedRequest {
+    method: String,
+    path: String,
+    query: Vec<(String, String)>,
+}
+
+impl RecordedRequest {
+    fn new(method: &str, path: &str, query: &[(&str, &str)]) -> Self {
+        Self {
+            method: method.to_string(),
+            path: path.to_string(),
+            query: query.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect(),
+        }
+    }
+}
+
+/// `GET /user_dict`には`USER_DICT`を、`POST /user_dict_word`には追加した単語のIDを、`POST /audio_query`には空の音声クエリを、
+/// `GET /is_initialized_speaker`には話者1だけ読み込み済みと、`GET /presets`には`PRESETS`を、プリセットの登録には登録したIDを、
+/// `GET /version`には`VERSION`を返し、
+/// 起動中のエンジンのように`POST /synthesis`には503を、それ以外には204を返すVOICEVOXのモック
+struct MockVoicevox {
+    url: Url,
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+}
+
+impl MockVoicevox {
+    async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let recorded = requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let Ok((mut stream, _)) = listener.accept().await else {
+                    return;
+                };
+                let recorded = recorded.clone();
+                tokio::spawn(async move {
+                    let Some(request) = read_request(&mut stream).await else {
+                        return;
+                    };
+                    let response = match (request.method.as_str(), request.path.as_str()) {
+                        ("GET", "/user_dict") => json_response(USER_DICT),
+                        ("POST", "/user_dict_word") => json_response(&format!("\"{}\"", ADDED_WORD_UUID)),
+                        ("POST", "/audio_query" | "/audio_query_from_preset") => json_response(AUDIO_QUERY),
+                        ("GET", "/presets") => json_response(PRESETS),
+                        ("GET", "/version") => json_response(VERSION),
+                        ("POST", "/add_preset" | "/update_preset") => json_response("3"),
+                        ("GET", "/is_initialized_speaker") => {
+                            let initialized = request.query.iter().any(|(key, value)| key == "speaker" && value == "1");
+                            json_response(&initialized.to_string())
+                        }
+                        ("POST", "/synthesis") => "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+                        _ => "HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n".to_string(),
+                    };
+                    recorded.lock().unwrap().push(request);
+                    let _ = stream.write_all(response.as_bytes()).await;
+                    let _ = stream.shutdown().await;
+                });
+            }
+        });
+
+        Self { url, requests }
+    }
+
+    fn client(&self) -> Client {
+        client_for(std::slice::from_ref(&self.url))
+    }
+
+    fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+/// テスト用に、リトライの待ち時間を短くしてヘルスチェックを止めたクライアントを作る
+pub(crate) fn client_for(urls: &[Url]) -> Client {
+    Client::new(Config {
+        database_url: "sqlite::memory:".to_string(),
+        discord_token: String::new(),
+        guild_id: "0".to_string(),
+        voicevox_endpoints: urls.iter().map(|url| EngineEndpoint { url: url.clone(), weight: 1 }).collect(),
+        default_speaker_id: 1,
+        warmup_speaker_ids: Vec::new(),
+        default_speed_scale: 1.0,
+        request_timeout_secs: 5,
+        idle_timeout_secs: 600,
+        retry_max_attempts: 3,
+        retry_base_delay_ms: 1,
+        circuit_failure_threshold: 2,
+        circuit_open_secs: 60,
+        health_check_interval_secs: 0,
+    })
+    .unwrap()
+}
+
+/// 接続を受け付けないURL。止まっているエンジンの代わりに使う
+pub(crate) async fn unreachable_url() -> Url {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap()
+}
+
+fn json_response(body: &str) -> String {
+    format!("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body)
+}
+
+async fn read_request(stream: &mut tokio::net::TcpStream) -> Option<RecordedRequest> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 1024];
+
+    // ボディは使わないので、ヘッダーの終わりまで読めば十分
+    while !buffer.windows(4).any(|window| window == b"\r\n\r\n") {
+        let read = stream.read(&mut chunk).await.ok()?;
+        if read == 0 {
+            return None;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    }
+
+    let head = String::from_utf8_lossy(&buffer);
+    let mut request_line = head.lines().next()?.split(' ');
+    let method = request_line.next()?.to_string();
+    let target = Url::parse(&format!("http://localhost{}", request_line.next()?)).ok()?;
+
+    Some(RecordedRequest {
+        method,
+        path: target.path().to_string(),
+        query: target.query_pairs().map(|(key, value)| (key.into_owned(), value.into_owned())).collect(),
+    })
+}
+
+#[tokio::test]
+async fn add_dict_word_posts_all_parameters() {
+    let mock = MockVoicevox::start().await;
+
+    mock.client().add_dict_word("鸚鵡", "オウム", 0, Some(WordType::CommonNoun), 5).await.unwrap();
+
+    assert_eq!(mock.requests(), vec![
+        RecordedRequest::new("POST", "/user_dict_word", &[
+            ("surface", "鸚鵡"),
+            ("pronunciation", "オウム"),
+            ("accent_type", "0"),
+            ("word_type", "COMMON_NOUN"),
+            ("priority", "5"),
+        ]),
+    ]);
+}
+
+#[tokio::test]
+async fn rewrite_dict_word_puts_to_word_uuid() {
+    let mock = MockVoicevox::start().await;
+
+    mock.client().rewrite_dict_word("鸚鵡", "インコ", 1, None, 10).await.unwrap();
+
+    assert_eq!(mock.requests(), vec![
+        RecordedRequest::new("GET", "/user_dict", &[]),
+        RecordedRequest::new("PUT", "/user_dict_word/2b6f8d2e-0000-4000-8000-000000000002", &[
+            ("surface", "鸚鵡"),
+            ("pronunciation", "インコ"),
+            ("accent_type", "1"),
+            ("word_type", "PROPER_NOUN"),
+            ("priority", "10"),
+        ]),
+    ]);
+}
+
+#[tokio::test]
+async fn rewrite_dict_word_matches_full_width_surface() {
+    let mock = MockVoicevox::start().await;
+
+    mock.client().rewrite_dict_word("midorin", "ミドリン", 0, Some(WordType::ProperNoun), 8).await.unwrap();
+
+    let requests = mock.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[1].method, "PUT");
+    assert_eq!(requests[1].path, "/user_dict_word/2b6f8d2e-0000-4000-8000-000000000001");
+}
+
+#[tokio::test]
+async fn rewrite_dict_word_without_match_sends_nothing() {
+    let mock = MockVoicevox::start().await;
+
+    let result = mock.client().rewrite_dict_word("存在しない", "ソンザイシナイ", 0, None, 10).await;
+
+    assert!(result.is_err());
+    assert_eq!(mock.requests(), vec![RecordedRequest::new("GET", "/user_dict", &[])]);
+}
+
+#[tokio::test]
+async fn delete_dict_word_deletes_word_uuid() {
+    let mock = MockVoicevox::start().await;
+
+    mock.client().delete_dict_word("鸚鵡").await.unwrap();
+
+    assert_eq!(mock.requests(), vec![
+        RecordedRequest::new("GET", "/user_dict", &[]),
+        RecordedRequest::new("DELETE", "/user_dict_word/2b6f8d2e-0000-4000-8000-000000000002", &[]),
+    ]);
+}
+
+#[tokio::test]
+async fn delete_dict_word_without_match_sends_nothing() {
+    let mock = MockVoicevox::start().await;
+
+    assert!(mock.client().delete_dict_word("存在しない").await.is_err());
+    assert_eq!(mock.requests(), vec![RecordedRequest::new("GET", "/user_dict", &[])]);
+}
+
+#[tokio::test]
+async fn find_uuid_by_surface_returns_none_when_missing() {
+    let mock = MockVoicevox::start().await;
+
+    let client = mock.client();
+    assert_eq!(client.primary().find_uuid_by_surface("鸚鵡").await.unwrap().as_deref(), Some("2b6f8d2e-0000-4000-8000-000000000002"));
+    assert_eq!(client.primary().find_uuid_by_surface("存在しない").await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn lookups_reuse_fetched_user_dict() {
+    let mock = MockVoicevox::start().await;
+
+    let client = mock.client();
+    client.rewrite_dict_word("鸚鵡", "インコ", 1, None, 10).await.unwrap();
+    client.rewrite_dict_word("midorin", "ミドリン", 0, None, 10).await.unwrap();
+
+    let methods = mock.requests().into_iter().map(|request| request.method).collect::<Vec<_>>();
+    assert_eq!(methods, vec!["GET", "PUT", "PUT"]);
+}
+
+#[tokio::test]
+async fn added_word_is_deleted_without_fetching_user_dict() {
+    let mock = MockVoicevox::start().await;
+
+    let client = mock.client();
+    client.add_dict_word("鳩", "ハト", 1, None, 10).await.unwrap();
+    client.delete_dict_word("鳩").await.unwrap();
+
+    let requests = mock.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0].method, "POST");
+    assert_eq!(requests[1], RecordedRequest::new("DELETE", &format!("/user_dict_word/{}", ADDED_WORD_UUID), &[]));
+}
+
+#[tokio::test]
+async fn synthesis_is_retried_on_server_error() {
+    let mock = MockVoicevox::start().await;
+
+    assert!(mock.client().synthesis("{}", 1).await.is_err());
+
+    let paths = mock.requests().into_iter().map(|request| request.path).collect::<Vec<_>>();
+    assert_eq!(paths, vec!["/synthesis", "/synthesis", "/synthesis"]);
+}
+
+#[tokio::test]
+async fn circuit_opens_after_repeated_failures() {
+    let mock = MockVoicevox::start().await;
+
+    let client = mock.client();
+    assert!(client.synthesis("{}", 1).await.is_err());
+    assert_eq!(client.outage(), None);
+    assert!(client.synthesis("{}", 1).await.is_err());
+    assert_eq!(client.outage(), Some(1));
+
+    // 止めている間はエンジンにリクエストを送らない
+    assert!(client.get_user_dict().await.is_err());
+    assert_eq!(mock.requests().len(), 6);
+}
+
+#[tokio::test]
+async fn success_resets_circuit_failures() {
+    let mock = MockVoicevox::start().await;
+
+    let client = mock.client();
+    assert!(client.synthesis("{}", 1).await.is_err());
+    client.get_user_dict().await.unwrap();
+    assert!(client.synthesis("{}", 1).await.is_err());
+
+    assert_eq!(client.outage(), None);
+}
+
+#[tokio::test]
+async fn dictionary_changes_are_mirrored_to_all_engines() {
+    let first = MockVoicevox::start().await;
+    let second = MockVoicevox::start().await;
+
+    let client = client_for(&[first.url.clone(), second.url.clone()]);
+    client.add_dict_word("鳩", "ハト", 1, None, 10).await.unwrap();
+    client.rewrite_dict_word("鸚鵡", "インコ", 1, None, 10).await.unwrap();
+
+    for mock in [&first, &second] {
+        let methods = mock.requests().into_iter().map(|request| request.method).collect::<Vec<_>>();
+        assert_eq!(methods, vec!["POST", "GET", "PUT"]);
+    }
+}
+
+#[tokio::test]
+async fn audio_query_fails_over_to_another_engine() {
+    let mock = MockVoicevox::start().await;
+
+    let client = client_for(&[unreachable_url().await, mock.url.clone()]);
+    client.create_audio_query("こんにちは", 1, 1.0).await.unwrap();
+
+    assert_eq!(mock.requests().len(), 1);
+    assert_eq!(mock.requests()[0].path, "/audio_query");
+}
+
+#[tokio::test]
+async fn mirrored_change_reports_unreachable_engine() {
+    let mock = MockVoicevox::start().await;
+
+    let client = client_for(&[mock.url.clone(), unreachable_url().await]);
+    assert!(client.add_dict_word("鳩", "ハト", 1, None, 10).await.is_err());
+
+    // 届いたエンジンには反映され、届かなかったエンジンは同期し直す
+    assert_eq!(mock.requests().len(), 1);
+    assert!(client.engines()[1].needs_sync.load(Ordering::Relaxed));
+}
+
+#[tokio::test]
+async fn mirrored_change_skips_paused_engine_until_resync() {
+    let first = MockVoicevox::start().await;
+    let second = MockVoicevox::start().await;
+
+    let client = client_for(&[first.url.clone(), second.url.clone()]);
+    let paused = &client.engines()[1];
+    paused.breaker.record_failure();
+    paused.breaker.record_failure();
+
+    let result = client.add_dict_word("鳩", "ハト", 1, None, 10).await;
+
+    assert!(result.unwrap_err().is::<EnginesPending>());
+    assert_eq!(first.requests().len(), 1);
+    assert!(second.requests().is_empty());
+    assert!(paused.needs_sync.load(Ordering::Relaxed));
+    assert!(!client.primary().needs_sync.load(Ordering::Relaxed));
+}
+
+#[tokio::test]
+async fn warm_up_initializes_only_unloaded_speakers() {
+    let mock = MockVoicevox::start().await;
+    let client = mock.client();
+
+    client.primary().warm_up(&[1, 3]).await;
+
+    assert_eq!(
+        mock.requests(),
+        vec![
+            RecordedRequest::new("GET", "/is_initialized_speaker", &[("speaker", "1")]),
+            RecordedRequest::new("GET", "/is_initialized_speaker", &[("speaker", "3")]),
+            RecordedRequest::new("POST", "/initialize_speaker", &[("speaker", "3"), ("skip_reinit", "true")]),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn put_preset_updates_existing_preset_and_adds_new_one() {
+    let mock = MockVoicevox::start().await;
+    let client = mock.client();
+
+    client.put_preset(&json!({"id": 0, "name": "1/通常"})).await.unwrap();
+    client.put_preset(&json!({"id": 0, "name": "1/早口"})).await.unwrap();
+
+    let requests = mock.requests().into_iter().map(|request| (request.method, request.path)).collect::<Vec<_>>();
+    let expected = [("GET", "/presets"), ("POST", "/update_preset"), ("GET", "/presets"), ("POST", "/add_preset")];
+    assert_eq!(requests, expected.map(|(method, path)| (method.to_string(), path.to_string())));
+}
+
+#[tokio::test]
+async fn audio_query_from_preset_uses_engine_preset_id() {
+    let mock = MockVoicevox::start().await;
+    let client = mock.client();
+
+    client.create_audio_query_from_preset("こんにちは", "1/通常").await.unwrap();
+
+    assert_eq!(
+        mock.requests().last(),
+        Some(&RecordedRequest::new("POST", "/audio_query_from_preset", &[("text", "こんにちは"), ("preset_id", "3")]))
+    );
+}
+
+#[tokio::test]
+async fn audio_query_from_preset_skips_engine_without_preset() {
+    let first = MockVoicevox::start().await;
+    let second = MockVoicevox::start().await;
+    let client = client_for(&[first.url.clone(), second.url.clone()]);
+    client.engines()[1].preset_ids.lock().unwrap().insert("2/特別".to_string(), 5);
+
+    client.create_audio_query_from_preset("こんにちは", "2/特別").await.unwrap();
+
+    // プリセットの無いエンジンには送らず、失敗としても数えない
+    assert_eq!(first.requests(), vec![RecordedRequest::new("GET", "/presets", &[])]);
+    assert_eq!(
+        second.requests(),
+        vec![RecordedRequest::new("POST", "/audio_query_from_preset", &[("text", "こんにちは"), ("preset_id", "5")])]
+    );
+    assert_eq!(client.outage(), None);
+}
+
+#[tokio::test]
+async fn recovered_engine_is_marked_for_resync() {
+    let mock = MockVoicevox::start().await;
+    let client = mock.client();
+    let engine = client.primary();
+    engine.find_uuid_by_surface("鸚鵡").await.unwrap();
+    engine.preset_id("1/通常").await.unwrap();
+    engine.healthy.store(false, Ordering::Relaxed);
+
+    engine.check_health().await;
+
+    // 再起動したエンジンでは単語IDとプリセットIDが変わるため、対応表を捨てて同期し直す
+    assert!(engine.is_available());
+    assert!(engine.needs_sync.load(Ordering::Relaxed));
+    assert!(engine.word_uuids.lock().unwrap().is_empty());
+    assert!(engine.preset_ids.lock().unwrap().is_empty());
+}
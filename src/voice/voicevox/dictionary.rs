@@ -0,0 +1,849 @@
+use crate::database;
+use crate::voice::voicevox::client::{engine_surface, Client as VoicevoxClient, Engine, WordType, DEFAULT_PRIORITY};
+use crate::voice::voicevox::history::{self, ChangeAction, ChangeEntry};
+use crate::voice::voicevox::accent;
+use crate::voice::voicevox::kana;
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use serenity::model::id::{GuildId, UserId};
+use sqlx::{SqliteConnection, SqlitePool};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use tracing::{debug, info, warn, instrument};
+
+/// 以前の辞書をデータベースに取り込んだことを記録する名前
+const SEED_MIGRATION: &str = "seed_global_dictionary";
+
+/// 辞書の適用範囲
+///
+/// 全体の辞書はエンジンに同期され、サーバーの辞書は読み上げ前にBOTが置き換える
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    Global,
+    Guild(GuildId),
+}
+
+impl Scope {
+    /// データベース上では全体の辞書をギルドID 0として保存する
+    pub(super) fn guild_id(&self) -> i64 {
+        match self {
+            Scope::Global => 0,
+            Scope::Guild(guild_id) => guild_id.get() as i64,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Scope::Global => "全体",
+            Scope::Guild(_) => "サーバー",
+        }
+    }
+}
+
+/// データベースに保存される辞書の単語。エンジンの辞書は全体の単語に合わせて同期される
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, sqlx::FromRow)]
+pub struct DictionaryWord {
+    pub surface: String,
+    pub pronunciation: String,
+    pub accent_type: i64,
+    #[sqlx(try_from = "String")]
+    pub word_type: WordType,
+    pub priority: i64,
+}
+
+/// テスト用に、単語の種類と優先度を既定値にした単語を作る
+#[cfg(test)]
+pub(crate) fn test_word(surface: &str, pronunciation: &str, accent_type: i64) -> DictionaryWord {
+    DictionaryWord {
+        surface: surface.to_string(),
+        pronunciation: pronunciation.to_string(),
+        accent_type,
+        word_type: WordType::default(),
+        priority: DEFAULT_PRIORITY as i64,
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SyncReport {
+    pub added: usize,
+    pub updated: usize,
+    pub removed: usize,
+    pub failed: usize,
+}
+
+/// 補完候補と読み上げ時の置き換えに使う、データベースの辞書の索引
+///
+/// 辞書を変更したら`refresh`で読み込み直す。サーバーの辞書の正規表現もその時に作り直す
+#[derive(Debug, Clone, Default)]
+pub struct DictionaryIndex {
+    state: Arc<RwLock<IndexState>>,
+}
+
+#[derive(Debug, Default)]
+struct IndexState {
+    words: HashMap<i64, Vec<DictionaryWord>>,
+    /// 使われたサーバーの分だけ作る
+    guild_dictionaries: HashMap<GuildId, Arc<GuildDictionary>>,
+}
+
+/// (表層形, 読み)
+type IndexEntry = (String, String);
+
+impl DictionaryIndex {
+    pub async fn refresh(&self, pool: &SqlitePool) -> Result<()> {
+        let rows = sqlx::query_as::<_, (i64, String, String, i64, String, i64)>(
+            "SELECT guild_id, surface, pronunciation, accent_type, word_type, priority FROM dictionary_entry ORDER BY surface",
+        )
+            .fetch_all(pool)
+            .await
+            .context("Failed to load dictionary index")?;
+
+        let mut words: HashMap<i64, Vec<DictionaryWord>> = HashMap::new();
+        for (guild_id, surface, pronunciation, accent_type, word_type, priority) in rows {
+            let word_type = WordType::try_from(word_type).unwrap_or_default();
+            words.entry(guild_id).or_default().push(DictionaryWord { surface, pronunciation, accent_type, word_type, priority });
+        }
+
+        debug!(scopes = words.len(), "Refreshed dictionary index");
+        *self.state.write().unwrap() = IndexState { words, guild_dictionaries: HashMap::new() };
+        Ok(())
+    }
+
+    /// 読み上げ前に適用するサーバーの辞書
+    pub fn guild_dictionary(&self, guild_id: GuildId) -> Arc<GuildDictionary> {
+        if let Some(dictionary) = self.state.read().unwrap().guild_dictionaries.get(&guild_id) {
+            return dictionary.clone();
+        }
+
+        let mut state = self.state.write().unwrap();
+        let words = state.words.get(&Scope::Guild(guild_id).guild_id()).cloned().unwrap_or_default();
+        state
+            .guild_dictionaries
+            .entry(guild_id)
+            .or_insert_with(|| {
+                debug!(%guild_id, words = words.len(), "Compiling guild dictionary");
+                Arc::new(GuildDictionary::new(words))
+            })
+            .clone()
+    }
+
+    /// 入力途中の文字列に前方一致、次に部分一致する単語を`limit`件まで返す
+    pub fn suggest(&self, scope: Scope, partial: &str, limit: usize) -> Vec<IndexEntry> {
+        let state = self.state.read().unwrap();
+        let Some(words) = state.words.get(&scope.guild_id()) else {
+            return Vec::new();
+        };
+
+        let partial = partial.trim().to_lowercase();
+        let reading = kana::to_katakana(&partial);
+
+        let mut matches = words
+            .iter()
+            .filter_map(|DictionaryWord { surface, pronunciation, .. }| {
+                let surface_lower = surface.to_lowercase();
+                let rank = if surface_lower.starts_with(&partial) || pronunciation.starts_with(&reading) {
+                    0
+                } else if surface_lower.contains(&partial) || pronunciation.contains(&reading) {
+                    1
+                } else {
+                    return None;
+                };
+                Some((rank, surface, pronunciation))
+            })
+            .collect::<Vec<_>>();
+        // 並び順が表層形の順なので、安定ソートで同じ順位の中はその順を保つ
+        matches.sort_by_key(|(rank, _, _)| *rank);
+
+        matches
+            .into_iter()
+            .take(limit)
+            .map(|(_, surface, pronunciation)| (surface.clone(), pronunciation.clone()))
+            .collect()
+    }
+}
+
+pub async fn fetch(pool: &SqlitePool, scope: Scope, surface: &str) -> Result<Option<DictionaryWord>> {
+    let mut conn = pool.acquire().await.context("Failed to acquire connection")?;
+    fetch_word(&mut conn, scope, surface).await
+}
+
+pub async fn fetch_all(pool: &SqlitePool, scope: Scope) -> Result<Vec<DictionaryWord>> {
+    let mut conn = pool.acquire().await.context("Failed to acquire connection")?;
+    fetch_words(&mut conn, scope).await
+}
+
+pub(super) async fn fetch_word(conn: &mut SqliteConnection, scope: Scope, surface: &str) -> Result<Option<DictionaryWord>> {
+    sqlx::query_as::<_, DictionaryWord>("SELECT surface, pronunciation, accent_type, word_type, priority FROM dictionary_entry WHERE guild_id = ? AND surface = ?")
+        .bind(scope.guild_id())
+        .bind(surface)
+        .fetch_optional(conn)
+        .await
+        .context("Failed to fetch dictionary word")
+}
+
+pub(super) async fn fetch_words(conn: &mut SqliteConnection, scope: Scope) -> Result<Vec<DictionaryWord>> {
+    sqlx::query_as::<_, DictionaryWord>("SELECT surface, pronunciation, accent_type, word_type, priority FROM dictionary_entry WHERE guild_id = ? ORDER BY surface")
+        .bind(scope.guild_id())
+        .fetch_all(conn)
+        .await
+        .context("Failed to fetch dictionary words")
+}
+
+/// 単語を追加するか、同じ単語があれば置き換える
+pub(super) async fn put_word(conn: &mut SqliteConnection, scope: Scope, word: &DictionaryWord) -> Result<()> {
+    sqlx::query("INSERT OR REPLACE INTO dictionary_entry (guild_id, surface, pronunciation, accent_type, word_type, priority) VALUES (?, ?, ?, ?, ?, ?)")
+        .bind(scope.guild_id())
+        .bind(&word.surface)
+        .bind(&word.pronunciation)
+        .bind(word.accent_type)
+        .bind(word.word_type.as_str())
+        .bind(word.priority)
+        .execute(conn)
+        .await
+        .context("Failed to write dictionary word")?;
+    Ok(())
+}
+
+pub(super) async fn remove_word(conn: &mut SqliteConnection, scope: Scope, surface: &str) -> Result<()> {
+    sqlx::query("DELETE FROM dictionary_entry WHERE guild_id = ? AND surface = ?")
+        .bind(scope.guild_id())
+        .bind(surface)
+        .execute(conn)
+        .await
+        .context("Failed to delete dictionary word")?;
+    Ok(())
+}
+
+/// 単語を追加する。既に同じ単語がある場合は`false`を返す
+pub async fn insert(pool: &SqlitePool, scope: Scope, actor: UserId, word: &DictionaryWord) -> Result<bool> {
+    let mut tx = pool.begin().await.context("Failed to begin transaction")?;
+
+    if fetch_word(&mut tx, scope, &word.surface).await?.is_some() {
+        return Ok(false);
+    }
+    put_word(&mut tx, scope, word).await?;
+    history::record(&mut tx, scope, actor, ChangeAction::Add, &[ChangeEntry::new(None, Some(word.clone()))]).await?;
+
+    tx.commit().await.context("Failed to commit transaction")?;
+    Ok(true)
+}
+
+/// 単語を更新する。単語が無い場合は`false`を返す
+pub async fn update(pool: &SqlitePool, scope: Scope, actor: UserId, word: &DictionaryWord) -> Result<bool> {
+    let mut tx = pool.begin().await.context("Failed to begin transaction")?;
+
+    let Some(before) = fetch_word(&mut tx, scope, &word.surface).await? else {
+        return Ok(false);
+    };
+    put_word(&mut tx, scope, word).await?;
+    history::record(&mut tx, scope, actor, ChangeAction::Edit, &[ChangeEntry::new(Some(before), Some(word.clone()))]).await?;
+
+    tx.commit().await.context("Failed to commit transaction")?;
+    Ok(true)
+}
+
+pub async fn delete(pool: &SqlitePool, scope: Scope, actor: UserId, surface: &str) -> Result<bool> {
+    let mut tx = pool.begin().await.context("Failed to begin transaction")?;
+
+    let Some(before) = fetch_word(&mut tx, scope, surface).await? else {
+        return Ok(false);
+    };
+    remove_word(&mut tx, scope, surface).await?;
+    history::record(&mut tx, scope, actor, ChangeAction::Remove, &[ChangeEntry::new(Some(before), None)]).await?;
+
+    tx.commit().await.context("Failed to commit transaction")?;
+    Ok(true)
+}
+
+/// すべての単語を削除する。削除前の辞書はスナップショットとして残す
+pub async fn delete_all(pool: &SqlitePool, scope: Scope, actor: UserId) -> Result<u64> {
+    let mut tx = pool.begin().await.context("Failed to begin transaction")?;
+
+    let words = fetch_words(&mut tx, scope).await?;
+    if words.is_empty() {
+        return Ok(0);
+    }
+    history::create_snapshot(&mut tx, scope, actor, "リセット前").await?;
+
+    let result = sqlx::query("DELETE FROM dictionary_entry WHERE guild_id = ?")
+        .bind(scope.guild_id())
+        .execute(&mut *tx)
+        .await
+        .context("Failed to delete dictionary words")?;
+    let entries = words.into_iter().map(|word| ChangeEntry::new(Some(word), None)).collect::<Vec<_>>();
+    history::record(&mut tx, scope, actor, ChangeAction::Reset, &entries).await?;
+
+    tx.commit().await.context("Failed to commit transaction")?;
+    Ok(result.rows_affected())
+}
+
+async fn insert_many(pool: &SqlitePool, scope: Scope, words: &[DictionaryWord]) -> Result<()> {
+    let mut tx = pool.begin().await.context("Failed to begin transaction")?;
+
+    for word in words {
+        put_word(&mut tx, scope, word).await?;
+    }
+
+    tx.commit().await.context("Failed to commit transaction")?;
+    Ok(())
+}
+
+/// インポート時に既存の辞書をどう扱うか
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportMode {
+    /// 既存の単語を残し、ファイルの単語を追加・上書きする
+    Merge,
+    /// ファイルに無い単語を削除し、ファイルの内容に置き換える
+    Replace,
+}
+
+impl ImportMode {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ImportMode::Merge => "マージ",
+            ImportMode::Replace => "置き換え",
+        }
+    }
+}
+
+/// インポートを適用する前に表示する差分
+#[derive(Debug, Default)]
+pub struct ImportDiff {
+    pub added: Vec<DictionaryWord>,
+    /// 既存の単語と読みかアクセントが異なるもの (変更前, 変更後)
+    pub changed: Vec<(DictionaryWord, DictionaryWord)>,
+    /// ファイル内で同じ単語に異なる読みが指定されているもの。適用されない
+    pub conflicting: Vec<String>,
+    pub removed: Vec<DictionaryWord>,
+    pub unchanged: usize,
+}
+
+impl ImportDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+}
+
+pub fn diff_import(current: &[DictionaryWord], imported: Vec<DictionaryWord>, mode: ImportMode) -> ImportDiff {
+    let mut diff = ImportDiff::default();
+
+    let mut by_surface: HashMap<String, DictionaryWord> = HashMap::new();
+    let mut conflicting = Vec::new();
+    for word in imported {
+        match by_surface.get(&word.surface) {
+            Some(existing) if *existing != word => {
+                if !conflicting.contains(&word.surface) {
+                    conflicting.push(word.surface.clone());
+                }
+            }
+            Some(_) => {}
+            None => {
+                by_surface.insert(word.surface.clone(), word);
+            }
+        }
+    }
+    for surface in &conflicting {
+        by_surface.remove(surface);
+    }
+    diff.conflicting = conflicting;
+
+    for word in current {
+        match by_surface.remove(&word.surface) {
+            Some(new_word) if new_word == *word => diff.unchanged += 1,
+            Some(new_word) => diff.changed.push((word.clone(), new_word)),
+            // 競合した単語は置き換えモードでも削除しない
+            None if mode == ImportMode::Replace && !diff.conflicting.contains(&word.surface) => diff.removed.push(word.clone()),
+            None => {}
+        }
+    }
+
+    diff.added = by_surface.into_values().collect();
+    diff.added.sort_by(|a, b| a.surface.cmp(&b.surface));
+    diff
+}
+
+/// ファイルの単語をインポートし、適用した差分を返す。適用前の辞書はスナップショットとして残す
+///
+/// 確認を待つ間に辞書が変わっていることがあるので、差分は適用する時点の辞書から計算し直す
+pub async fn apply_import(pool: &SqlitePool, scope: Scope, actor: UserId, imported: Vec<DictionaryWord>, mode: ImportMode) -> Result<ImportDiff> {
+    let mut tx = pool.begin().await.context("Failed to begin transaction")?;
+
+    let current = fetch_words(&mut tx, scope).await?;
+    let diff = diff_import(&current, imported, mode);
+    if !diff.is_empty() {
+        history::create_snapshot(&mut tx, scope, actor, "インポート前").await?;
+        apply_diff(&mut tx, scope, &diff).await?;
+        history::record(&mut tx, scope, actor, ChangeAction::Import, &ChangeEntry::from_diff(&diff)).await?;
+    }
+
+    tx.commit().await.context("Failed to commit transaction")?;
+    Ok(diff)
+}
+
+pub(super) async fn apply_diff(conn: &mut SqliteConnection, scope: Scope, diff: &ImportDiff) -> Result<()> {
+    for word in diff.added.iter().chain(diff.changed.iter().map(|(_, new_word)| new_word)) {
+        put_word(conn, scope, word).await?;
+    }
+    for word in &diff.removed {
+        remove_word(conn, scope, &word.surface).await?;
+    }
+    Ok(())
+}
+
+/// VOICEVOXの`/user_dict`と同じ形式のJSONに変換する
+pub fn to_engine_json(words: &[DictionaryWord]) -> Result<String> {
+    let dict: serde_json::Map<String, Value> = words
+        .iter()
+        .map(|word| (uuid::Uuid::new_v4().to_string(), serde_json::json!({
+            "surface": word.surface,
+            "pronunciation": word.pronunciation,
+            "accent_type": word.accent_type,
+            "word_type": word.word_type.as_str(),
+            "priority": word.priority,
+        })))
+        .collect();
+
+    serde_json::to_string_pretty(&dict).context("Failed to serialize dictionary")
+}
+
+pub fn to_csv(words: &[DictionaryWord]) -> String {
+    let mut csv = String::from("surface,pronunciation,accent_type,word_type,priority\n");
+    for word in words {
+        csv.push_str(&format!(
+            "{},{},{},{},{}\n",
+            escape_csv_field(&word.surface), escape_csv_field(&word.pronunciation), word.accent_type, word.word_type.as_str(), word.priority,
+        ));
+    }
+    csv
+}
+
+fn escape_csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// 読み上げ前にBOTが置き換えるサーバーの辞書
+///
+/// 置き換えた読みはエンジンの辞書に一致しなくなるため、全体の辞書より優先される。
+/// アクセントは置き換えた後の音声クエリに`apply_accents`で反映する
+#[derive(Debug)]
+pub struct GuildDictionary {
+    pattern: Option<Regex>,
+    words: HashMap<String, DictionaryWord>,
+}
+
+impl GuildDictionary {
+    fn new(words: Vec<DictionaryWord>) -> Self {
+        // 長い単語から順に試し、部分的に重なる単語では長い方を優先する
+        let mut surfaces = words.iter().map(|word| word.surface.as_str()).collect::<Vec<_>>();
+        surfaces.sort_by_key(|surface| std::cmp::Reverse(surface.chars().count()));
+
+        let pattern = if surfaces.is_empty() {
+            None
+        } else {
+            let pattern = surfaces.iter().map(|surface| regex::escape(surface)).collect::<Vec<_>>().join("|");
+            Regex::new(&pattern).inspect_err(|e| warn!("Failed to build guild dictionary pattern: {}", e)).ok()
+        };
+
+        Self {
+            pattern,
+            words: words.into_iter().map(|word| (word.surface.clone(), word)).collect(),
+        }
+    }
+
+    /// 単語を読みに置き換え、置き換えた単語を返す
+    pub fn replace(&self, text: &str) -> (String, Vec<DictionaryWord>) {
+        let Some(pattern) = &self.pattern else {
+            return (text.to_string(), Vec::new());
+        };
+
+        let mut replaced = Vec::<DictionaryWord>::new();
+        let text = pattern
+            .replace_all(text, |caps: &regex::Captures| match self.words.get(&caps[0]) {
+                Some(word) => {
+                    if !replaced.iter().any(|replaced| replaced.surface == word.surface) {
+                        replaced.push(word.clone());
+                    }
+                    word.pronunciation.clone()
+                }
+                None => caps[0].to_string(),
+            })
+            .to_string();
+        (text, replaced)
+    }
+
+    /// 置き換えた単語の読みで始まるアクセント句に、辞書のアクセントを設定して音高を計算し直す
+    ///
+    /// エンジンが単語を複数のアクセント句に分けた場合は、エンジンのアクセントのままにする
+    pub async fn apply_accents(voicevox_client: &VoicevoxClient, audio_query: &str, words: &[DictionaryWord], speaker_id: u8) -> Result<String> {
+        if words.is_empty() {
+            return Ok(audio_query.to_string());
+        }
+
+        let query: Value = serde_json::from_str(audio_query).context("Failed to parse audio query")?;
+        let mut accent_phrases = query["accent_phrases"].clone();
+        let Some(phrases) = accent_phrases.as_array_mut() else {
+            return Ok(audio_query.to_string());
+        };
+        if !set_word_accents(phrases, words) {
+            return Ok(audio_query.to_string());
+        }
+
+        let accent_phrases = voicevox_client.create_mora_pitch(&accent_phrases, speaker_id).await?;
+        accent::replace_accent_phrases(audio_query, accent_phrases)
+    }
+}
+
+/// 単語の読みで始まるアクセント句の`accent`を書き換える。書き換えたら`true`を返す
+fn set_word_accents(phrases: &mut [Value], words: &[DictionaryWord]) -> bool {
+    let words = words.iter().map(|word| (kana::split_morae(&word.pronunciation), word.accent_type)).collect::<Vec<_>>();
+
+    let mut changed = false;
+    for phrase in phrases {
+        let morae = phrase["moras"]
+            .as_array()
+            .map(|moras| moras.iter().map(|mora| mora["text"].as_str().unwrap_or_default()).collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        // 長い読みの単語を優先する
+        let Some((word_morae, accent_type)) = words
+            .iter()
+            .filter(|(word_morae, _)| {
+                !word_morae.is_empty() && morae.len() >= word_morae.len() && morae.iter().zip(word_morae).all(|(mora, word_mora)| mora == word_mora)
+            })
+            .max_by_key(|(word_morae, _)| word_morae.len())
+        else {
+            continue;
+        };
+
+        // 平板型は下がり目が無いので、助詞などを含めたアクセント句の最後のモーラにする
+        let accent = match usize::try_from(*accent_type) {
+            Ok(accent_type) if (1..=word_morae.len()).contains(&accent_type) => accent_type,
+            _ => morae.len(),
+        };
+        if phrase["accent"].as_u64() != Some(accent as u64) {
+            phrase["accent"] = Value::from(accent);
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// VOICEVOXの`/user_dict`形式のJSONを単語IDと単語の組に変換する
+pub fn parse_engine_dict(json: &str) -> Result<Vec<(String, DictionaryWord)>> {
+    let dict: Value = serde_json::from_str(json).context("Failed to parse user dict")?;
+    let Some(dict) = dict.as_object() else {
+        return Err(anyhow::anyhow!("User dict is not an object"));
+    };
+
+    let mut words = Vec::new();
+    for (uuid, entry) in dict {
+        let surface = entry.get("surface").and_then(|v| v.as_str());
+        let pronunciation = entry.get("pronunciation").and_then(|v| v.as_str());
+        let accent_type = entry.get("accent_type").and_then(|v| v.as_i64());
+
+        match (surface, pronunciation, accent_type) {
+            (Some(surface), Some(pronunciation), Some(accent_type)) => words.push((uuid.clone(), DictionaryWord {
+                surface: surface.to_string(),
+                pronunciation: pronunciation.to_string(),
+                accent_type,
+                word_type: engine_word_type(entry),
+                priority: entry.get("priority").and_then(|v| v.as_i64()).unwrap_or(DEFAULT_PRIORITY as i64),
+            })),
+            _ => warn!(uuid, "Skipping malformed user dict entry"),
+        }
+    }
+
+    Ok(words)
+}
+
+/// エンジンの辞書は品詞で、書き出したファイルは`word_type`で単語の種類を持つ
+fn engine_word_type(entry: &Value) -> WordType {
+    if let Some(word_type) = entry.get("word_type").and_then(|v| v.as_str()).and_then(|v| v.parse().ok()) {
+        return word_type;
+    }
+
+    let part_of_speech = entry.get("part_of_speech").and_then(|v| v.as_str()).unwrap_or_default();
+    let detail = entry.get("part_of_speech_detail_1").and_then(|v| v.as_str()).unwrap_or_default();
+    WordType::from_part_of_speech(part_of_speech, detail)
+}
+
+/// 辞書が空の場合、以前の`user_dict.json`かエンジンの辞書から単語を取り込む
+///
+/// データベースに移行するための処理なので一度だけ行い、後で辞書をリセットしても取り込み直さない
+#[instrument(skip(pool, voicevox_client))]
+pub async fn seed_if_empty(pool: &SqlitePool, voicevox_client: &VoicevoxClient) -> Result<()> {
+    if database::migration_applied(pool, SEED_MIGRATION).await? {
+        return Ok(());
+    }
+
+    let count = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM dictionary_entry WHERE guild_id = ?")
+        .bind(Scope::Global.guild_id())
+        .fetch_one(pool)
+        .await
+        .context("Failed to count dictionary words")?;
+
+    // 以前のバージョンで取り込み済みの場合は、記録だけ残す
+    if count > 0 {
+        return database::mark_migration_applied(pool, SEED_MIGRATION).await;
+    }
+
+    let source = match tokio::fs::read_to_string("user_dict.json").await {
+        Ok(data) => {
+            info!("Importing dictionary from user_dict.json");
+            data
+        }
+        Err(_) => {
+            info!("Importing dictionary from voicevox engine");
+            voicevox_client.get_user_dict().await?
+        }
+    };
+
+    let words: Vec<DictionaryWord> = parse_engine_dict(&source)?.into_iter().map(|(_, word)| word).collect();
+    insert_many(pool, Scope::Global, &words).await?;
+    database::mark_migration_applied(pool, SEED_MIGRATION).await?;
+    info!("Imported {} words into the dictionary", words.len());
+    Ok(())
+}
+
+/// データベースの全体の辞書をすべてのエンジンに反映する
+///
+/// 起動時や辞書をまとめて変更したときに呼び出し、エンジン側にしか無い単語は削除する
+#[instrument(skip(pool, voicevox_client))]
+pub async fn sync_to_engine(pool: &SqlitePool, voicevox_client: &VoicevoxClient) -> Result<SyncReport> {
+    let words = fetch_all(pool, Scope::Global).await?;
+    let mut report = SyncReport::default();
+    let mut reached = 0;
+    let mut last_error = None;
+
+    // 単語IDはエンジンごとに違うため、エンジンごとに辞書を比べる。一部のエンジンに届かない場合は失敗した件数に含める
+    for engine in voicevox_client.engines() {
+        if let Err(e) = sync_engine(engine, &words, &mut report).await {
+            warn!(url = %engine.url(), "Failed to synchronize dictionary to engine: {}", e);
+            engine.mark_needs_sync();
+            report.failed += words.len();
+            last_error = Some(e);
+        } else {
+            reached += 1;
+        }
+    }
+    if reached == 0
+        && let Some(e) = last_error
+    {
+        return Err(e);
+    }
+
+    info!(added = report.added, updated = report.updated, removed = report.removed, failed = report.failed, "Synchronized dictionary to engine");
+    Ok(report)
+}
+
+/// データベースの全体の辞書を1台のエンジンに反映する。再起動したエンジンを同期し直すのに使う
+#[instrument(skip(pool, engine), fields(url = %engine.url()))]
+pub async fn resync_engine(pool: &SqlitePool, engine: &Engine) -> Result<SyncReport> {
+    let words = fetch_all(pool, Scope::Global).await?;
+    let mut report = SyncReport::default();
+    sync_engine(engine, &words, &mut report).await?;
+
+    info!(added = report.added, updated = report.updated, removed = report.removed, failed = report.failed, "Synchronized dictionary to engine");
+    Ok(report)
+}
+
+async fn sync_engine(engine: &Engine, words: &[DictionaryWord], report: &mut SyncReport) -> Result<()> {
+    let engine_words = parse_engine_dict(&engine.get_user_dict().await?)?;
+
+    let mut engine_by_surface: HashMap<String, (String, DictionaryWord)> = HashMap::new();
+
+    for (uuid, word) in engine_words {
+        // 同じ表層形が重複している場合は余分なものを削除する
+        if engine_by_surface.contains_key(&word.surface) {
+            remove_engine_word(engine, &uuid, report).await;
+            continue;
+        }
+        engine_by_surface.insert(word.surface.clone(), (uuid, word));
+    }
+
+    for word in words {
+        match engine_by_surface.remove(&engine_surface(&word.surface)) {
+            Some((_, engine_word)) if engine_word.pronunciation == word.pronunciation
+                && engine_word.accent_type == word.accent_type
+                && engine_word.word_type == word.word_type
+                && engine_word.priority == word.priority => {}
+            Some((uuid, _)) => {
+                debug!(surface = %word.surface, "Updating word in engine");
+                match rewrite_engine_word(engine, &uuid, word).await {
+                    Ok(()) => report.updated += 1,
+                    Err(e) => {
+                        warn!(surface = %word.surface, "Failed to update word in engine: {}", e);
+                        report.failed += 1;
+                    }
+                }
+            }
+            None => {
+                debug!(surface = %word.surface, "Adding word to engine");
+                match add_engine_word(engine, word).await {
+                    Ok(()) => report.added += 1,
+                    Err(e) => {
+                        warn!(surface = %word.surface, "Failed to add word to engine: {}", e);
+                        report.failed += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    for (uuid, _) in engine_by_surface.into_values() {
+        remove_engine_word(engine, &uuid, report).await;
+    }
+
+    Ok(())
+}
+
+async fn add_engine_word(engine: &Engine, word: &DictionaryWord) -> Result<()> {
+    let accent_type = u8::try_from(word.accent_type).context("Accent type out of range")?;
+    let priority = u8::try_from(word.priority).context("Priority out of range")?;
+    engine.add_dict_word(&word.surface, &word.pronunciation, accent_type, Some(word.word_type), priority).await
+}
+
+async fn rewrite_engine_word(engine: &Engine, uuid: &str, word: &DictionaryWord) -> Result<()> {
+    let accent_type = u8::try_from(word.accent_type).context("Accent type out of range")?;
+    let priority = u8::try_from(word.priority).context("Priority out of range")?;
+    engine.rewrite_dict_word_by_uuid(uuid, &word.surface, &word.pronunciation, accent_type, Some(word.word_type), priority).await
+}
+
+async fn remove_engine_word(engine: &Engine, uuid: &str, report: &mut SyncReport) {
+    match engine.delete_dict_word_by_uuid(uuid).await {
+        Ok(()) => report.removed += 1,
+        Err(e) => {
+            warn!(uuid, "Failed to remove word from engine: {}", e);
+            report.failed += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voice::voicevox::client::tests as client_tests;
+
+    fn phrase(morae: &[&str], accent: usize) -> Value {
+        serde_json::json!({
+            "moras": morae.iter().map(|mora| serde_json::json!({"text": mora, "pitch": 5.0})).collect::<Vec<_>>(),
+            "accent": accent,
+        })
+    }
+
+    fn surfaces(words: &[DictionaryWord]) -> Vec<&str> {
+        words.iter().map(|word| word.surface.as_str()).collect()
+    }
+
+    #[test]
+    fn diff_import_merge_keeps_missing_words_and_skips_conflicts() {
+        let current = [test_word("緑", "ミドリ", 0), test_word("青", "アオ", 1), test_word("赤", "アカ", 1)];
+        let imported = vec![test_word("緑", "ミドリ", 0), test_word("青", "アオイロ", 1), test_word("黄", "キ", 1), test_word("紫", "ムラサキ", 0), test_word("紫", "パープル", 1)];
+
+        let diff = diff_import(&current, imported, ImportMode::Merge);
+
+        assert_eq!(surfaces(&diff.added), ["黄"]);
+        assert_eq!(diff.changed, [(test_word("青", "アオ", 1), test_word("青", "アオイロ", 1))]);
+        assert_eq!(diff.conflicting, ["紫"]);
+        assert!(diff.removed.is_empty());
+        assert_eq!(diff.unchanged, 1);
+    }
+
+    #[test]
+    fn diff_import_replace_removes_missing_words_except_conflicts() {
+        let current = [test_word("緑", "ミドリ", 0), test_word("赤", "アカ", 1), test_word("紫", "ムラサキ", 0)];
+        let imported = vec![test_word("緑", "ミドリ", 0), test_word("紫", "ムラサキ", 0), test_word("紫", "パープル", 1)];
+
+        let diff = diff_import(&current, imported, ImportMode::Replace);
+
+        assert_eq!(surfaces(&diff.removed), ["赤"]);
+        assert_eq!(diff.conflicting, ["紫"]);
+        assert!(diff.added.is_empty() && diff.changed.is_empty());
+        assert!(!diff.is_empty());
+    }
+
+    #[tokio::test]
+    async fn apply_import_writes_diff_and_records_history() {
+        let pool = crate::database::memory_pool().await;
+        let scope = Scope::Guild(GuildId::new(1));
+        let actor = UserId::new(2);
+        insert(&pool, scope, actor, &test_word("緑", "ミドリ", 0)).await.unwrap();
+        insert(&pool, scope, actor, &test_word("赤", "アカ", 1)).await.unwrap();
+
+        let diff = apply_import(&pool, scope, actor, vec![test_word("緑", "ミドリイロ", 3), test_word("黄", "キ", 1)], ImportMode::Replace).await.unwrap();
+
+        assert_eq!((diff.added.len(), diff.changed.len(), diff.removed.len()), (1, 1, 1));
+        assert_eq!(fetch_all(&pool, scope).await.unwrap(), [test_word("緑", "ミドリイロ", 3), test_word("黄", "キ", 1)]);
+        // 他のサーバーと全体の辞書には影響しない
+        assert!(fetch_all(&pool, Scope::Global).await.unwrap().is_empty());
+        let (changes, total) = history::changes(&pool, scope, 10, 0).await.unwrap();
+        assert_eq!(total, 3);
+        assert_eq!(changes[0].action, ChangeAction::Import);
+        assert_eq!(changes[0].entry_count, 3);
+    }
+
+    #[tokio::test]
+    async fn apply_import_diffs_against_words_changed_after_preview() {
+        let pool = crate::database::memory_pool().await;
+        let scope = Scope::Guild(GuildId::new(1));
+        let actor = UserId::new(2);
+        insert(&pool, scope, actor, &test_word("緑", "ミドリ", 0)).await.unwrap();
+        let imported = vec![test_word("緑", "ミドリ", 0)];
+        let preview = diff_import(&fetch_all(&pool, scope).await.unwrap(), imported.clone(), ImportMode::Replace);
+        assert!(preview.is_empty());
+
+        // 確認を待つ間に追加された単語も、置き換えモードでは削除する
+        insert(&pool, scope, actor, &test_word("青", "アオ", 1)).await.unwrap();
+        let diff = apply_import(&pool, scope, actor, imported, ImportMode::Replace).await.unwrap();
+
+        assert_eq!(diff.removed, [test_word("青", "アオ", 1)]);
+        assert_eq!(fetch_all(&pool, scope).await.unwrap(), [test_word("緑", "ミドリ", 0)]);
+    }
+
+    #[tokio::test]
+    async fn seed_runs_only_once() {
+        let pool = crate::database::memory_pool().await;
+        insert(&pool, Scope::Global, UserId::new(2), &test_word("緑", "ミドリ", 0)).await.unwrap();
+        // エンジンに問い合わせたら失敗するよう、接続を受け付けないURLにする
+        let voicevox_client = client_tests::client_for(&[client_tests::unreachable_url().await]);
+
+        // 取り込み済みの辞書があれば、記録だけ残す
+        seed_if_empty(&pool, &voicevox_client).await.unwrap();
+        assert!(database::migration_applied(&pool, SEED_MIGRATION).await.unwrap());
+
+        // リセットして空になっても、エンジンや`user_dict.json`から取り込み直さない
+        sqlx::query("DELETE FROM dictionary_entry").execute(&pool).await.unwrap();
+        seed_if_empty(&pool, &voicevox_client).await.unwrap();
+        assert!(fetch_all(&pool, Scope::Global).await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn replace_prefers_longer_words_and_reports_replaced_words() {
+        let dictionary = GuildDictionary::new(vec![test_word("緑", "ミドリ", 0), test_word("緑色", "ミドリイロ", 3), test_word("未使用", "ミシヨウ", 0)]);
+
+        let (text, replaced) = dictionary.replace("緑色と緑色と緑");
+
+        assert_eq!(text, "ミドリイロとミドリイロとミドリ");
+        assert_eq!(replaced.iter().map(|word| word.surface.as_str()).collect::<Vec<_>>(), ["緑色", "緑"]);
+    }
+
+    #[test]
+    fn set_word_accents_updates_phrases_starting_with_the_reading() {
+        let mut phrases = vec![phrase(&["ミ", "ド", "リ", "ン", "ガ"], 1), phrase(&["キ", "タ"], 1), phrase(&["ズ", "ン", "ダ"], 1)];
+        let words = [test_word("みどりん", "ミドリン", 0), test_word("ずんだ", "ズンダ", 2)];
+
+        assert!(set_word_accents(&mut phrases, &words));
+
+        // 平板型は助詞を含めた最後のモーラになる
+        assert_eq!(phrases[0]["accent"], 5);
+        assert_eq!(phrases[1]["accent"], 1);
+        assert_eq!(phrases[2]["accent"], 2);
+        assert!(!set_word_accents(&mut phrases, &words));
+    }
+}
@@ -1,16 +1,19 @@
+use crate::database;
 use serenity::prelude::Context;
 use serenity::all::{Message, GuildId, User};
 use regex::Regex;
 use once_cell::sync::Lazy;
+use sqlx::SqlitePool;
+use tracing::warn;
 
 static RE_EMOJI: Lazy<Regex> = Lazy::new(|| Regex::new(r"<a?:\w+:\d+>").unwrap());
 static RE_URL: Lazy<Regex> = Lazy::new(|| Regex::new(r"https?://[\w!?/+\-_~;.,*&@#$%()='\]]+").unwrap());
 
-pub async fn format_voicevox_message(ctx: &Context, msg: &Message) -> String {
+pub async fn format_voicevox_message(ctx: &Context, pool: &SqlitePool, msg: &Message) -> String {
     let mut text = msg.content.clone();
 
     if let Some(guild_id) = msg.guild_id {
-        text = replace_user_mentions(ctx, guild_id, &msg.mentions, &text).await;
+        text = replace_user_mentions(ctx, pool, guild_id, &msg.mentions, &text).await;
     }
 
     text = RE_EMOJI.replace_all(&text, "").to_string();
@@ -29,6 +32,7 @@ pub async fn format_voicevox_message(ctx: &Context, msg: &Message) -> String {
 
 async fn replace_user_mentions(
     ctx: &Context,
+    pool: &SqlitePool,
     guild_id: GuildId,
     mentions: &[User],
     text: &str,
@@ -36,9 +40,18 @@ async fn replace_user_mentions(
     let mut result_text = text.to_string();
 
     for user in mentions {
-        let display_name = match guild_id.member(&ctx.http, user.id).await {
-            Ok(member) => member.nick.unwrap_or_else(|| user.name.clone()),
-            Err(_) => user.name.clone(),
+        // 読み上げを停止しているユーザーの名前は読まない
+        let opted_out = database::is_opted_out(pool, user.id).await.unwrap_or_else(|e| {
+            warn!("Failed to fetch opt-out state: {}", e);
+            true
+        });
+        let display_name = if opted_out {
+            "ユーザー".to_string()
+        } else {
+            match guild_id.member(&ctx.http, user.id).await {
+                Ok(member) => member.nick.unwrap_or_else(|| user.name.clone()),
+                Err(_) => user.name.clone(),
+            }
         };
 
         let pattern = format!(r"<@!?{}>", user.id);
@@ -49,4 +62,18 @@ async fn replace_user_mentions(
     }
 
     result_text
-}
\ No newline at end of file
+}
+
+/// 発言者の名前の読みを返す。登録された読みがあればそれを優先する
+pub async fn author_name(pool: &SqlitePool, guild_id: GuildId, msg: &Message) -> String {
+    match database::fetch_name_reading(pool, guild_id, msg.author.id).await {
+        Ok(Some(reading)) => return reading,
+        Ok(None) => {}
+        Err(e) => warn!("Failed to fetch name reading: {}", e),
+    }
+
+    msg.member
+        .as_ref()
+        .and_then(|member| member.nick.clone())
+        .unwrap_or_else(|| msg.author.display_name().to_string())
+}
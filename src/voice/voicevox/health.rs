@@ -0,0 +1,62 @@
+use reqwest::Client as HttpClient;
+use serde_json::Value;
+use std::time::{Duration, Instant};
+use tracing::debug;
+use url::Url;
+
+/// 応答時間の移動平均で、新しい値をどれだけ重く見るか
+const LATENCY_SMOOTHING: f64 = 0.2;
+
+/// ヘルスチェックで分かったエンジンの状態。応答しなかった場合もバージョンなどは最後に分かったものを残す
+#[derive(Debug, Clone, Default)]
+pub struct EngineStatus {
+    pub reachable: bool,
+    pub version: Option<String>,
+    /// `/engine_manifest`のエンジン名
+    pub engine_name: Option<String>,
+    pub core_versions: Vec<String>,
+    /// 直近の`/version`の応答時間
+    pub latency: Option<Duration>,
+    pub average_latency: Option<Duration>,
+    pub checked_at: Option<Instant>,
+}
+
+/// `/version`で応答時間を測り、バージョンが変わったときだけ`/engine_manifest`と`/core_versions`を取り直す
+pub async fn probe(http: &HttpClient, url: &Url, previous: &EngineStatus) -> EngineStatus {
+    let mut status = EngineStatus { reachable: false, latency: None, checked_at: Some(Instant::now()), ..previous.clone() };
+
+    let started = Instant::now();
+    let Some(version) = fetch_json(http, url, "/version").await.and_then(|version| version.as_str().map(str::to_string)) else {
+        return status;
+    };
+    let latency = started.elapsed();
+
+    status.reachable = true;
+    status.latency = Some(latency);
+    status.average_latency = Some(match previous.average_latency {
+        Some(average) => average.mul_f64(1.0 - LATENCY_SMOOTHING) + latency.mul_f64(LATENCY_SMOOTHING),
+        None => latency,
+    });
+
+    if status.version.as_deref() != Some(version.as_str()) || status.engine_name.is_none() {
+        status.engine_name = fetch_json(http, url, "/engine_manifest")
+            .await
+            .and_then(|manifest| manifest["name"].as_str().map(str::to_string));
+        status.core_versions = fetch_json(http, url, "/core_versions")
+            .await
+            .and_then(|versions| serde_json::from_value(versions).ok())
+            .unwrap_or_default();
+    }
+    status.version = Some(version);
+    status
+}
+
+async fn fetch_json(http: &HttpClient, url: &Url, path: &str) -> Option<Value> {
+    let result = async {
+        let res = http.get(url.join(path)?).send().await?.error_for_status()?;
+        anyhow::Ok(res.json::<Value>().await?)
+    }
+    .await;
+
+    result.inspect_err(|e| debug!(path, "Health probe failed: {}", e)).ok()
+}
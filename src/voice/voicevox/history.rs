@@ -0,0 +1,415 @@
+use crate::voice::voicevox::dictionary::{self, DictionaryWord, ImportDiff, ImportMode, Scope};
+use anyhow::{Context, Result};
+use serenity::model::id::UserId;
+use sqlx::{SqliteConnection, SqlitePool};
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::info;
+
+/// サーバーごとに残すスナップショットの数。古いものから削除する
+const MAX_SNAPSHOTS: i64 = 20;
+
+/// 辞書に対する操作の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeAction {
+    Add,
+    Edit,
+    Remove,
+    Reset,
+    Import,
+    Undo,
+    Restore,
+}
+
+impl ChangeAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeAction::Add => "add",
+            ChangeAction::Edit => "edit",
+            ChangeAction::Remove => "remove",
+            ChangeAction::Reset => "reset",
+            ChangeAction::Import => "import",
+            ChangeAction::Undo => "undo",
+            ChangeAction::Restore => "restore",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ChangeAction::Add => "追加",
+            ChangeAction::Edit => "編集",
+            ChangeAction::Remove => "削除",
+            ChangeAction::Reset => "リセット",
+            ChangeAction::Import => "インポート",
+            ChangeAction::Undo => "取り消し",
+            ChangeAction::Restore => "復元",
+        }
+    }
+}
+
+impl TryFrom<String> for ChangeAction {
+    type Error = String;
+
+    fn try_from(value: String) -> std::result::Result<Self, Self::Error> {
+        [ChangeAction::Add, ChangeAction::Edit, ChangeAction::Remove, ChangeAction::Reset, ChangeAction::Import, ChangeAction::Undo, ChangeAction::Restore]
+            .into_iter()
+            .find(|action| action.as_str() == value)
+            .ok_or_else(|| format!("Unknown dictionary change action: {}", value))
+    }
+}
+
+/// 1つの単語の変更前と変更後。`None`は単語が無い状態
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangeEntry {
+    pub surface: String,
+    pub before: Option<DictionaryWord>,
+    pub after: Option<DictionaryWord>,
+}
+
+impl ChangeEntry {
+    pub fn new(before: Option<DictionaryWord>, after: Option<DictionaryWord>) -> Self {
+        let surface = before.as_ref().or(after.as_ref()).map(|word| word.surface.clone()).unwrap_or_default();
+        Self { surface, before, after }
+    }
+
+    pub fn from_diff(diff: &ImportDiff) -> Vec<Self> {
+        diff.added
+            .iter()
+            .map(|word| Self::new(None, Some(word.clone())))
+            .chain(diff.changed.iter().map(|(old, new)| Self::new(Some(old.clone()), Some(new.clone()))))
+            .chain(diff.removed.iter().map(|word| Self::new(Some(word.clone()), None)))
+            .collect()
+    }
+}
+
+/// 履歴の1件。1回の操作で変更したすべての単語をまとめる
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct Change {
+    pub id: i64,
+    pub user_id: i64,
+    #[sqlx(try_from = "String")]
+    pub action: ChangeAction,
+    /// UNIX時間(秒)
+    pub created_at: i64,
+    /// `/dictionary undo`で取り消されたか
+    pub undone: bool,
+    pub entry_count: i64,
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct Snapshot {
+    pub id: i64,
+    pub label: String,
+    pub word_count: i64,
+}
+
+#[derive(Debug, Default)]
+pub struct UndoReport {
+    /// 取り消した操作の数
+    pub changes: usize,
+    /// 元に戻した単語の変更
+    pub entries: Vec<ChangeEntry>,
+}
+
+fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_secs() as i64).unwrap_or_default()
+}
+
+fn to_json(word: &Option<DictionaryWord>) -> Result<Option<String>> {
+    word.as_ref().map(serde_json::to_string).transpose().context("Failed to serialize dictionary word")
+}
+
+fn from_json(json: Option<String>) -> Result<Option<DictionaryWord>> {
+    json.map(|json| serde_json::from_str(&json)).transpose().context("Failed to deserialize dictionary word")
+}
+
+/// 変更を履歴に記録する。変更した単語が無ければ何もしない
+pub(super) async fn record(conn: &mut SqliteConnection, scope: Scope, actor: UserId, action: ChangeAction, entries: &[ChangeEntry]) -> Result<()> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+
+    let change_id = sqlx::query("INSERT INTO dictionary_change (guild_id, user_id, action, created_at) VALUES (?, ?, ?, ?)")
+        .bind(scope.guild_id())
+        .bind(actor.get() as i64)
+        .bind(action.as_str())
+        .bind(now())
+        .execute(&mut *conn)
+        .await
+        .context("Failed to record dictionary change")?
+        .last_insert_rowid();
+
+    for entry in entries {
+        sqlx::query("INSERT INTO dictionary_change_entry (change_id, surface, before_word, after_word) VALUES (?, ?, ?, ?)")
+            .bind(change_id)
+            .bind(&entry.surface)
+            .bind(to_json(&entry.before)?)
+            .bind(to_json(&entry.after)?)
+            .execute(&mut *conn)
+            .await
+            .context("Failed to record dictionary change")?;
+    }
+
+    Ok(())
+}
+
+/// 新しい順に履歴を返す。2つ目の値は履歴の総数
+pub async fn changes(pool: &SqlitePool, scope: Scope, limit: i64, offset: i64) -> Result<(Vec<Change>, i64)> {
+    let changes = sqlx::query_as::<_, Change>(
+        "SELECT c.id, c.user_id, c.action, c.created_at, c.undone, COUNT(e.change_id) AS entry_count \
+         FROM dictionary_change c LEFT JOIN dictionary_change_entry e ON e.change_id = c.id \
+         WHERE c.guild_id = ? GROUP BY c.id ORDER BY c.id DESC LIMIT ? OFFSET ?",
+    )
+    .bind(scope.guild_id())
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch dictionary history")?;
+
+    let total = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM dictionary_change WHERE guild_id = ?")
+        .bind(scope.guild_id())
+        .fetch_one(pool)
+        .await
+        .context("Failed to count dictionary history")?;
+
+    Ok((changes, total))
+}
+
+pub async fn entries(pool: &SqlitePool, change_id: i64, limit: i64) -> Result<Vec<ChangeEntry>> {
+    let mut conn = pool.acquire().await.context("Failed to acquire connection")?;
+    fetch_entries(&mut conn, change_id, limit).await
+}
+
+async fn fetch_entries(conn: &mut SqliteConnection, change_id: i64, limit: i64) -> Result<Vec<ChangeEntry>> {
+    let rows = sqlx::query_as::<_, (String, Option<String>, Option<String>)>(
+        "SELECT surface, before_word, after_word FROM dictionary_change_entry WHERE change_id = ? ORDER BY rowid LIMIT ?",
+    )
+    .bind(change_id)
+    .bind(limit)
+    .fetch_all(conn)
+    .await
+    .context("Failed to fetch dictionary change entries")?;
+
+    rows.into_iter()
+        .map(|(surface, before, after)| Ok(ChangeEntry { surface, before: from_json(before)?, after: from_json(after)? }))
+        .collect()
+}
+
+/// 取り消されていない直近の操作を新しい順に`count`件取り消す
+///
+/// 取り消し自体も履歴に残るが、取り消しの操作はさらに取り消す対象にはならない
+pub async fn undo(pool: &SqlitePool, scope: Scope, actor: UserId, count: i64) -> Result<UndoReport> {
+    let mut tx = pool.begin().await.context("Failed to begin transaction")?;
+
+    let change_ids = sqlx::query_scalar::<_, i64>("SELECT id FROM dictionary_change WHERE guild_id = ? AND undone = 0 AND action != ? ORDER BY id DESC LIMIT ?")
+        .bind(scope.guild_id())
+        .bind(ChangeAction::Undo.as_str())
+        .bind(count)
+        .fetch_all(&mut *tx)
+        .await
+        .context("Failed to fetch dictionary history")?;
+
+    // 単語ごとに取り消す前の状態を覚えておき、最後に取り消し全体を1件の履歴にする
+    let mut original: HashMap<String, Option<DictionaryWord>> = HashMap::new();
+    let mut surfaces = Vec::new();
+    for change_id in &change_ids {
+        for entry in fetch_entries(&mut tx, *change_id, i64::MAX).await? {
+            if !original.contains_key(&entry.surface) {
+                let current = dictionary::fetch_word(&mut tx, scope, &entry.surface).await?;
+                original.insert(entry.surface.clone(), current);
+                surfaces.push(entry.surface.clone());
+            }
+            match &entry.before {
+                Some(word) => dictionary::put_word(&mut tx, scope, word).await?,
+                None => dictionary::remove_word(&mut tx, scope, &entry.surface).await?,
+            }
+        }
+
+        sqlx::query("UPDATE dictionary_change SET undone = 1 WHERE id = ?")
+            .bind(change_id)
+            .execute(&mut *tx)
+            .await
+            .context("Failed to mark dictionary change as undone")?;
+    }
+
+    let mut entries = Vec::new();
+    for surface in surfaces {
+        let before = original.remove(&surface).flatten();
+        let after = dictionary::fetch_word(&mut tx, scope, &surface).await?;
+        if before != after {
+            entries.push(ChangeEntry { surface, before, after });
+        }
+    }
+    record(&mut tx, scope, actor, ChangeAction::Undo, &entries).await?;
+
+    tx.commit().await.context("Failed to commit transaction")?;
+    info!(changes = change_ids.len(), words = entries.len(), "Undid dictionary changes");
+    Ok(UndoReport { changes: change_ids.len(), entries })
+}
+
+/// 現在の辞書をスナップショットとして保存し、そのIDを返す
+pub(super) async fn create_snapshot(conn: &mut SqliteConnection, scope: Scope, actor: UserId, label: &str) -> Result<i64> {
+    let words = dictionary::fetch_words(&mut *conn, scope).await?;
+    let json = serde_json::to_string(&words).context("Failed to serialize dictionary snapshot")?;
+
+    let snapshot_id = sqlx::query("INSERT INTO dictionary_snapshot (guild_id, user_id, label, created_at, word_count, words) VALUES (?, ?, ?, ?, ?, ?)")
+        .bind(scope.guild_id())
+        .bind(actor.get() as i64)
+        .bind(label)
+        .bind(now())
+        .bind(words.len() as i64)
+        .bind(json)
+        .execute(&mut *conn)
+        .await
+        .context("Failed to create dictionary snapshot")?
+        .last_insert_rowid();
+
+    sqlx::query("DELETE FROM dictionary_snapshot WHERE guild_id = ? AND id NOT IN (SELECT id FROM dictionary_snapshot WHERE guild_id = ? ORDER BY id DESC LIMIT ?)")
+        .bind(scope.guild_id())
+        .bind(scope.guild_id())
+        .bind(MAX_SNAPSHOTS)
+        .execute(&mut *conn)
+        .await
+        .context("Failed to prune dictionary snapshots")?;
+
+    Ok(snapshot_id)
+}
+
+pub async fn snapshot(pool: &SqlitePool, scope: Scope, actor: UserId, label: &str) -> Result<i64> {
+    let mut tx = pool.begin().await.context("Failed to begin transaction")?;
+    let snapshot_id = create_snapshot(&mut tx, scope, actor, label).await?;
+    tx.commit().await.context("Failed to commit transaction")?;
+    Ok(snapshot_id)
+}
+
+/// 新しい順にスナップショットを返す
+pub async fn snapshots(pool: &SqlitePool, scope: Scope, limit: i64) -> Result<Vec<Snapshot>> {
+    sqlx::query_as::<_, Snapshot>("SELECT id, label, word_count FROM dictionary_snapshot WHERE guild_id = ? ORDER BY id DESC LIMIT ?")
+        .bind(scope.guild_id())
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+        .context("Failed to fetch dictionary snapshots")
+}
+
+/// 現在の辞書からスナップショットの時点に戻すための差分。スナップショットが無ければ`None`を返す
+pub async fn snapshot_diff(pool: &SqlitePool, scope: Scope, snapshot_id: i64) -> Result<Option<ImportDiff>> {
+    let mut conn = pool.acquire().await.context("Failed to acquire connection")?;
+    diff_snapshot(&mut conn, scope, snapshot_id).await
+}
+
+async fn diff_snapshot(conn: &mut SqliteConnection, scope: Scope, snapshot_id: i64) -> Result<Option<ImportDiff>> {
+    let json = sqlx::query_scalar::<_, String>("SELECT words FROM dictionary_snapshot WHERE guild_id = ? AND id = ?")
+        .bind(scope.guild_id())
+        .bind(snapshot_id)
+        .fetch_optional(&mut *conn)
+        .await
+        .context("Failed to fetch dictionary snapshot")?;
+    let Some(json) = json else {
+        return Ok(None);
+    };
+    let words: Vec<DictionaryWord> = serde_json::from_str(&json).context("Failed to deserialize dictionary snapshot")?;
+
+    let current = dictionary::fetch_words(conn, scope).await?;
+    Ok(Some(dictionary::diff_import(&current, words, ImportMode::Replace)))
+}
+
+/// 辞書をスナップショットの時点の内容に戻す。スナップショットが無ければ`None`を返す
+///
+/// 復元前の辞書も新しいスナップショットとして残すので、復元自体もやり直せる
+pub async fn restore_snapshot(pool: &SqlitePool, scope: Scope, actor: UserId, snapshot_id: i64) -> Result<Option<ImportDiff>> {
+    let mut tx = pool.begin().await.context("Failed to begin transaction")?;
+
+    let Some(diff) = diff_snapshot(&mut tx, scope, snapshot_id).await? else {
+        return Ok(None);
+    };
+    if !diff.is_empty() {
+        create_snapshot(&mut tx, scope, actor, "復元前").await?;
+        dictionary::apply_diff(&mut tx, scope, &diff).await?;
+        record(&mut tx, scope, actor, ChangeAction::Restore, &ChangeEntry::from_diff(&diff)).await?;
+    }
+
+    tx.commit().await.context("Failed to commit transaction")?;
+    info!(snapshot_id, "Restored dictionary snapshot");
+    Ok(Some(diff))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voice::voicevox::dictionary::test_word;
+    use serenity::model::id::GuildId;
+
+    const ACTOR: UserId = UserId::new(2);
+
+    /// `緑`と`赤`が登録された辞書
+    async fn setup() -> (SqlitePool, Scope) {
+        let pool = crate::database::memory_pool().await;
+        let scope = Scope::Guild(GuildId::new(1));
+        dictionary::insert(&pool, scope, ACTOR, &test_word("緑", "ミドリ", 0)).await.unwrap();
+        dictionary::insert(&pool, scope, ACTOR, &test_word("赤", "アカ", 1)).await.unwrap();
+        (pool, scope)
+    }
+
+    async fn import_replace(pool: &SqlitePool, scope: Scope, words: Vec<DictionaryWord>) {
+        dictionary::apply_import(pool, scope, ACTOR, words, ImportMode::Replace).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn undo_reverts_replace_import() {
+        let (pool, scope) = setup().await;
+        let before = dictionary::fetch_all(&pool, scope).await.unwrap();
+        import_replace(&pool, scope, vec![test_word("緑", "ミドリイロ", 3), test_word("黄", "キ", 1)]).await;
+
+        let report = undo(&pool, scope, ACTOR, 1).await.unwrap();
+
+        assert_eq!(report.changes, 1);
+        assert_eq!(report.entries.len(), 3);
+        assert_eq!(dictionary::fetch_all(&pool, scope).await.unwrap(), before);
+        let (changes, _) = changes(&pool, scope, 10, 0).await.unwrap();
+        assert_eq!((changes[0].action, changes[0].undone), (ChangeAction::Undo, false));
+        assert_eq!((changes[1].action, changes[1].undone), (ChangeAction::Import, true));
+    }
+
+    #[tokio::test]
+    async fn undo_skips_previous_undo_and_reverts_older_changes() {
+        let (pool, scope) = setup().await;
+        dictionary::delete(&pool, scope, ACTOR, "赤").await.unwrap();
+
+        undo(&pool, scope, ACTOR, 1).await.unwrap();
+        assert_eq!(dictionary::fetch_all(&pool, scope).await.unwrap(), [test_word("緑", "ミドリ", 0), test_word("赤", "アカ", 1)]);
+
+        // 2回目は取り消しそのものではなく、その前の`赤`の追加を取り消す
+        let report = undo(&pool, scope, ACTOR, 1).await.unwrap();
+        assert_eq!(report.changes, 1);
+        assert_eq!(dictionary::fetch_all(&pool, scope).await.unwrap(), [test_word("緑", "ミドリ", 0)]);
+
+        // 取り消す操作が無くなっても、取り消しの履歴は対象にならない
+        undo(&pool, scope, ACTOR, 10).await.unwrap();
+        let report = undo(&pool, scope, ACTOR, 10).await.unwrap();
+        assert_eq!(report.changes, 0);
+        assert!(report.entries.is_empty());
+        assert!(dictionary::fetch_all(&pool, scope).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn undo_after_snapshot_restore_returns_to_state_before_restore() {
+        let (pool, scope) = setup().await;
+        let snapshot_id = snapshot(&pool, scope, ACTOR, "テスト").await.unwrap();
+        import_replace(&pool, scope, vec![test_word("黄", "キ", 1)]).await;
+        dictionary::insert(&pool, scope, ACTOR, &test_word("青", "アオ", 1)).await.unwrap();
+        let before_restore = dictionary::fetch_all(&pool, scope).await.unwrap();
+
+        let diff = restore_snapshot(&pool, scope, ACTOR, snapshot_id).await.unwrap().unwrap();
+        assert_eq!(diff.added.len() + diff.removed.len(), 4);
+        assert_eq!(dictionary::fetch_all(&pool, scope).await.unwrap(), [test_word("緑", "ミドリ", 0), test_word("赤", "アカ", 1)]);
+
+        let report = undo(&pool, scope, ACTOR, 1).await.unwrap();
+        assert_eq!(report.changes, 1);
+        assert_eq!(dictionary::fetch_all(&pool, scope).await.unwrap(), before_restore);
+        // 復元前の辞書もスナップショットに残っている
+        assert_eq!(snapshots(&pool, scope, 10).await.unwrap().iter().map(|snapshot| snapshot.label.as_str()).collect::<Vec<_>>(), ["復元前", "インポート前", "テスト"]);
+        assert!(restore_snapshot(&pool, scope, ACTOR, snapshot_id + 100).await.unwrap().is_none());
+    }
+}
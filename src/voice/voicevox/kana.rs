@@ -0,0 +1,149 @@
+use thiserror::Error;
+
+/// 半角カタカナ(`ｦ`〜`ﾝ`)に対応する全角カタカナ
+const HALF_WIDTH_KANA: &str = "ヲァィゥェォャュョッーアイウエオカキクケコサシスセソタチツテトナニヌネノハヒフヘホマミムメモヤユヨラリルレロワン";
+
+/// 辞書に登録する読みとアクセントの検証エラー。そのままユーザーに表示する
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum KanaError {
+    #[error("読みが空です")]
+    Empty,
+    #[error("読みに使えない文字「{0}」が含まれています。ひらがなかカタカナで入力してください")]
+    InvalidChar(char),
+    #[error("アクセントの位置({accent_type})は0から読みのモーラ数({morae})までの数字にしてください")]
+    AccentOutOfRange { accent_type: i64, morae: usize },
+}
+
+/// ひらがなと半角カタカナを全角カタカナに変換する。空白は取り除く
+pub fn to_katakana(text: &str) -> String {
+    let mut katakana = String::with_capacity(text.len());
+
+    for c in text.chars().filter(|c| !c.is_whitespace()) {
+        match c {
+            'ぁ'..='ゖ' | 'ゝ' | 'ゞ' => katakana.push(char::from_u32(c as u32 + 0x60).unwrap_or(c)),
+            'ｦ'..='ﾝ' => katakana.extend(HALF_WIDTH_KANA.chars().nth(c as usize - 'ｦ' as usize)),
+            // 半角の濁点・半濁点は直前の文字と合成する
+            'ﾞ' | 'ﾟ' => {
+                let handakuten = c == 'ﾟ';
+                match katakana.pop().map(|prev| (prev, combine_mark(prev, handakuten))) {
+                    Some((_, Some(combined))) => katakana.push(combined),
+                    Some((prev, None)) => katakana.extend([prev, if handakuten { '゜' } else { '゛' }]),
+                    None => katakana.push(if handakuten { '゜' } else { '゛' }),
+                }
+            }
+            _ => katakana.push(c),
+        }
+    }
+
+    katakana
+}
+
+fn combine_mark(prev: char, handakuten: bool) -> Option<char> {
+    match (prev, handakuten) {
+        ('ウ', false) => Some('ヴ'),
+        // 濁音はUnicodeで清音の次に並んでいる
+        ('カ' | 'キ' | 'ク' | 'ケ' | 'コ' | 'サ' | 'シ' | 'ス' | 'セ' | 'ソ' | 'タ' | 'チ' | 'ツ' | 'テ' | 'ト' | 'ハ' | 'ヒ' | 'フ' | 'ヘ' | 'ホ', false) => char::from_u32(prev as u32 + 1),
+        ('ハ' | 'ヒ' | 'フ' | 'ヘ' | 'ホ', true) => char::from_u32(prev as u32 + 2),
+        _ => None,
+    }
+}
+
+fn is_katakana(c: char) -> bool {
+    matches!(c, 'ァ'..='ヴ' | 'ー')
+}
+
+/// 読みを全角カタカナに揃え、VOICEVOXが受け付けない文字が無いか確認する
+pub fn normalize_pronunciation(text: &str) -> Result<String, KanaError> {
+    let pronunciation = to_katakana(text);
+    if pronunciation.is_empty() {
+        return Err(KanaError::Empty);
+    }
+    if let Some(c) = pronunciation.chars().find(|c| !is_katakana(*c)) {
+        return Err(KanaError::InvalidChar(c));
+    }
+    Ok(pronunciation)
+}
+
+fn is_small_kana(c: char) -> bool {
+    matches!(c, 'ァ' | 'ィ' | 'ゥ' | 'ェ' | 'ォ' | 'ャ' | 'ュ' | 'ョ' | 'ヮ')
+}
+
+/// 拗音などの小書きの文字は前の文字と合わせて1モーラとして数える
+pub fn count_morae(pronunciation: &str) -> usize {
+    pronunciation.chars().filter(|c| !is_small_kana(*c)).count()
+}
+
+/// 読みをモーラごとに分ける。小書きの文字は前のモーラに含める
+pub fn split_morae(pronunciation: &str) -> Vec<String> {
+    let mut morae: Vec<String> = Vec::new();
+    for c in pronunciation.chars() {
+        match morae.last_mut() {
+            Some(mora) if is_small_kana(c) => mora.push(c),
+            _ => morae.push(c.to_string()),
+        }
+    }
+    morae
+}
+
+/// アクセントの位置が0(平板型)から読みのモーラ数までに収まっているか確認する
+pub fn validate_accent(pronunciation: &str, accent_type: i64) -> Result<u8, KanaError> {
+    let morae = count_morae(pronunciation);
+    if accent_type < 0 || accent_type > morae as i64 {
+        return Err(KanaError::AccentOutOfRange { accent_type, morae });
+    }
+    u8::try_from(accent_type).map_err(|_| KanaError::AccentOutOfRange { accent_type, morae })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn to_katakana_converts_hiragana_and_half_width_kana() {
+        assert_eq!(to_katakana("ずんだ もん"), "ズンダモン");
+        assert_eq!(to_katakana("ゔぁいおりん"), "ヴァイオリン");
+        assert_eq!(to_katakana("ｶﾞｯｺｳ"), "ガッコウ");
+        assert_eq!(to_katakana("ﾊﾟﾝﾀﾞ"), "パンダ");
+        assert_eq!(to_katakana("ｳﾞｨｰﾅｽ"), "ヴィーナス");
+        // 合成できない濁点は全角の記号として残す
+        assert_eq!(to_katakana("ｱﾞ"), "ア゛");
+        assert_eq!(to_katakana("ABC"), "ABC");
+    }
+
+    #[test]
+    fn normalize_pronunciation_rejects_empty_and_non_kana() {
+        assert_eq!(normalize_pronunciation("ｶﾞｰﾃﾞﾝ"), Ok("ガーデン".to_string()));
+        assert_eq!(normalize_pronunciation("  "), Err(KanaError::Empty));
+        assert_eq!(normalize_pronunciation("みどりa"), Err(KanaError::InvalidChar('a')));
+        assert_eq!(normalize_pronunciation("ｱﾞ"), Err(KanaError::InvalidChar('゛')));
+    }
+
+    #[test]
+    fn count_morae_joins_small_kana_but_counts_long_vowels_and_sokuon() {
+        assert_eq!(count_morae("キャット"), 3);
+        assert_eq!(count_morae("ショウ"), 2);
+        assert_eq!(count_morae("コーヒー"), 4);
+        assert_eq!(count_morae("ッ"), 1);
+        assert_eq!(count_morae("ァ"), 0);
+        assert_eq!(count_morae(""), 0);
+    }
+
+    #[test]
+    fn split_morae_keeps_small_kana_with_previous_mora() {
+        assert_eq!(split_morae("キャッチャー"), ["キャ", "ッ", "チャ", "ー"]);
+        assert_eq!(split_morae("ヴァイオリン"), ["ヴァ", "イ", "オ", "リ", "ン"]);
+        // 先頭の小書きの文字は単独で並べる
+        assert_eq!(split_morae("ァア"), ["ァ", "ア"]);
+        assert_eq!(split_morae(&to_katakana("ｶﾞｯﾊﾟ")), ["ガ", "ッ", "パ"]);
+    }
+
+    #[test]
+    fn validate_accent_accepts_zero_to_mora_count() {
+        assert_eq!(validate_accent("キャット", 0), Ok(0));
+        assert_eq!(validate_accent("キャット", 3), Ok(3));
+        assert_eq!(validate_accent("キャット", 4), Err(KanaError::AccentOutOfRange { accent_type: 4, morae: 3 }));
+        assert_eq!(validate_accent("キャット", -1), Err(KanaError::AccentOutOfRange { accent_type: -1, morae: 3 }));
+        assert_eq!(validate_accent("ァ", 1), Err(KanaError::AccentOutOfRange { accent_type: 1, morae: 0 }));
+        assert_eq!(validate_accent(&"ア".repeat(300), 256), Err(KanaError::AccentOutOfRange { accent_type: 256, morae: 300 }));
+    }
+}
@@ -1,2 +1,9 @@
+pub mod accent;
 pub mod client;
-pub mod format;
\ No newline at end of file
+pub mod dictionary;
+pub mod format;
+pub mod health;
+pub mod history;
+pub mod kana;
+pub mod preset;
+pub mod resilience;
@@ -0,0 +1,309 @@
+use crate::voice::voicevox::client::{Client as VoicevoxClient, Engine, Speaker};
+use crate::voice::voicevox::dictionary::SyncReport;
+use anyhow::{Context, Result};
+use serde_json::{Value, json};
+use serenity::model::id::{GuildId, UserId};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use tracing::{debug, info, instrument, warn};
+
+const COLUMNS: &str = "name, style_id, speed_scale, pitch_scale, intonation_scale, volume_scale, pre_phoneme_length, post_phoneme_length, pause_length_scale";
+
+/// サーバーごとに保存する読み上げのプリセット。エンジンのプリセットはこれに合わせて同期される
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct VoicePreset {
+    pub name: String,
+    pub style_id: i64,
+    pub speed_scale: f64,
+    pub pitch_scale: f64,
+    pub intonation_scale: f64,
+    pub volume_scale: f64,
+    /// 音声の前後の無音の長さ(秒)
+    pub pre_phoneme_length: f64,
+    pub post_phoneme_length: f64,
+    /// 句読点などの間の長さの倍率
+    pub pause_length_scale: f64,
+}
+
+impl VoicePreset {
+    /// 音声クエリの既定値と同じ値で作る
+    pub fn new(name: &str, style_id: i64) -> Self {
+        Self {
+            name: name.to_string(),
+            style_id,
+            speed_scale: 1.0,
+            pitch_scale: 0.0,
+            intonation_scale: 1.0,
+            volume_scale: 1.0,
+            pre_phoneme_length: 0.1,
+            post_phoneme_length: 0.1,
+            pause_length_scale: 1.0,
+        }
+    }
+
+    /// エンジン上のプリセット名。エンジンのプリセットは全サーバーで共有されるため、ギルドIDを付けて区別する
+    pub fn engine_name(guild_id: GuildId, name: &str) -> String {
+        format!("{}/{}", guild_id, name)
+    }
+
+    /// `/add_preset`と`/update_preset`に送る形にする。IDはエンジンごとに違うため、送るときに入れる
+    pub fn to_engine_json(&self, guild_id: GuildId, speaker_uuid: &str) -> Value {
+        json!({
+            "id": 0,
+            "name": Self::engine_name(guild_id, &self.name),
+            "speaker_uuid": speaker_uuid,
+            "style_id": self.style_id,
+            "speedScale": self.speed_scale,
+            "pitchScale": self.pitch_scale,
+            "intonationScale": self.intonation_scale,
+            "volumeScale": self.volume_scale,
+            "prePhonemeLength": self.pre_phoneme_length,
+            "postPhonemeLength": self.post_phoneme_length,
+            "pauseLengthScale": self.pause_length_scale,
+        })
+    }
+}
+
+/// BOTが作ったエンジンのプリセットかどうか。それ以外のプリセットは同期で削除しない
+fn is_bot_preset(engine_name: &str) -> bool {
+    engine_name.split_once('/').is_some_and(|(guild_id, _)| guild_id.parse::<u64>().is_ok())
+}
+
+pub async fn fetch(pool: &SqlitePool, guild_id: GuildId, name: &str) -> Result<Option<VoicePreset>> {
+    sqlx::query_as::<_, VoicePreset>(&format!("SELECT {} FROM voice_preset WHERE guild_id = ? AND name = ?", COLUMNS))
+        .bind(guild_id.get() as i64)
+        .bind(name)
+        .fetch_optional(pool)
+        .await
+        .context("Failed to fetch voice preset")
+}
+
+pub async fn fetch_all(pool: &SqlitePool, guild_id: GuildId) -> Result<Vec<VoicePreset>> {
+    sqlx::query_as::<_, VoicePreset>(&format!("SELECT {} FROM voice_preset WHERE guild_id = ? ORDER BY name", COLUMNS))
+        .bind(guild_id.get() as i64)
+        .fetch_all(pool)
+        .await
+        .context("Failed to fetch voice presets")
+}
+
+/// プリセットを追加する。既に同じ名前のプリセットがある場合は`false`を返す
+pub async fn insert(pool: &SqlitePool, guild_id: GuildId, preset: &VoicePreset) -> Result<bool> {
+    let result = sqlx::query(&format!("INSERT OR IGNORE INTO voice_preset (guild_id, {}) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)", COLUMNS))
+        .bind(guild_id.get() as i64)
+        .bind(&preset.name)
+        .bind(preset.style_id)
+        .bind(preset.speed_scale)
+        .bind(preset.pitch_scale)
+        .bind(preset.intonation_scale)
+        .bind(preset.volume_scale)
+        .bind(preset.pre_phoneme_length)
+        .bind(preset.post_phoneme_length)
+        .bind(preset.pause_length_scale)
+        .execute(pool)
+        .await
+        .context("Failed to insert voice preset")?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// プリセットを更新する。プリセットが無い場合は`false`を返す
+pub async fn update(pool: &SqlitePool, guild_id: GuildId, preset: &VoicePreset) -> Result<bool> {
+    let result = sqlx::query("UPDATE voice_preset SET style_id = ?, speed_scale = ?, pitch_scale = ?, intonation_scale = ?, volume_scale = ?, pre_phoneme_length = ?, post_phoneme_length = ?, pause_length_scale = ? WHERE guild_id = ? AND name = ?")
+        .bind(preset.style_id)
+        .bind(preset.speed_scale)
+        .bind(preset.pitch_scale)
+        .bind(preset.intonation_scale)
+        .bind(preset.volume_scale)
+        .bind(preset.pre_phoneme_length)
+        .bind(preset.post_phoneme_length)
+        .bind(preset.pause_length_scale)
+        .bind(guild_id.get() as i64)
+        .bind(&preset.name)
+        .execute(pool)
+        .await
+        .context("Failed to update voice preset")?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// プリセットを削除し、選んでいたユーザーは既定の声に戻す
+pub async fn delete(pool: &SqlitePool, guild_id: GuildId, name: &str) -> Result<bool> {
+    let mut tx = pool.begin().await.context("Failed to begin transaction")?;
+
+    let result = sqlx::query("DELETE FROM voice_preset WHERE guild_id = ? AND name = ?")
+        .bind(guild_id.get() as i64)
+        .bind(name)
+        .execute(&mut *tx)
+        .await
+        .context("Failed to delete voice preset")?;
+    sqlx::query("DELETE FROM user_preset WHERE guild_id = ? AND name = ?")
+        .bind(guild_id.get() as i64)
+        .bind(name)
+        .execute(&mut *tx)
+        .await
+        .context("Failed to clear preset selections")?;
+
+    tx.commit().await.context("Failed to commit transaction")?;
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn select(pool: &SqlitePool, guild_id: GuildId, user_id: UserId, name: &str) -> Result<()> {
+    sqlx::query("INSERT OR REPLACE INTO user_preset (guild_id, user_id, name) VALUES (?, ?, ?)")
+        .bind(guild_id.get() as i64)
+        .bind(user_id.get() as i64)
+        .bind(name)
+        .execute(pool)
+        .await
+        .context("Failed to select voice preset")?;
+
+    Ok(())
+}
+
+pub async fn clear_selection(pool: &SqlitePool, guild_id: GuildId, user_id: UserId) -> Result<bool> {
+    let result = sqlx::query("DELETE FROM user_preset WHERE guild_id = ? AND user_id = ?")
+        .bind(guild_id.get() as i64)
+        .bind(user_id.get() as i64)
+        .execute(pool)
+        .await
+        .context("Failed to clear voice preset selection")?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// ユーザーが選んでいるプリセット
+pub async fn selected(pool: &SqlitePool, guild_id: GuildId, user_id: UserId) -> Result<Option<VoicePreset>> {
+    sqlx::query_as::<_, VoicePreset>(
+        "SELECT p.name, p.style_id, p.speed_scale, p.pitch_scale, p.intonation_scale, p.volume_scale, p.pre_phoneme_length, p.post_phoneme_length, p.pause_length_scale \
+         FROM user_preset u JOIN voice_preset p ON p.guild_id = u.guild_id AND p.name = u.name WHERE u.guild_id = ? AND u.user_id = ?"
+    )
+        .bind(guild_id.get() as i64)
+        .bind(user_id.get() as i64)
+        .fetch_optional(pool)
+        .await
+        .context("Failed to fetch selected voice preset")
+}
+
+/// 誰かが選んでいるプリセットのスタイル。起動時にモデルを読み込んでおくのに使う
+pub async fn selected_style_ids(pool: &SqlitePool) -> Result<Vec<i64>> {
+    sqlx::query_scalar::<_, i64>("SELECT DISTINCT p.style_id FROM user_preset u JOIN voice_preset p ON p.guild_id = u.guild_id AND p.name = u.name")
+        .fetch_all(pool)
+        .await
+        .context("Failed to fetch selected preset styles")
+}
+
+/// スタイルIDから、エンジンのプリセットに必要な話者のUUIDを引く
+fn speaker_uuids(speakers: Vec<Speaker>) -> HashMap<i64, String> {
+    speakers
+        .into_iter()
+        .flat_map(|speaker| speaker.styles.into_iter().map(move |style| (style.id, speaker.speaker_uuid.clone())))
+        .collect()
+}
+
+/// データベースのプリセットをすべてのエンジンに反映する。BOTが作ったもので不要になったプリセットは削除する
+pub async fn sync_to_engine(pool: &SqlitePool, voicevox_client: &VoicevoxClient) -> Result<SyncReport> {
+    let mut report = SyncReport::default();
+    let presets = engine_presets(pool, voicevox_client.speakers().await?, &mut report).await?;
+
+    // プリセットIDはエンジンごとに違うため、エンジンごとに比べる
+    let mut reached = 0;
+    let mut last_error = None;
+    for engine in voicevox_client.engines() {
+        if let Err(e) = sync_engine(engine, &presets, &mut report).await {
+            warn!(url = %engine.url(), "Failed to synchronize presets to engine: {}", e);
+            engine.mark_needs_sync();
+            report.failed += presets.len();
+            last_error = Some(e);
+        } else {
+            reached += 1;
+        }
+    }
+    if reached == 0
+        && let Some(e) = last_error
+    {
+        return Err(e);
+    }
+
+    info!(added = report.added, updated = report.updated, removed = report.removed, failed = report.failed, "Synchronized presets to engine");
+    Ok(report)
+}
+
+/// データベースのプリセットを1台のエンジンに反映する。再起動したエンジンを同期し直すのに使う
+#[instrument(skip(pool, engine), fields(url = %engine.url()))]
+pub async fn resync_engine(pool: &SqlitePool, engine: &Engine) -> Result<SyncReport> {
+    let mut report = SyncReport::default();
+    let presets = engine_presets(pool, engine.get_speakers().await?, &mut report).await?;
+    sync_engine(engine, &presets, &mut report).await?;
+
+    info!(added = report.added, updated = report.updated, removed = report.removed, failed = report.failed, "Synchronized presets to engine");
+    Ok(report)
+}
+
+/// すべてのサーバーのプリセットをエンジンに送る形にする。話者が見つからないものは失敗した件数に含める
+async fn engine_presets(pool: &SqlitePool, speakers: Vec<Speaker>, report: &mut SyncReport) -> Result<Vec<Value>> {
+    let rows = sqlx::query_scalar::<_, i64>("SELECT DISTINCT guild_id FROM voice_preset")
+        .fetch_all(pool)
+        .await
+        .context("Failed to fetch preset guilds")?;
+    let speaker_uuids = speaker_uuids(speakers);
+
+    let mut presets = Vec::new();
+    for guild_id in rows {
+        let guild_id = GuildId::new(guild_id as u64);
+        for preset in fetch_all(pool, guild_id).await? {
+            match speaker_uuids.get(&preset.style_id) {
+                Some(speaker_uuid) => presets.push(preset.to_engine_json(guild_id, speaker_uuid)),
+                None => {
+                    warn!(guild_id = %guild_id, name = %preset.name, style_id = preset.style_id, "Preset style is not available in engine");
+                    report.failed += 1;
+                }
+            }
+        }
+    }
+    Ok(presets)
+}
+
+async fn sync_engine(engine: &Engine, presets: &[Value], report: &mut SyncReport) -> Result<()> {
+    let mut engine_presets = engine
+        .get_presets()
+        .await?
+        .into_iter()
+        .filter_map(|preset| Some((preset.get("name")?.as_str()?.to_string(), preset)))
+        .collect::<HashMap<_, _>>();
+
+    for preset in presets {
+        let name = preset["name"].as_str().unwrap_or_default();
+        let existing = engine_presets.remove(name);
+        if existing.as_ref().is_some_and(|existing| same_preset(existing, preset)) {
+            continue;
+        }
+
+        debug!(name, "Writing preset to engine");
+        match engine.put_preset(preset).await {
+            Ok(()) if existing.is_some() => report.updated += 1,
+            Ok(()) => report.added += 1,
+            Err(e) => {
+                warn!(name, "Failed to write preset to engine: {}", e);
+                report.failed += 1;
+            }
+        }
+    }
+
+    for name in engine_presets.into_keys().filter(|name| is_bot_preset(name)) {
+        debug!(name, "Removing preset from engine");
+        match engine.delete_preset(&name).await {
+            Ok(()) => report.removed += 1,
+            Err(e) => {
+                warn!(name, "Failed to remove preset from engine: {}", e);
+                report.failed += 1;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// IDはエンジンごとに違い、エンジンが付け足す項目もあるため、送る項目だけを比べる
+fn same_preset(existing: &Value, preset: &Value) -> bool {
+    preset
+        .as_object()
+        .is_some_and(|fields| fields.iter().filter(|(key, _)| *key != "id").all(|(key, value)| existing.get(key) == Some(value)))
+}
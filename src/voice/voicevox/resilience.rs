@@ -0,0 +1,98 @@
+use crate::config::Config;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tracing::{info, warn};
+
+/// サーキットブレーカーが開いている間に返すエラー
+#[derive(Debug, Error)]
+#[error("VOICEVOX engine is unavailable; requests are paused for {0:?}")]
+pub struct CircuitOpen(pub Duration);
+
+/// 止まっているエンジンには変更を送らず、復帰したときの同期で反映する。そのようなエンジンがあったことを返すエラー
+#[derive(Debug, Error)]
+#[error("{pending} of {total} VOICEVOX engines are unavailable; the change will be applied when they recover")]
+pub struct EnginesPending {
+    pub pending: usize,
+    pub total: usize,
+}
+
+/// 失敗したリクエストを再試行する間隔の決め方
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// 最初のリクエストを含めた試行回数
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            max_attempts: config.retry_max_attempts.max(1),
+            base_delay: Duration::from_millis(config.retry_base_delay_ms),
+        }
+    }
+
+    /// `attempt`回目の失敗の後に待つ時間。倍々に伸ばし、同時に再試行が集中しないよう後半をランダムにする
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let delay = self.base_delay.saturating_mul(1 << attempt.saturating_sub(1).min(10));
+        let half = delay / 2;
+        half + half.mul_f64(fastrand::f64())
+    }
+}
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    failures: u32,
+    open_until: Option<Instant>,
+}
+
+/// 続けて失敗したらしばらくリクエストを止めるサーキットブレーカー
+///
+/// 止めている時間が過ぎたら次のリクエストを試しに通し、成功すれば閉じ、失敗すればまた止める
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    open_duration: Duration,
+    state: Mutex<BreakerState>,
+}
+
+impl CircuitBreaker {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            failure_threshold: config.circuit_failure_threshold.max(1),
+            open_duration: Duration::from_secs(config.circuit_open_secs),
+            state: Mutex::default(),
+        }
+    }
+
+    /// リクエストを送ってよいか。止めている間は残り時間を返す
+    pub fn check(&self) -> Result<(), CircuitOpen> {
+        match self.state.lock().unwrap().open_until {
+            Some(until) if until > Instant::now() => Err(CircuitOpen(until - Instant::now())),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.open_until.take().is_some() {
+            info!("VOICEVOX engine recovered; resuming requests");
+        }
+        state.failures = 0;
+    }
+
+    pub fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.failures += 1;
+
+        // 試しに通したリクエストが失敗した場合は、続けて止める
+        let half_open = state.open_until.is_some();
+        if half_open || state.failures >= self.failure_threshold {
+            if !half_open {
+                warn!(failures = state.failures, open_secs = self.open_duration.as_secs(), "VOICEVOX engine keeps failing; pausing requests");
+            }
+            state.open_until = Some(Instant::now() + self.open_duration);
+        }
+    }
+}